@@ -3,15 +3,21 @@ use std::{env, io, path, process};
 fn main() -> io::Result<()> {
     println!("cargo:rerun-if-changed=src/test.zs");
     println!("cargo:rerun-if-changed=src/test.rs");
+    println!("cargo:rerun-if-changed=src/common.zs");
+    println!("cargo:rerun-if-changed=src/common.rs");
 
     let root = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
-    let path = path::Path::new(&root).join("src/test.zs");
 
-    // this executes the binary directly, assuming you installed it
-    process::Command::new("pst")
-        .arg("rust")
-        .arg(path.into_os_string().into_string().unwrap())
-        .status()?;
+    // test.zs includes common.zs, which gets its own module
+    for file in ["src/common.zs", "src/test.zs"] {
+        let path = path::Path::new(&root).join(file);
+
+        // this executes the binary directly, assuming you installed it
+        process::Command::new("pst")
+            .arg("rust")
+            .arg(path.into_os_string().into_string().unwrap())
+            .status()?;
+    }
 
     // use std::fs;
     // use io::Write;
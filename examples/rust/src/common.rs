@@ -0,0 +1,116 @@
+// AUTOGENERATED - MAY BE OVERWRITTEN
+
+#[allow(
+    unused_assignments,
+    unused_imports,
+    unused_variables,
+    unused_parens,
+    clippy::derivable_impls,
+    clippy::needless_range_loop,
+    clippy::needless_parens_on_range_literals,
+    clippy::unnecessary_cast,
+    clippy::identity_op,
+    clippy::nonminimal_bool,
+    clippy::double_parens
+)]
+pub mod pstruct {
+    use pstruct_rs::*;
+    use std::ffi::CString;
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[repr(u8)]
+    pub enum Unit {
+        #[default]
+        Meters = 1,
+        Feet = 2,
+    }
+    impl std::convert::TryFrom<u8> for Unit {
+        type Error = PError;
+        fn try_from(value: u8) -> Result<Self> {
+            match value {
+                1 => Ok(Self::Meters),
+                2 => Ok(Self::Feet),
+                _ => Err(PError::UnknownVariant),
+            }
+        }
+    }
+    impl Primitive for Unit {
+        fn encode_le(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as u8).encode_le(buf)
+        }
+        fn encode_be(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as u8).encode_be(buf)
+        }
+        fn decode_le(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: u8 = 0;
+            value.decode_le(data)?;
+            *self = <Self as std::convert::TryFrom<u8>>::try_from(value)?;
+            Ok(())
+        }
+        fn decode_be(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: u8 = 0;
+            value.decode_be(data)?;
+            *self = <Self as std::convert::TryFrom<u8>>::try_from(value)?;
+            Ok(())
+        }
+    }
+    #[derive(Debug)]
+    pub struct Vec3 {
+        pub x: f32,
+        pub y: f32,
+        pub z: f32,
+    }
+    impl Default for Vec3 {
+        fn default() -> Self {
+            Self {
+                x: Default::default(),
+                y: Default::default(),
+                z: Default::default(),
+            }
+        }
+    }
+    impl Pstruct for Vec3 {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            self.x.encode_le(&mut buf[..4])?;
+            buf = &mut buf[4..];
+            self.y.encode_le(&mut buf[..4])?;
+            buf = &mut buf[4..];
+            self.z.encode_le(&mut buf[..4])?;
+            buf = &mut buf[4..];
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let size = 4;
+            self.x
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 4;
+            self.y
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 4;
+            self.z
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            4 + 4 + 4
+        }
+    }
+}
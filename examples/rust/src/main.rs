@@ -2,8 +2,9 @@
 
 // pstruct!("test.zs");
 
+mod common;
 mod test;
-use pstruct_rs::Pstruct;
+use pstruct_rs::{PError, Pstruct};
 use test::*;
 
 fn main() {
@@ -26,6 +27,8 @@ fn main() {
             amazing: vec![0xCA, 0xFE, 0xF0, 0x0D],
         },
         o: [0x41; 16],
+        p: pstruct::Kind::Big,
+        q: vec![pstruct::Color::Red, pstruct::Color::Blue],
         u: ["A".to_string(), "BB".to_string(), "CCC".to_string()],
         v: [
             pstruct::Wow {
@@ -56,4 +59,310 @@ fn main() {
     println!("{:x?}", encoded);
     let decoded = pstruct::Test::decode_new(&encoded).unwrap();
     println!("{:#?}", decoded);
+    // constants are exported, m being sized by NAME_LEN
+    assert!(decoded.m.as_bytes().len() < pstruct::NAME_LEN);
+
+    let message = pstruct::Message {
+        version: 2,
+        urgent: 1,
+        count: 3,
+        extra: vec![7, 8, 9],
+        pairs: vec![-1, -2, -3, -4, -5],
+        label: CString::new("hi").unwrap(),
+        bonus: Some(0x55),
+        ext: Some(pstruct::Wow {
+            amazing: vec![0xee],
+        }),
+        code: Some(0x1234),
+        late: None,
+        kind: pstruct::Kind::Big,
+        body: pstruct::Payload::S(pstruct::S {
+            t: "tagged".to_string(),
+        }),
+    };
+    let encoded = message.encode().unwrap();
+    println!("{:x?}", encoded);
+    let decoded = pstruct::Message::decode_new(&encoded).unwrap();
+    println!("{:#?}", decoded);
+    let mut corrupted = encoded.clone();
+    corrupted[0] = 0;
+    assert!(pstruct::Message::decode_new(&corrupted).is_err());
+    assert_eq!(&encoded[4..6], &[0x28, 0x03]);
+    let overflowing = pstruct::Message {
+        version: 16,
+        ..Default::default()
+    };
+    assert!(overflowing.encode().is_err());
+    let mismatched = pstruct::Message {
+        version: 2,
+        count: 1,
+        extra: vec![7],
+        pairs: vec![-1, -2],
+        code: Some(0x1234),
+        ..Default::default()
+    };
+    assert!(matches!(mismatched.encode(), Err(PError::LengthMismatch)));
+    assert_eq!(decoded.late, None);
+    let missing = pstruct::Message {
+        version: 2,
+        urgent: 1,
+        ..Default::default()
+    };
+    assert!(missing.encode().is_err());
+    assert_eq!(decoded.pairs, message.pairs);
+    assert_eq!(decoded.bonus, Some(0x55));
+    let short = pstruct::Message {
+        count: 0,
+        ..Default::default()
+    };
+    assert!(short.encode().is_err());
+
+    let inventory = pstruct::Inventory {
+        owner: 7,
+        items: vec![
+            pstruct::Wow {
+                amazing: vec![1, 2],
+            },
+            pstruct::Wow { amazing: vec![] },
+        ],
+        name: CString::new("chest").unwrap(),
+    };
+    let encoded = inventory.encode().unwrap();
+    println!("{:x?}", encoded);
+    assert_eq!(&encoded[..4], &[2, 0, 6, 7]);
+    let decoded = pstruct::Inventory::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.items.len(), 2);
+    assert_eq!(decoded.name, inventory.name);
+
+    let catalog = pstruct::Catalog {
+        entries: vec![
+            pstruct::Wow {
+                amazing: vec![1, 2, 3],
+            },
+            pstruct::Wow { amazing: vec![4] },
+        ],
+        sizes: vec![-1, 2],
+    };
+    let encoded = catalog.encode().unwrap();
+    println!("{:x?}", encoded);
+    assert_eq!(&encoded[..2], &[12, 0]);
+    assert_eq!(&encoded[14..15], &[4]);
+    let decoded = pstruct::Catalog::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.entries.len(), 2);
+    assert_eq!(decoded.sizes, catalog.sizes);
+    let mut truncated = encoded.clone();
+    truncated[0] = 9;
+    assert!(pstruct::Catalog::decode_new(&truncated).is_err());
+    // an element claiming more bytes than the prefix allows fails instead of reading past it
+    let overrunning = [4, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    assert!(matches!(
+        pstruct::Catalog::decode_new(&overrunning),
+        Err(PError::NotEnoughData)
+    ));
+
+    let label = pstruct::Label {
+        short: "hey".to_string(),
+        code: "AB12".to_string(),
+        tags: ["x".to_string(), "yz".to_string()],
+        notes: vec!["note".to_string()],
+        long: CString::new("L".repeat(300)).unwrap(),
+    };
+    let encoded = label.encode().unwrap();
+    println!("{:x?}", encoded);
+    assert_eq!(&encoded[..4], b"\x03hey");
+    assert_eq!(&encoded[4..20], b"AB12    x   yz  ");
+    let decoded = pstruct::Label::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.code, label.code);
+    assert_eq!(decoded.tags, label.tags);
+    assert_eq!(decoded.notes, label.notes);
+    assert_eq!(decoded.long, label.long);
+    let mut invalid = encoded.clone();
+    invalid[4] = 0xff;
+    assert!(matches!(
+        pstruct::Label::decode_new(&invalid),
+        Err(PError::InvalidUtf8)
+    ));
+    invalid[4] = b'A';
+    invalid[1] = 0xc3;
+    assert!(matches!(
+        pstruct::Label::decode_new(&invalid),
+        Err(PError::InvalidUtf8)
+    ));
+    assert_eq!(
+        &encoded[encoded.len() - 303..encoded.len() - 300],
+        &[0x01, 0x2d, b'L']
+    );
+    let too_long = pstruct::Label {
+        code: "123456789".to_string(),
+        ..Default::default()
+    };
+    assert!(too_long.encode().is_err());
+
+    let envelope = pstruct::Envelope {
+        kind: 7,
+        payload: vec![1, 2, 3],
+    };
+    let encoded = envelope.encode().unwrap();
+    println!("{:x?}", encoded);
+    assert_eq!(encoded, vec![7, 1, 2, 3]);
+    let decoded = pstruct::Envelope::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.payload, envelope.payload);
+    let decoded = pstruct::Envelope::decode_new(&[7]).unwrap();
+    assert!(decoded.payload.is_empty());
+    assert!(pstruct::Envelope::decode_new(&[0; 66]).is_err());
+
+    let mut tiles = [[0; 4]; 4];
+    tiles[1][2] = 5;
+    let map = pstruct::TileMap {
+        rows: 2,
+        cols: 3,
+        tiles,
+        grid: vec![vec![1, 2, 3], vec![-4, -5, -6]],
+        names: [CString::new("a").unwrap(), CString::new("bc").unwrap()],
+        layers: vec![Default::default()],
+    };
+    let encoded = map.encode().unwrap();
+    println!("{:x?}", encoded);
+    assert_eq!(encoded[2 + 4 + 2], 5);
+    assert_eq!(&encoded[18..24], &[0, 1, 0, 2, 0, 3]);
+    assert_eq!(&encoded[30..36], &[2, b'a', 0, 3, b'b', b'c']);
+    assert_eq!(encoded[37], 1);
+    let decoded = pstruct::TileMap::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.tiles, map.tiles);
+    assert_eq!(decoded.grid, map.grid);
+    assert_eq!(decoded.names, map.names);
+    assert_eq!(decoded.layers.len(), 1);
+    let mut wrong = map;
+    // ragged rows and missing rows don't match rows and cols
+    let row = wrong.grid.pop().unwrap();
+    assert!(matches!(wrong.encode(), Err(PError::LengthMismatch)));
+    wrong.grid.push(row[..2].to_vec());
+    assert!(matches!(wrong.encode(), Err(PError::LengthMismatch)));
+    wrong.grid[1] = row;
+    wrong.layers = (0..5).map(|_| Default::default()).collect();
+    assert!(wrong.encode().is_err());
+
+    let flags = pstruct::Flags {
+        on: true,
+        history: [false, true, true],
+        checked: true,
+        answers: vec![true, false],
+    };
+    let encoded = flags.encode().unwrap();
+    assert_eq!(encoded, vec![1, 0, 1, 1, 1, 2, 1, 0]);
+    let decoded = pstruct::Flags::decode_new(&[7, 0, 2, 0, 1, 1, 1]).unwrap();
+    assert!(decoded.on);
+    assert_eq!(decoded.history, [false, true, false]);
+    assert!(decoded.checked);
+    assert_eq!(decoded.answers, vec![true]);
+    assert!(matches!(
+        pstruct::Flags::decode_new(&[1, 0, 0, 0, 2, 0]),
+        Err(PError::InvalidBool)
+    ));
+    assert!(matches!(
+        pstruct::Flags::decode_new(&[1, 0, 0, 0, 1, 1, 2]),
+        Err(PError::InvalidBool)
+    ));
+
+    let varints = pstruct::Varints {
+        id: 300,
+        delta: -1,
+        big: u64::MAX,
+        items: vec![9; 130],
+        name: "hi".to_string(),
+    };
+    let encoded = varints.encode().unwrap();
+    assert_eq!(encoded.len(), varints.size());
+    assert_eq!(&encoded[..3], &[0xac, 0x02, 0x01]);
+    assert_eq!(&encoded[13..15], &[0x82, 0x01]);
+    assert_eq!(&encoded[145..], &[2, b'h', b'i']);
+    let decoded = pstruct::Varints::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.id, varints.id);
+    assert_eq!(decoded.delta, varints.delta);
+    assert_eq!(decoded.big, varints.big);
+    assert_eq!(decoded.items, varints.items);
+    assert_eq!(decoded.name, varints.name);
+    assert!(matches!(
+        pstruct::Varints::decode_new(&[0xff, 0xff, 0xff, 0xff, 0x7f]),
+        Err(PError::OutOfRange)
+    ));
+
+    let sample = pstruct::Sample {
+        left: -2,
+        right: 0x123456,
+        stamp: 0xffff_ffff_ffff,
+        channel: pstruct::Channel::Stereo,
+        hash: 1 << 120 | 7,
+        delta: -1,
+        frames: vec![1, 2],
+    };
+    let encoded = sample.encode().unwrap();
+    assert_eq!(encoded.len(), 3 + 3 + 6 + 3 + 16 + 16 + 3 + 2);
+    assert_eq!(&encoded[..6], &[0xfe, 0xff, 0xff, 0x12, 0x34, 0x56]);
+    assert_eq!(&encoded[12..15], &[0, 0, 0x80]);
+    assert_eq!(encoded[15], 1);
+    assert_eq!(encoded[30], 7);
+    assert_eq!(&encoded[47..], &[2, 0, 0, 1, 2]);
+    let decoded = pstruct::Sample::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.left, sample.left);
+    assert_eq!(decoded.right, sample.right);
+    assert_eq!(decoded.stamp, sample.stamp);
+    assert_eq!(decoded.channel, sample.channel);
+    assert_eq!(decoded.hash, sample.hash);
+    assert_eq!(decoded.delta, sample.delta);
+    assert_eq!(decoded.frames, sample.frames);
+    let mut wrong = sample;
+    wrong.left = 1 << 23;
+    assert!(matches!(wrong.encode(), Err(PError::OutOfRange)));
+    wrong.left = 0;
+    wrong.stamp = 1 << 48;
+    assert!(matches!(wrong.encode(), Err(PError::OutOfRange)));
+
+    let position = pstruct::Position {
+        x: -1.5,
+        y: 2.25,
+        heading: 300.0,
+        track: [0.5, -0.25],
+    };
+    let encoded = position.encode().unwrap();
+    assert_eq!(
+        encoded,
+        vec![0x00, 0x80, 0xfe, 0xff, 0x00, 0x02, 0x40, 0x00, 0xff, 0xff, 0x08, 0xfc]
+    );
+    let decoded = pstruct::Position::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.x, -1.5);
+    assert_eq!(decoded.y, 2.25);
+    assert_eq!(decoded.heading, 255.0 + 255.0 / 256.0);
+    assert_eq!(decoded.track, [0.5, -0.25]);
+    let mut wrong = position;
+    wrong.track[0] = 8.0;
+    assert!(matches!(wrong.encode(), Err(PError::OutOfRange)));
+
+    let header = pstruct::Header {
+        kind: 0x0102,
+        tags: vec![7],
+        count: 0x0304,
+    };
+    let encoded = header.encode().unwrap();
+    assert_eq!(encoded, vec![0x01, 0x02, 0, 1, 7, 0x04, 0x03]);
+    let decoded = pstruct::Header::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.kind, header.kind);
+    assert_eq!(decoded.tags, header.tags);
+    assert_eq!(decoded.count, header.count);
+
+    let waypoint = pstruct::Waypoint {
+        position: common::pstruct::Vec3 {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        },
+        unit: common::pstruct::Unit::Feet,
+        path: [Default::default(), Default::default()],
+    };
+    let encoded = waypoint.encode().unwrap();
+    assert_eq!(encoded.len(), 12 + 1 + 24);
+    assert_eq!(encoded[12], 2);
+    let decoded = pstruct::Waypoint::decode_new(&encoded).unwrap();
+    assert_eq!(decoded.position.z, 3.0);
+    assert_eq!(decoded.unit, common::pstruct::Unit::Feet);
 }
@@ -1,9 +1,174 @@
 // AUTOGENERATED - MAY BE OVERWRITTEN
 
-#[allow(unused_assignments, unused_variables)]
+#[allow(
+    unused_assignments,
+    unused_imports,
+    unused_variables,
+    unused_parens,
+    clippy::derivable_impls,
+    clippy::needless_range_loop,
+    clippy::needless_parens_on_range_literals,
+    clippy::unnecessary_cast,
+    clippy::identity_op,
+    clippy::nonminimal_bool,
+    clippy::double_parens
+)]
 pub mod pstruct {
+    use super::super::common::pstruct::{Unit, Vec3};
     use pstruct_rs::*;
     use std::ffi::CString;
+    pub const NAME_LEN: usize = 40usize;
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[repr(u16)]
+    pub enum Kind {
+        #[default]
+        Small = 1,
+        Big = 2,
+    }
+    impl std::convert::TryFrom<u16> for Kind {
+        type Error = PError;
+        fn try_from(value: u16) -> Result<Self> {
+            match value {
+                1 => Ok(Self::Small),
+                2 => Ok(Self::Big),
+                _ => Err(PError::UnknownVariant),
+            }
+        }
+    }
+    impl Primitive for Kind {
+        fn encode_le(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as u16).encode_le(buf)
+        }
+        fn encode_be(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as u16).encode_be(buf)
+        }
+        fn decode_le(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: u16 = 0;
+            value.decode_le(data)?;
+            *self = <Self as std::convert::TryFrom<u16>>::try_from(value)?;
+            Ok(())
+        }
+        fn decode_be(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: u16 = 0;
+            value.decode_be(data)?;
+            *self = <Self as std::convert::TryFrom<u16>>::try_from(value)?;
+            Ok(())
+        }
+    }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[repr(i8)]
+    pub enum Color {
+        #[default]
+        Red = -1,
+        Green = 0,
+        Blue = 127,
+    }
+    impl std::convert::TryFrom<i8> for Color {
+        type Error = PError;
+        fn try_from(value: i8) -> Result<Self> {
+            match value {
+                -1 => Ok(Self::Red),
+                0 => Ok(Self::Green),
+                127 => Ok(Self::Blue),
+                _ => Err(PError::UnknownVariant),
+            }
+        }
+    }
+    impl Primitive for Color {
+        fn encode_le(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as i8).encode_le(buf)
+        }
+        fn encode_be(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as i8).encode_be(buf)
+        }
+        fn decode_le(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: i8 = 0;
+            value.decode_le(data)?;
+            *self = <Self as std::convert::TryFrom<i8>>::try_from(value)?;
+            Ok(())
+        }
+        fn decode_be(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: i8 = 0;
+            value.decode_be(data)?;
+            *self = <Self as std::convert::TryFrom<i8>>::try_from(value)?;
+            Ok(())
+        }
+    }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[repr(u32)]
+    pub enum Channel {
+        #[default]
+        Mono = 1,
+        Stereo = 8388608,
+    }
+    impl std::convert::TryFrom<u32> for Channel {
+        type Error = PError;
+        fn try_from(value: u32) -> Result<Self> {
+            match value {
+                1 => Ok(Self::Mono),
+                8388608 => Ok(Self::Stereo),
+                _ => Err(PError::UnknownVariant),
+            }
+        }
+    }
+    impl Primitive for Channel {
+        fn encode_le(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as u32).encode_narrow_le(3usize, buf)
+        }
+        fn encode_be(&self, buf: &mut [u8]) -> Result<()> {
+            (*self as u32).encode_narrow_be(3usize, buf)
+        }
+        fn decode_le(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: u32 = 0;
+            value.decode_narrow_le(3usize, data)?;
+            *self = <Self as std::convert::TryFrom<u32>>::try_from(value)?;
+            Ok(())
+        }
+        fn decode_be(&mut self, data: &[u8]) -> Result<()> {
+            let mut value: u32 = 0;
+            value.decode_narrow_be(3usize, data)?;
+            *self = <Self as std::convert::TryFrom<u32>>::try_from(value)?;
+            Ok(())
+        }
+    }
+    #[derive(Debug)]
+    pub enum Payload {
+        Wow(Wow),
+        S(S),
+    }
+    impl Default for Payload {
+        fn default() -> Self {
+            Self::Wow(Default::default())
+        }
+    }
+    impl Payload {
+        pub fn tag(&self) -> i128 {
+            match self {
+                Self::Wow(_) => 1,
+                Self::S(_) => 2,
+            }
+        }
+        pub fn decode_tagged(&mut self, tag: i128, data: &[u8]) -> Result<()> {
+            *self = match tag {
+                1 => Self::Wow(Wow::decode_new(data)?),
+                2 => Self::S(S::decode_new(data)?),
+                _ => return Err(PError::UnknownVariant),
+            };
+            Ok(())
+        }
+        pub fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            match self {
+                Self::Wow(inner) => inner.encode_buf(buf),
+                Self::S(inner) => inner.encode_buf(buf),
+            }
+        }
+        pub fn size(&self) -> usize {
+            match self {
+                Self::Wow(inner) => inner.size(),
+                Self::S(inner) => inner.size(),
+            }
+        }
+    }
     #[derive(Debug)]
     pub struct Test {
         pub a: u8,
@@ -21,6 +186,8 @@ pub mod pstruct {
         pub m: CString,
         pub n: Wow,
         pub o: [u8; 16usize],
+        pub p: Kind,
+        pub q: Vec<Color>,
         pub u: [String; 3usize],
         pub v: [Wow; 2usize],
         pub w: Vec<u8>,
@@ -63,6 +230,8 @@ pub mod pstruct {
                     Default::default(),
                     Default::default(),
                 ],
+                p: Default::default(),
+                q: vec![],
                 u: [Default::default(), Default::default(), Default::default()],
                 v: [Default::default(), Default::default()],
                 w: vec![],
@@ -103,7 +272,9 @@ pub mod pstruct {
             buf = &mut buf[4..];
             self.k.encode_le(&mut buf[..8])?;
             buf = &mut buf[8..];
-            (self.l.len() as u16).encode_le(&mut buf[..2])?;
+            let tmp_len = <u16 as ::core::convert::TryFrom<usize>>::try_from(self.l.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..2])?;
             buf = &mut buf[2..];
             self.l
                 .as_bytes()
@@ -125,8 +296,20 @@ pub mod pstruct {
                 self.o[idx].encode_le(&mut buf[..1])?;
                 buf = &mut buf[1..];
             }
+            self.p.encode_le(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(self.q.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            for idx in 0..self.q.len() {
+                self.q[idx].encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
             for idx in 0..self.u.len() {
-                (self.u[idx].len() as u16).encode_le(&mut buf[..2])?;
+                let tmp_len = <u16 as ::core::convert::TryFrom<usize>>::try_from(self.u[idx].len())
+                    .map_err(|_| PError::OutOfRange)?;
+                tmp_len.encode_le(&mut buf[..2])?;
                 buf = &mut buf[2..];
                 self.u[idx]
                     .as_bytes()
@@ -138,24 +321,40 @@ pub mod pstruct {
                 self.v[idx].encode_buf(&mut buf[..self.v[idx].size()])?;
                 buf = &mut buf[self.v[idx].size()..];
             }
-            assert!(self.w.len() == self.g as usize, "todo improve errors");
+            let tmp_len = (self.g as usize);
+            if self.w.len() != tmp_len {
+                return Err(PError::LengthMismatch);
+            }
             for idx in 0..self.w.len() {
                 self.w[idx].encode_le(&mut buf[..1])?;
                 buf = &mut buf[1..];
             }
-            assert!(self.x.len() == self.c as usize, "todo improve errors");
+            let tmp_len = (self.c as usize);
+            if self.x.len() != tmp_len {
+                return Err(PError::LengthMismatch);
+            }
             for idx in 0..self.x.len() {
                 self.x[idx].encode_buf(&mut buf[..self.x[idx].size()])?;
                 buf = &mut buf[self.x[idx].size()..];
             }
-            (self.y.len() as u8).encode_le(&mut buf[..1])?;
+            if (self.y.len()) > 8usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(self.y.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
             buf = &mut buf[1..];
             for idx in 0..self.y.len() {
                 self.y[idx].encode_le(&mut buf[..1])?;
                 buf = &mut buf[1..];
             }
-            assert!(self.z.as_bytes().len() < 255, "cstring too large");
-            buf[0] = (self.z.as_bytes().len() + 1) as u8;
+            if (self.z.as_bytes().len() + 1) > 40usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            let tmp_len =
+                <u8 as ::core::convert::TryFrom<usize>>::try_from(self.z.as_bytes().len() + 1)
+                    .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
             buf = &mut buf[1..];
             self.z
                 .as_bytes()
@@ -171,50 +370,66 @@ pub mod pstruct {
             Ok(res)
         }
         fn decode(&mut self, data: &[u8]) -> Result<()> {
-            assert!(data.len() >= self.size(), "todo improve errors");
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
             let mut data = data;
             let size = 1;
-            self.a.decode_le(&data[..size])?;
+            self.a
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 2;
-            self.b.decode_le(&data[..size])?;
+            self.b
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 4;
-            self.c.decode_le(&data[..size])?;
+            self.c
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 8;
-            self.d.decode_le(&data[..size])?;
+            self.d
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 1;
-            self.e.decode_le(&data[..size])?;
+            self.e
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 2;
-            self.f.decode_le(&data[..size])?;
+            self.f
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 4;
-            self.g.decode_le(&data[..size])?;
+            self.g
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 8;
-            self.h.decode_le(&data[..size])?;
+            self.h
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 1;
-            self.i.decode_le(&data[..size])?;
+            self.i
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 4;
-            self.j.decode_le(&data[..size])?;
+            self.j
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let size = 8;
-            self.k.decode_le(&data[..size])?;
+            self.k
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
             data = &data[size..];
             let mut tmp_len: u16 = 0;
-            tmp_len.decode_le(&data[..2])?;
+            tmp_len.decode_le(data.get(..2).ok_or(PError::NotEnoughData)?)?;
             data = &data[2..];
             let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
-            tmp_buf.decode_le(&data[..(tmp_len as usize)])?;
-            self.l = String::from_utf8(tmp_buf).unwrap();
+            tmp_buf.decode_le(
+                data.get(..(tmp_len as usize))
+                    .ok_or(PError::NotEnoughData)?,
+            )?;
+            self.l = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
             data = &data[(tmp_len as usize)..];
             let mut tmp_buf: Vec<u8> = vec![0; 40usize];
-            tmp_buf.decode_le(&data[..(40usize)])?;
+            tmp_buf.decode_le(data.get(..(40usize)).ok_or(PError::NotEnoughData)?)?;
             let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
             self.m = CString::new(tmp_buf).unwrap();
             data = &data[40usize..];
@@ -222,55 +437,86 @@ pub mod pstruct {
             data = &data[self.n.size()..];
             for idx in 0..(16usize as usize) {
                 let size = 1;
-                self.o[idx].decode_le(&data[..size])?;
+                self.o[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            let size = 2;
+            self.p
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let mut tmp_len: u8 = 0;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
+            data = &data[1..];
+            self.q = Vec::with_capacity(tmp_len as usize);
+            for idx in 0..(tmp_len as usize) {
+                self.q.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                let size = 1;
+                self.q[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
                 data = &data[size..];
             }
             for idx in 0..(3usize as usize) {
                 let mut tmp_len: u16 = 0;
-                tmp_len.decode_le(&data[..2])?;
+                tmp_len.decode_le(data.get(..2).ok_or(PError::NotEnoughData)?)?;
                 data = &data[2..];
                 let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
-                tmp_buf.decode_le(&data[..(tmp_len as usize)])?;
-                self.u[idx] = String::from_utf8(tmp_buf).unwrap();
+                tmp_buf.decode_le(
+                    data.get(..(tmp_len as usize))
+                        .ok_or(PError::NotEnoughData)?,
+                )?;
+                self.u[idx] = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
                 data = &data[(tmp_len as usize)..];
             }
             for idx in 0..(2usize as usize) {
                 self.v[idx].decode(data)?;
                 data = &data[self.v[idx].size()..];
             }
-            self.w = Vec::with_capacity(self.g as usize);
-            for idx in 0..(self.g as usize) {
+            let tmp_len = (self.g as usize);
+            self.w = Vec::with_capacity(tmp_len);
+            for idx in 0..tmp_len {
                 self.w.push(Default::default());
             }
-            for idx in 0..(self.g as usize) {
+            for idx in 0..(tmp_len as usize) {
                 let size = 1;
-                self.w[idx].decode_le(&data[..size])?;
+                self.w[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
                 data = &data[size..];
             }
-            self.x = Vec::with_capacity(self.c as usize);
-            for idx in 0..(self.c as usize) {
+            let tmp_len = (self.c as usize);
+            self.x = Vec::with_capacity(tmp_len);
+            for idx in 0..tmp_len {
                 self.x.push(Default::default());
             }
-            for idx in 0..(self.c as usize) {
+            for idx in 0..(tmp_len as usize) {
                 self.x[idx].decode(data)?;
                 data = &data[self.x[idx].size()..];
             }
             let mut tmp_len: u8 = 0;
-            tmp_len.decode_le(&data[..1])?;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
             data = &data[1..];
+            if (tmp_len as usize) > 8usize {
+                return Err(PError::ArrayTooLarge);
+            }
             self.y = Vec::with_capacity(tmp_len as usize);
             for idx in 0..(tmp_len as usize) {
                 self.y.push(Default::default());
             }
             for idx in 0..(tmp_len as usize) {
                 let size = 1;
-                self.y[idx].decode_le(&data[..size])?;
+                self.y[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
                 data = &data[size..];
             }
-            let tmp_len: u8 = data[0];
+            let mut tmp_len: u8 = 0;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
             data = &data[1..];
+            if (tmp_len as usize) > 40usize {
+                return Err(PError::ArrayTooLarge);
+            }
             let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
-            tmp_buf.decode_le(&data[..(tmp_len as usize)])?;
+            tmp_buf.decode_le(
+                data.get(..(tmp_len as usize))
+                    .ok_or(PError::NotEnoughData)?,
+            )?;
             let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
             self.z = CString::new(tmp_buf).unwrap();
             data = &data[(tmp_len as usize)..];
@@ -287,18 +533,18 @@ pub mod pstruct {
                 + 1
                 + 4
                 + 8
-                + self.l.len()
-                + 2
+                + (self.l.len() + 2)
                 + 40usize
                 + self.n.size()
                 + (16usize * (1))
-                + (self.u.iter().map(|i| i.len() + 2).sum::<usize>())
-                + (self.v.iter().map(|i| i.size()).sum::<usize>())
-                + (self.g as usize * (1))
-                + (self.x.iter().map(|i| i.size()).sum::<usize>())
-                + self.y.len() * (1)
-                + 1
-                + (self.z.as_bytes().len() + 2)
+                + 2
+                + (1 + (self.q.len() * (1)))
+                + self.u.iter().map(|i| (i.len() + 2)).sum::<usize>()
+                + self.v.iter().map(|i| i.size()).sum::<usize>()
+                + ((self.g as usize) * (1))
+                + self.x.iter().map(|i| i.size()).sum::<usize>()
+                + (1 + (self.y.len() * (1)))
+                + (1 + self.z.as_bytes().len() + 1)
         }
     }
     #[derive(Debug)]
@@ -319,12 +565,16 @@ pub mod pstruct {
         fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
             assert!(buf.len() >= self.size(), "todo improve errors");
             let mut buf = buf;
-            (self.amazing.len() as u8).encode_le(&mut buf[..1])?;
+            let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(self.amazing.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
             buf = &mut buf[1..];
             for idx in 0..self.amazing.len() {
                 self.amazing[idx].encode_le(&mut buf[..1])?;
                 buf = &mut buf[1..];
             }
+            buf[..3usize].fill(0);
+            buf = &mut buf[3usize..];
             Ok(())
         }
         fn decode_new(data: &[u8]) -> Result<Self> {
@@ -333,10 +583,12 @@ pub mod pstruct {
             Ok(res)
         }
         fn decode(&mut self, data: &[u8]) -> Result<()> {
-            assert!(data.len() >= self.size(), "todo improve errors");
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
             let mut data = data;
             let mut tmp_len: u8 = 0;
-            tmp_len.decode_le(&data[..1])?;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
             data = &data[1..];
             self.amazing = Vec::with_capacity(tmp_len as usize);
             for idx in 0..(tmp_len as usize) {
@@ -344,13 +596,14 @@ pub mod pstruct {
             }
             for idx in 0..(tmp_len as usize) {
                 let size = 1;
-                self.amazing[idx].decode_le(&data[..size])?;
+                self.amazing[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
                 data = &data[size..];
             }
+            data = data.get(3usize..).ok_or(PError::NotEnoughData)?;
             Ok(())
         }
         fn size(&self) -> usize {
-            self.amazing.len() * (1) + 1
+            (1 + (self.amazing.len() * (1))) + 3usize
         }
     }
     #[derive(Debug)]
@@ -373,7 +626,9 @@ pub mod pstruct {
         fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
             assert!(buf.len() >= self.size(), "todo improve errors");
             let mut buf = buf;
-            (self.t.len() as u16).encode_le(&mut buf[..2])?;
+            let tmp_len = <u16 as ::core::convert::TryFrom<usize>>::try_from(self.t.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..2])?;
             buf = &mut buf[2..];
             self.t
                 .as_bytes()
@@ -388,19 +643,1519 @@ pub mod pstruct {
             Ok(res)
         }
         fn decode(&mut self, data: &[u8]) -> Result<()> {
-            assert!(data.len() >= self.size(), "todo improve errors");
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
             let mut data = data;
             let mut tmp_len: u16 = 0;
-            tmp_len.decode_le(&data[..2])?;
+            tmp_len.decode_le(data.get(..2).ok_or(PError::NotEnoughData)?)?;
             data = &data[2..];
             let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
-            tmp_buf.decode_le(&data[..(tmp_len as usize)])?;
-            self.t = String::from_utf8(tmp_buf).unwrap();
+            tmp_buf.decode_le(
+                data.get(..(tmp_len as usize))
+                    .ok_or(PError::NotEnoughData)?,
+            )?;
+            self.t = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+            data = &data[(tmp_len as usize)..];
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            (self.t.len() + 2)
+        }
+    }
+    #[derive(Debug)]
+    pub struct Message {
+        pub version: u16,
+        pub urgent: u16,
+        pub count: u16,
+        pub extra: Vec<u8>,
+        pub pairs: Vec<i16>,
+        pub label: CString,
+        pub bonus: Option<u8>,
+        pub ext: Option<Wow>,
+        pub code: Option<u16>,
+        pub late: Option<Vec<u8>>,
+        pub kind: Kind,
+        pub body: Payload,
+    }
+    impl Default for Message {
+        fn default() -> Self {
+            Self {
+                version: 0,
+                urgent: 0,
+                count: 0,
+                extra: vec![],
+                pairs: vec![],
+                label: Default::default(),
+                bonus: None,
+                ext: None,
+                code: None,
+                late: None,
+                kind: Default::default(),
+                body: Default::default(),
+            }
+        }
+    }
+    impl Pstruct for Message {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            {
+                let magic: u32 = 3405691582;
+                magic.encode_be(&mut buf[..4usize])?;
+                buf = &mut buf[4usize..];
+            }
+            {
+                let mut bits: u16 = 0;
+                if self.version > 15 {
+                    return Err(PError::OutOfRange);
+                }
+                bits |= self.version << 12u32;
+                if self.urgent > 1 {
+                    return Err(PError::OutOfRange);
+                }
+                bits |= self.urgent << 11u32;
+                if self.count > 2047 {
+                    return Err(PError::OutOfRange);
+                }
+                bits |= self.count << 0u32;
+                bits.encode_be(&mut buf[..2usize])?;
+                buf = &mut buf[2usize..];
+            }
+            let tmp_len = (self.count as usize);
+            if self.extra.len() != tmp_len {
+                return Err(PError::LengthMismatch);
+            }
+            for idx in 0..self.extra.len() {
+                self.extra[idx].encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
+            let tmp_len = Some((self.count as i128))
+                .zip(Some(2i128))
+                .and_then(|(lhs, rhs)| lhs.checked_mul(rhs))
+                .zip(Some(1i128))
+                .and_then(|(lhs, rhs)| lhs.checked_sub(rhs))
+                .and_then(|len| <usize as ::core::convert::TryFrom<i128>>::try_from(len).ok())
+                .ok_or(PError::OutOfRange)?;
+            if self.pairs.len() != tmp_len {
+                return Err(PError::LengthMismatch);
+            }
+            for idx in 0..self.pairs.len() {
+                self.pairs[idx].encode_le(&mut buf[..2])?;
+                buf = &mut buf[2..];
+            }
+            let tmp_len = Some((self.version as i128))
+                .zip(Some(2i128))
+                .and_then(|(lhs, rhs)| lhs.checked_add(rhs))
+                .and_then(|len| <usize as ::core::convert::TryFrom<i128>>::try_from(len).ok())
+                .ok_or(PError::OutOfRange)?;
+            assert!(self.label.as_bytes().len() < tmp_len, "cstring too large");
+            self.label
+                .as_bytes()
+                .to_vec()
+                .encode_le(&mut buf[..self.label.as_bytes().len()])?;
+            for idx in self.label.as_bytes().len()..tmp_len {
+                buf[idx] = 0;
+            }
+            buf = &mut buf[tmp_len..];
+            if Some((self.count as i128))
+                .zip(Some(2i128))
+                .and_then(|(lhs, rhs)| lhs.checked_mul(rhs))
+                .zip(Some(4i128))
+                .map(|(lhs, rhs)| lhs > rhs)
+                .ok_or(PError::OutOfRange)?
+            {
+                match &self.bonus {
+                    Some(value) => {
+                        value.encode_le(&mut buf[..1])?;
+                        buf = &mut buf[1..];
+                    }
+                    None => return Err(PError::MissingField),
+                }
+            }
+            if (((self.urgent as i128) == 1i128) && ((self.version as i128) >= 2i128)) {
+                match &self.ext {
+                    Some(value) => {
+                        value.encode_buf(&mut buf[..value.size()])?;
+                        buf = &mut buf[value.size()..];
+                    }
+                    None => return Err(PError::MissingField),
+                }
+            }
+            if (((self.version as i128) & 3i128) != 0) {
+                match &self.code {
+                    Some(value) => {
+                        value.encode_be(&mut buf[..2])?;
+                        buf = &mut buf[2..];
+                    }
+                    None => return Err(PError::MissingField),
+                }
+            }
+            if !((self.version as i128) < 8i128) {
+                match &self.late {
+                    Some(value) => {
+                        let tmp_len =
+                            <u8 as ::core::convert::TryFrom<usize>>::try_from(value.len())
+                                .map_err(|_| PError::OutOfRange)?;
+                        tmp_len.encode_le(&mut buf[..1])?;
+                        buf = &mut buf[1..];
+                        for idx in 0..value.len() {
+                            value[idx].encode_le(&mut buf[..1])?;
+                            buf = &mut buf[1..];
+                        }
+                    }
+                    None => return Err(PError::MissingField),
+                }
+            }
+            self.kind.encode_le(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            if self.body.tag() != self.kind as i128 {
+                return Err(PError::TagMismatch);
+            }
+            self.body.encode_buf(&mut buf[..self.body.size()])?;
+            buf = &mut buf[self.body.size()..];
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            {
+                let mut magic: u32 = 0;
+                magic.decode_be(data.get(..4usize).ok_or(PError::NotEnoughData)?)?;
+                if magic != 3405691582 {
+                    return Err(PError::BadMagic);
+                }
+                data = &data[4usize..];
+            }
+            {
+                let mut bits: u16 = 0;
+                bits.decode_be(data.get(..2usize).ok_or(PError::NotEnoughData)?)?;
+                self.version = (bits >> 12u32) & 15;
+                self.urgent = (bits >> 11u32) & 1;
+                self.count = (bits >> 0u32) & 2047;
+                data = &data[2usize..];
+            }
+            let tmp_len = (self.count as usize);
+            self.extra = Vec::with_capacity(tmp_len);
+            for idx in 0..tmp_len {
+                self.extra.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                let size = 1;
+                self.extra[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            let tmp_len = Some((self.count as i128))
+                .zip(Some(2i128))
+                .and_then(|(lhs, rhs)| lhs.checked_mul(rhs))
+                .zip(Some(1i128))
+                .and_then(|(lhs, rhs)| lhs.checked_sub(rhs))
+                .and_then(|len| <usize as ::core::convert::TryFrom<i128>>::try_from(len).ok())
+                .ok_or(PError::OutOfRange)?;
+            self.pairs = Vec::with_capacity(tmp_len);
+            for idx in 0..tmp_len {
+                self.pairs.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                let size = 2;
+                self.pairs[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            let tmp_len = Some((self.version as i128))
+                .zip(Some(2i128))
+                .and_then(|(lhs, rhs)| lhs.checked_add(rhs))
+                .and_then(|len| <usize as ::core::convert::TryFrom<i128>>::try_from(len).ok())
+                .ok_or(PError::OutOfRange)?;
+            let mut tmp_buf: Vec<u8> = vec![0; tmp_len];
+            tmp_buf.decode_le(data.get(..tmp_len).ok_or(PError::NotEnoughData)?)?;
+            let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
+            self.label = CString::new(tmp_buf).unwrap();
+            data = &data[tmp_len..];
+            self.bonus = None;
+            if Some((self.count as i128))
+                .zip(Some(2i128))
+                .and_then(|(lhs, rhs)| lhs.checked_mul(rhs))
+                .zip(Some(4i128))
+                .map(|(lhs, rhs)| lhs > rhs)
+                .ok_or(PError::OutOfRange)?
+            {
+                let mut value: u8 = Default::default();
+                let size = 1;
+                value.decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+                self.bonus = Some(value);
+            }
+            self.ext = None;
+            if (((self.urgent as i128) == 1i128) && ((self.version as i128) >= 2i128)) {
+                let mut value: Wow = Default::default();
+                value.decode(data)?;
+                data = &data[value.size()..];
+                self.ext = Some(value);
+            }
+            self.code = None;
+            if (((self.version as i128) & 3i128) != 0) {
+                let mut value: u16 = Default::default();
+                let size = 2;
+                value.decode_be(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+                self.code = Some(value);
+            }
+            self.late = None;
+            if !((self.version as i128) < 8i128) {
+                let mut value: Vec<u8> = vec![];
+                let mut tmp_len: u8 = 0;
+                tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
+                data = &data[1..];
+                value = Vec::with_capacity(tmp_len as usize);
+                for idx in 0..(tmp_len as usize) {
+                    value.push(Default::default());
+                }
+                for idx in 0..(tmp_len as usize) {
+                    let size = 1;
+                    value[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                    data = &data[size..];
+                }
+                self.late = Some(value);
+            }
+            let size = 2;
+            self.kind
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            self.body.decode_tagged(self.kind as i128, data)?;
+            data = &data[self.body.size()..];
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            4usize
+                + 2usize
+                + ((self.count as usize) * (1))
+                + (Some((self.count as i128))
+                    .zip(Some(2i128))
+                    .and_then(|(lhs, rhs)| lhs.checked_mul(rhs))
+                    .zip(Some(1i128))
+                    .and_then(|(lhs, rhs)| lhs.checked_sub(rhs))
+                    .and_then(|len| <usize as ::core::convert::TryFrom<i128>>::try_from(len).ok())
+                    .unwrap_or(0)
+                    * (2))
+                + Some((self.version as i128))
+                    .zip(Some(2i128))
+                    .and_then(|(lhs, rhs)| lhs.checked_add(rhs))
+                    .and_then(|len| <usize as ::core::convert::TryFrom<i128>>::try_from(len).ok())
+                    .unwrap_or(0)
+                + (if Some((self.count as i128))
+                    .zip(Some(2i128))
+                    .and_then(|(lhs, rhs)| lhs.checked_mul(rhs))
+                    .zip(Some(4i128))
+                    .map(|(lhs, rhs)| lhs > rhs)
+                    .unwrap_or(false)
+                {
+                    self.bonus.as_ref().map_or(0, |value| 1)
+                } else {
+                    0
+                })
+                + (if (((self.urgent as i128) == 1i128) && ((self.version as i128) >= 2i128)) {
+                    self.ext.as_ref().map_or(0, |value| value.size())
+                } else {
+                    0
+                })
+                + (if (((self.version as i128) & 3i128) != 0) {
+                    self.code.as_ref().map_or(0, |value| 2)
+                } else {
+                    0
+                })
+                + (if !((self.version as i128) < 8i128) {
+                    self.late
+                        .as_ref()
+                        .map_or(0, |value| (1 + (value.len() * (1))))
+                } else {
+                    0
+                })
+                + 2
+                + self.body.size()
+        }
+    }
+    #[derive(Debug)]
+    pub struct Inventory {
+        pub owner: u8,
+        pub items: Vec<Wow>,
+        pub name: CString,
+    }
+    impl Default for Inventory {
+        fn default() -> Self {
+            Self {
+                owner: Default::default(),
+                items: vec![],
+                name: Default::default(),
+            }
+        }
+    }
+    impl Pstruct for Inventory {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            {
+                let len = <u16 as ::core::convert::TryFrom<usize>>::try_from(self.items.len())
+                    .map_err(|_| PError::OutOfRange)?;
+                len.encode_le(&mut buf[..2usize])?;
+                buf = &mut buf[2usize..];
+            }
+            {
+                let len = <u8 as ::core::convert::TryFrom<usize>>::try_from(
+                    (self.name.as_bytes().len() + 1),
+                )
+                .map_err(|_| PError::OutOfRange)?;
+                len.encode_le(&mut buf[..1usize])?;
+                buf = &mut buf[1usize..];
+            }
+            self.owner.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            if (self.items.len()) > 8usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            for idx in 0..self.items.len() {
+                self.items[idx].encode_buf(&mut buf[..self.items[idx].size()])?;
+                buf = &mut buf[self.items[idx].size()..];
+            }
+            let tmp_len = (self.name.as_bytes().len() + 1);
+            self.name
+                .as_bytes()
+                .to_vec()
+                .encode_le(&mut buf[..self.name.as_bytes().len()])?;
+            for idx in self.name.as_bytes().len()..tmp_len {
+                buf[idx] = 0;
+            }
+            buf = &mut buf[tmp_len..];
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let mut len_count: u16 = 0;
+            len_count.decode_le(data.get(..2usize).ok_or(PError::NotEnoughData)?)?;
+            data = &data[2usize..];
+            let mut len_nlen: u8 = 0;
+            len_nlen.decode_le(data.get(..1usize).ok_or(PError::NotEnoughData)?)?;
+            data = &data[1usize..];
+            let size = 1;
+            self.owner
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let tmp_len = (len_count as usize);
+            if (tmp_len) > 8usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            self.items = Vec::with_capacity(tmp_len);
+            for idx in 0..tmp_len {
+                self.items.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                self.items[idx].decode(data)?;
+                data = &data[self.items[idx].size()..];
+            }
+            let tmp_len = (len_nlen as usize);
+            let mut tmp_buf: Vec<u8> = vec![0; tmp_len];
+            tmp_buf.decode_le(data.get(..tmp_len).ok_or(PError::NotEnoughData)?)?;
+            let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
+            self.name = CString::new(tmp_buf).unwrap();
+            data = &data[tmp_len..];
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            2usize
+                + 1usize
+                + 1
+                + self.items.iter().map(|i| i.size()).sum::<usize>()
+                + (self.name.as_bytes().len() + 1)
+        }
+    }
+    #[derive(Debug)]
+    pub struct Catalog {
+        pub entries: Vec<Wow>,
+        pub sizes: Vec<i16>,
+    }
+    impl Default for Catalog {
+        fn default() -> Self {
+            Self {
+                entries: vec![],
+                sizes: vec![],
+            }
+        }
+    }
+    impl Pstruct for Catalog {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            if (self.entries.len()) > 4usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            let tmp_len = <u16 as ::core::convert::TryFrom<usize>>::try_from(
+                self.entries.iter().map(|elem| elem.size()).sum::<usize>(),
+            )
+            .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            for idx in 0..self.entries.len() {
+                self.entries[idx].encode_buf(&mut buf[..self.entries[idx].size()])?;
+                buf = &mut buf[self.entries[idx].size()..];
+            }
+            let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(
+                self.sizes.iter().map(|elem| 2).sum::<usize>(),
+            )
+            .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            for idx in 0..self.sizes.len() {
+                self.sizes[idx].encode_le(&mut buf[..2])?;
+                buf = &mut buf[2..];
+            }
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let mut tmp_len: u16 = 0;
+            tmp_len.decode_le(data.get(..2).ok_or(PError::NotEnoughData)?)?;
+            data = &data[2..];
+            if (tmp_len as usize) > data.len() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut elems = &data[..(tmp_len as usize)];
+            data = &data[(tmp_len as usize)..];
+            self.entries = Vec::new();
+            while !elems.is_empty() {
+                let mut value: Wow = Default::default();
+                value.decode(elems)?;
+                let size = value.size();
+                if size > elems.len() {
+                    return Err(PError::NotEnoughData);
+                }
+                elems = &elems[size..];
+                self.entries.push(value);
+                if (self.entries.len()) > 4usize {
+                    return Err(PError::ArrayTooLarge);
+                }
+            }
+            let mut tmp_len: u8 = 0;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
+            data = &data[1..];
+            if (tmp_len as usize) > data.len() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut elems = &data[..(tmp_len as usize)];
             data = &data[(tmp_len as usize)..];
+            self.sizes = Vec::new();
+            while !elems.is_empty() {
+                let mut value: i16 = Default::default();
+                value.decode_le(elems)?;
+                let size = 2;
+                if size > elems.len() {
+                    return Err(PError::NotEnoughData);
+                }
+                elems = &elems[size..];
+                self.sizes.push(value);
+            }
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            (2 + self.entries.iter().map(|i| i.size()).sum::<usize>())
+                + (1 + (self.sizes.len() * (2)))
+        }
+    }
+    #[derive(Debug)]
+    pub struct Label {
+        pub short: String,
+        pub code: String,
+        pub tags: [String; 2usize],
+        pub notes: Vec<String>,
+        pub long: CString,
+    }
+    impl Default for Label {
+        fn default() -> Self {
+            Self {
+                short: Default::default(),
+                code: Default::default(),
+                tags: [Default::default(), Default::default()],
+                notes: vec![],
+                long: Default::default(),
+            }
+        }
+    }
+    impl Pstruct for Label {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(self.short.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            self.short
+                .as_bytes()
+                .to_vec()
+                .encode_le(&mut buf[..self.short.len()])?;
+            buf = &mut buf[self.short.len()..];
+            if self.code.len() > 8usize {
+                return Err(PError::OutOfRange);
+            }
+            self.code
+                .as_bytes()
+                .to_vec()
+                .encode_le(&mut buf[..self.code.len()])?;
+            buf[self.code.len()..8usize].fill(b' ');
+            buf = &mut buf[8usize..];
+            for idx in 0..self.tags.len() {
+                if self.tags[idx].len() > 4usize {
+                    return Err(PError::OutOfRange);
+                }
+                self.tags[idx]
+                    .as_bytes()
+                    .to_vec()
+                    .encode_le(&mut buf[..self.tags[idx].len()])?;
+                buf[self.tags[idx].len()..4usize].fill(b' ');
+                buf = &mut buf[4usize..];
+            }
+            let tmp_len = <u16 as ::core::convert::TryFrom<usize>>::try_from(self.notes.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            for idx in 0..self.notes.len() {
+                let tmp_len =
+                    <u16 as ::core::convert::TryFrom<usize>>::try_from(self.notes[idx].len())
+                        .map_err(|_| PError::OutOfRange)?;
+                tmp_len.encode_le(&mut buf[..2])?;
+                buf = &mut buf[2..];
+                self.notes[idx]
+                    .as_bytes()
+                    .to_vec()
+                    .encode_le(&mut buf[..self.notes[idx].len()])?;
+                buf = &mut buf[self.notes[idx].len()..];
+            }
+            let tmp_len =
+                <u16 as ::core::convert::TryFrom<usize>>::try_from(self.long.as_bytes().len() + 1)
+                    .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_be(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            self.long
+                .as_bytes()
+                .to_vec()
+                .encode_be(&mut buf[..self.long.as_bytes().len()])?;
+            buf[self.long.as_bytes().len()] = 0;
+            buf = &mut buf[(self.long.as_bytes().len() + 1)..];
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let mut tmp_len: u8 = 0;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
+            data = &data[1..];
+            let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
+            tmp_buf.decode_le(
+                data.get(..(tmp_len as usize))
+                    .ok_or(PError::NotEnoughData)?,
+            )?;
+            self.short = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+            data = &data[(tmp_len as usize)..];
+            let mut tmp_buf: Vec<u8> = vec![0; 8usize];
+            tmp_buf.decode_le(data.get(..8usize).ok_or(PError::NotEnoughData)?)?;
+            let end = tmp_buf
+                .iter()
+                .rposition(|c| *c != b' ')
+                .map_or(0, |idx| idx + 1);
+            tmp_buf.truncate(end);
+            self.code = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+            data = &data[8usize..];
+            for idx in 0..(2usize as usize) {
+                let mut tmp_buf: Vec<u8> = vec![0; 4usize];
+                tmp_buf.decode_le(data.get(..4usize).ok_or(PError::NotEnoughData)?)?;
+                let end = tmp_buf
+                    .iter()
+                    .rposition(|c| *c != b' ')
+                    .map_or(0, |idx| idx + 1);
+                tmp_buf.truncate(end);
+                self.tags[idx] = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+                data = &data[4usize..];
+            }
+            let mut tmp_len: u16 = 0;
+            tmp_len.decode_le(data.get(..2).ok_or(PError::NotEnoughData)?)?;
+            data = &data[2..];
+            self.notes = Vec::with_capacity(tmp_len as usize);
+            for idx in 0..(tmp_len as usize) {
+                self.notes.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                let mut tmp_len: u16 = 0;
+                tmp_len.decode_le(data.get(..2).ok_or(PError::NotEnoughData)?)?;
+                data = &data[2..];
+                let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
+                tmp_buf.decode_le(
+                    data.get(..(tmp_len as usize))
+                        .ok_or(PError::NotEnoughData)?,
+                )?;
+                self.notes[idx] = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+                data = &data[(tmp_len as usize)..];
+            }
+            let mut tmp_len: u16 = 0;
+            tmp_len.decode_be(data.get(..2).ok_or(PError::NotEnoughData)?)?;
+            data = &data[2..];
+            let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
+            tmp_buf.decode_be(
+                data.get(..(tmp_len as usize))
+                    .ok_or(PError::NotEnoughData)?,
+            )?;
+            let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
+            self.long = CString::new(tmp_buf).unwrap();
+            data = &data[(tmp_len as usize)..];
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            (self.short.len() + 1)
+                + 8usize
+                + self.tags.iter().map(|i| 4usize).sum::<usize>()
+                + (2 + self.notes.iter().map(|i| (i.len() + 2)).sum::<usize>())
+                + (2 + self.long.as_bytes().len() + 1)
+        }
+    }
+    #[derive(Debug)]
+    pub struct Envelope {
+        pub kind: u8,
+        pub payload: Vec<u8>,
+    }
+    impl Default for Envelope {
+        fn default() -> Self {
+            Self {
+                kind: Default::default(),
+                payload: vec![],
+            }
+        }
+    }
+    impl Pstruct for Envelope {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            self.kind.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            if (self.payload.len()) > 64usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            for idx in 0..self.payload.len() {
+                self.payload[idx].encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let size = 1;
+            self.kind
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let tmp_len = data.len();
+            if (tmp_len) > 64usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            self.payload = vec![0; tmp_len];
+            for idx in 0..(tmp_len as usize) {
+                let size = 1;
+                self.payload[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            1 + (self.payload.len() * (1))
+        }
+    }
+    #[derive(Debug)]
+    pub struct TileMap {
+        pub rows: u8,
+        pub cols: u8,
+        pub tiles: [[u8; 4usize]; 4usize],
+        pub grid: Vec<Vec<i16>>,
+        pub names: [CString; 2usize],
+        pub layers: Vec<[Wow; 2usize]>,
+    }
+    impl Default for TileMap {
+        fn default() -> Self {
+            Self {
+                rows: Default::default(),
+                cols: Default::default(),
+                tiles: [
+                    [
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                    ],
+                    [
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                    ],
+                    [
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                    ],
+                    [
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                    ],
+                ],
+                grid: vec![],
+                names: [Default::default(), Default::default()],
+                layers: vec![],
+            }
+        }
+    }
+    impl Pstruct for TileMap {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            self.rows.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            self.cols.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            for idx1 in 0..self.tiles.len() {
+                for idx in 0..self.tiles[idx1].len() {
+                    self.tiles[idx1][idx].encode_le(&mut buf[..1])?;
+                    buf = &mut buf[1..];
+                }
+            }
+            let tmp_len = (self.rows as usize);
+            if self.grid.len() != tmp_len {
+                return Err(PError::LengthMismatch);
+            }
+            for idx1 in 0..self.grid.len() {
+                let tmp_len = (self.cols as usize);
+                if self.grid[idx1].len() != tmp_len {
+                    return Err(PError::LengthMismatch);
+                }
+                for idx in 0..self.grid[idx1].len() {
+                    self.grid[idx1][idx].encode_be(&mut buf[..2])?;
+                    buf = &mut buf[2..];
+                }
+            }
+            for idx1 in 0..self.names.len() {
+                let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(
+                    self.names[idx1].as_bytes().len() + 1,
+                )
+                .map_err(|_| PError::OutOfRange)?;
+                tmp_len.encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+                self.names[idx1]
+                    .as_bytes()
+                    .to_vec()
+                    .encode_le(&mut buf[..self.names[idx1].as_bytes().len()])?;
+                buf[self.names[idx1].as_bytes().len()] = 0;
+                buf = &mut buf[(self.names[idx1].as_bytes().len() + 1)..];
+            }
+            if (self.layers.len()) > 4usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(self.layers.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            for idx1 in 0..self.layers.len() {
+                for idx in 0..self.layers[idx1].len() {
+                    self.layers[idx1][idx].encode_buf(&mut buf[..self.layers[idx1][idx].size()])?;
+                    buf = &mut buf[self.layers[idx1][idx].size()..];
+                }
+            }
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let size = 1;
+            self.rows
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 1;
+            self.cols
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            for idx1 in 0..self.tiles.len() {
+                for idx in 0..(4usize as usize) {
+                    let size = 1;
+                    self.tiles[idx1][idx]
+                        .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                    data = &data[size..];
+                }
+            }
+            let tmp_len = (self.rows as usize);
+            self.grid = Vec::with_capacity(tmp_len);
+            for _ in 0..tmp_len {
+                self.grid.push(vec![]);
+            }
+            for idx1 in 0..self.grid.len() {
+                let tmp_len = (self.cols as usize);
+                self.grid[idx1] = Vec::with_capacity(tmp_len);
+                for idx in 0..tmp_len {
+                    self.grid[idx1].push(Default::default());
+                }
+                for idx in 0..(tmp_len as usize) {
+                    let size = 2;
+                    self.grid[idx1][idx]
+                        .decode_be(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                    data = &data[size..];
+                }
+            }
+            for idx1 in 0..self.names.len() {
+                let mut tmp_len: u8 = 0;
+                tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
+                data = &data[1..];
+                let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
+                tmp_buf.decode_le(
+                    data.get(..(tmp_len as usize))
+                        .ok_or(PError::NotEnoughData)?,
+                )?;
+                let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
+                self.names[idx1] = CString::new(tmp_buf).unwrap();
+                data = &data[(tmp_len as usize)..];
+            }
+            let mut tmp_len: u8 = 0;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
+            data = &data[1..];
+            if (tmp_len as usize) > 4usize {
+                return Err(PError::ArrayTooLarge);
+            }
+            self.layers = Vec::with_capacity(tmp_len as usize);
+            for _ in 0..(tmp_len as usize) {
+                self.layers.push([Default::default(), Default::default()]);
+            }
+            for idx1 in 0..self.layers.len() {
+                for idx in 0..(2usize as usize) {
+                    self.layers[idx1][idx].decode(data)?;
+                    data = &data[self.layers[idx1][idx].size()..];
+                }
+            }
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            1 + 1
+                + (self.tiles.iter().map(|row| (4usize * (1))).sum::<usize>())
+                + (self
+                    .grid
+                    .iter()
+                    .map(|row| ((self.cols as usize) * (2)))
+                    .sum::<usize>())
+                + (self
+                    .names
+                    .iter()
+                    .map(|row| (1 + row.as_bytes().len() + 1))
+                    .sum::<usize>())
+                + (1 + self
+                    .layers
+                    .iter()
+                    .map(|row| row.iter().map(|i| i.size()).sum::<usize>())
+                    .sum::<usize>())
+        }
+    }
+    #[derive(Debug)]
+    pub struct Flags {
+        pub on: bool,
+        pub history: [bool; 3usize],
+        pub checked: bool,
+        pub answers: Vec<bool>,
+    }
+    impl Default for Flags {
+        fn default() -> Self {
+            Self {
+                on: Default::default(),
+                history: [Default::default(), Default::default(), Default::default()],
+                checked: Default::default(),
+                answers: vec![],
+            }
+        }
+    }
+    impl Pstruct for Flags {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            self.on.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            for idx in 0..self.history.len() {
+                self.history[idx].encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
+            self.checked.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            let tmp_len = <u8 as ::core::convert::TryFrom<usize>>::try_from(
+                self.answers.iter().map(|elem| 1).sum::<usize>(),
+            )
+            .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            for idx in 0..self.answers.len() {
+                self.answers[idx].encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let size = 1;
+            self.on
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            for idx in 0..(3usize as usize) {
+                let size = 1;
+                self.history[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            let bytes = data.get(..1).ok_or(PError::NotEnoughData)?;
+            if bytes[0] > 1 {
+                return Err(PError::InvalidBool);
+            }
+            self.checked.decode_le(bytes)?;
+            data = &data[1..];
+            let mut tmp_len: u8 = 0;
+            tmp_len.decode_le(data.get(..1).ok_or(PError::NotEnoughData)?)?;
+            data = &data[1..];
+            if (tmp_len as usize) > data.len() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut elems = &data[..(tmp_len as usize)];
+            data = &data[(tmp_len as usize)..];
+            self.answers = Vec::new();
+            while !elems.is_empty() {
+                if elems[0] > 1 {
+                    return Err(PError::InvalidBool);
+                }
+                let mut value: bool = Default::default();
+                value.decode_le(elems)?;
+                let size = 1;
+                if size > elems.len() {
+                    return Err(PError::NotEnoughData);
+                }
+                elems = &elems[size..];
+                self.answers.push(value);
+            }
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            1 + (3usize * (1)) + 1 + (1 + (self.answers.len() * (1)))
+        }
+    }
+    #[derive(Debug)]
+    pub struct Varints {
+        pub id: u32,
+        pub delta: i64,
+        pub big: u64,
+        pub items: Vec<u8>,
+        pub name: String,
+    }
+    impl Default for Varints {
+        fn default() -> Self {
+            Self {
+                id: Default::default(),
+                delta: Default::default(),
+                big: Default::default(),
+                items: vec![],
+                name: Default::default(),
+            }
+        }
+    }
+    impl Pstruct for Varints {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            let size = self.id.encode_var(buf)?;
+            buf = &mut buf[size..];
+            let size = self.delta.encode_var(buf)?;
+            buf = &mut buf[size..];
+            let size = self.big.encode_var(buf)?;
+            buf = &mut buf[size..];
+            let tmp_len = <u32 as ::core::convert::TryFrom<usize>>::try_from(self.items.len())
+                .map_err(|_| PError::OutOfRange)?;
+            let len_size = tmp_len.encode_var(buf)?;
+            buf = &mut buf[len_size..];
+            for idx in 0..self.items.len() {
+                self.items[idx].encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
+            let tmp_len = <u32 as ::core::convert::TryFrom<usize>>::try_from(self.name.len())
+                .map_err(|_| PError::OutOfRange)?;
+            let len_size = tmp_len.encode_var(buf)?;
+            buf = &mut buf[len_size..];
+            self.name
+                .as_bytes()
+                .to_vec()
+                .encode_le(&mut buf[..self.name.len()])?;
+            buf = &mut buf[self.name.len()..];
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let (value, size) = <u32 as Varint>::decode_var(data)?;
+            self.id = value;
+            data = &data[size..];
+            let (value, size) = <i64 as Varint>::decode_var(data)?;
+            self.delta = value;
+            data = &data[size..];
+            let (value, size) = <u64 as Varint>::decode_var(data)?;
+            self.big = value;
+            data = &data[size..];
+            let (tmp_len, len_size) = <u32 as Varint>::decode_var(data)?;
+            data = &data[len_size..];
+            self.items = Vec::with_capacity(tmp_len as usize);
+            for idx in 0..(tmp_len as usize) {
+                self.items.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                let size = 1;
+                self.items[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            let (tmp_len, len_size) = <u32 as Varint>::decode_var(data)?;
+            data = &data[len_size..];
+            let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
+            tmp_buf.decode_le(
+                data.get(..(tmp_len as usize))
+                    .ok_or(PError::NotEnoughData)?,
+            )?;
+            self.name = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+            data = &data[(tmp_len as usize)..];
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            self.id.var_len()
+                + self.delta.var_len()
+                + self.big.var_len()
+                + (((self.items.len()) as u32).var_len() + (self.items.len() * (1)))
+                + (self.name.len() + ((self.name.len()) as u32).var_len())
+        }
+    }
+    #[derive(Debug)]
+    pub struct Sample {
+        pub left: i32,
+        pub right: i32,
+        pub stamp: u64,
+        pub channel: Channel,
+        pub hash: u128,
+        pub delta: i128,
+        pub frames: Vec<u8>,
+    }
+    impl Default for Sample {
+        fn default() -> Self {
+            Self {
+                left: Default::default(),
+                right: Default::default(),
+                stamp: Default::default(),
+                channel: Default::default(),
+                hash: Default::default(),
+                delta: Default::default(),
+                frames: vec![],
+            }
+        }
+    }
+    impl Pstruct for Sample {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            self.left.encode_narrow_le(3usize, &mut buf[..3])?;
+            buf = &mut buf[3..];
+            self.right.encode_narrow_be(3usize, &mut buf[..3])?;
+            buf = &mut buf[3..];
+            self.stamp.encode_narrow_le(6usize, &mut buf[..6])?;
+            buf = &mut buf[6..];
+            self.channel.encode_le(&mut buf[..3])?;
+            buf = &mut buf[3..];
+            self.hash.encode_be(&mut buf[..16])?;
+            buf = &mut buf[16..];
+            self.delta.encode_le(&mut buf[..16])?;
+            buf = &mut buf[16..];
+            let tmp_len = <u32 as ::core::convert::TryFrom<usize>>::try_from(self.frames.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_narrow_le(3usize, &mut buf[..3])?;
+            buf = &mut buf[3..];
+            for idx in 0..self.frames.len() {
+                self.frames[idx].encode_le(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let size = 3;
+            self.left
+                .decode_narrow_le(3usize, data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 3;
+            self.right
+                .decode_narrow_be(3usize, data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 6;
+            self.stamp
+                .decode_narrow_le(6usize, data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 3;
+            self.channel
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 16;
+            self.hash
+                .decode_be(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let size = 16;
+            self.delta
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let mut tmp_len: u32 = 0;
+            tmp_len.decode_narrow_le(3usize, data.get(..3).ok_or(PError::NotEnoughData)?)?;
+            data = &data[3..];
+            self.frames = Vec::with_capacity(tmp_len as usize);
+            for idx in 0..(tmp_len as usize) {
+                self.frames.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                let size = 1;
+                self.frames[idx].decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            3 + 3 + 6 + 3 + 16 + 16 + (3 + (self.frames.len() * (1)))
+        }
+    }
+    #[derive(Debug)]
+    pub struct Position {
+        pub x: f64,
+        pub y: f64,
+        pub heading: f32,
+        pub track: [f32; 2usize],
+    }
+    impl Default for Position {
+        fn default() -> Self {
+            Self {
+                x: Default::default(),
+                y: Default::default(),
+                heading: Default::default(),
+                track: [Default::default(), Default::default()],
+            }
+        }
+    }
+    impl Pstruct for Position {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            let raw: i32 = self.x.to_fixed(16u32, false)?;
+            raw.encode_le(&mut buf[..4usize])?;
+            buf = &mut buf[4usize..];
+            let raw: i32 = self.y.to_fixed(16u32, false)?;
+            raw.encode_be(&mut buf[..4usize])?;
+            buf = &mut buf[4usize..];
+            let raw: u16 = self.heading.to_fixed(8u32, true)?;
+            raw.encode_le(&mut buf[..2usize])?;
+            buf = &mut buf[2usize..];
+            for idx in 0..self.track.len() {
+                let raw: i8 = self.track[idx].to_fixed(4u32, false)?;
+                raw.encode_le(&mut buf[..1usize])?;
+                buf = &mut buf[1usize..];
+            }
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let size = 4usize;
+            {
+                let mut raw: i32 = 0;
+                raw.decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)
+                    .map(|()| self.x = <f64 as Fixed>::from_fixed(raw, 16u32))
+            }?;
+            data = &data[size..];
+            let size = 4usize;
+            {
+                let mut raw: i32 = 0;
+                raw.decode_be(data.get(..size).ok_or(PError::NotEnoughData)?)
+                    .map(|()| self.y = <f64 as Fixed>::from_fixed(raw, 16u32))
+            }?;
+            data = &data[size..];
+            let size = 2usize;
+            {
+                let mut raw: u16 = 0;
+                raw.decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)
+                    .map(|()| self.heading = <f32 as Fixed>::from_fixed(raw, 8u32))
+            }?;
+            data = &data[size..];
+            for idx in 0..(2usize as usize) {
+                let size = 1usize;
+                {
+                    let mut raw: i8 = 0;
+                    raw.decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)
+                        .map(|()| self.track[idx] = <f32 as Fixed>::from_fixed(raw, 4u32))
+                }?;
+                data = &data[size..];
+            }
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            4usize + 4usize + 2usize + (2usize * (1usize))
+        }
+    }
+    #[derive(Debug)]
+    pub struct Header {
+        pub kind: u16,
+        pub tags: Vec<u8>,
+        pub count: u16,
+    }
+    impl Default for Header {
+        fn default() -> Self {
+            Self {
+                kind: Default::default(),
+                tags: vec![],
+                count: Default::default(),
+            }
+        }
+    }
+    impl Pstruct for Header {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            self.kind.encode_be(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            let tmp_len = <u16 as ::core::convert::TryFrom<usize>>::try_from(self.tags.len())
+                .map_err(|_| PError::OutOfRange)?;
+            tmp_len.encode_be(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            for idx in 0..self.tags.len() {
+                self.tags[idx].encode_be(&mut buf[..1])?;
+                buf = &mut buf[1..];
+            }
+            self.count.encode_le(&mut buf[..2])?;
+            buf = &mut buf[2..];
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            let size = 2;
+            self.kind
+                .decode_be(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            let mut tmp_len: u16 = 0;
+            tmp_len.decode_be(data.get(..2).ok_or(PError::NotEnoughData)?)?;
+            data = &data[2..];
+            self.tags = Vec::with_capacity(tmp_len as usize);
+            for idx in 0..(tmp_len as usize) {
+                self.tags.push(Default::default());
+            }
+            for idx in 0..(tmp_len as usize) {
+                let size = 1;
+                self.tags[idx].decode_be(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+                data = &data[size..];
+            }
+            let size = 2;
+            self.count
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            Ok(())
+        }
+        fn size(&self) -> usize {
+            2 + (2 + (self.tags.len() * (1))) + 2
+        }
+    }
+    #[derive(Debug)]
+    pub struct Waypoint {
+        pub position: Vec3,
+        pub unit: Unit,
+        pub path: [Vec3; 2usize],
+    }
+    impl Default for Waypoint {
+        fn default() -> Self {
+            Self {
+                position: Default::default(),
+                unit: Default::default(),
+                path: [Default::default(), Default::default()],
+            }
+        }
+    }
+    impl Pstruct for Waypoint {
+        fn encode(&self) -> Result<Vec<u8>> {
+            let mut res = vec![0; self.size()];
+            self.encode_buf(&mut res)?;
+            Ok(res)
+        }
+        fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+            assert!(buf.len() >= self.size(), "todo improve errors");
+            let mut buf = buf;
+            self.position.encode_buf(&mut buf[..self.position.size()])?;
+            buf = &mut buf[self.position.size()..];
+            self.unit.encode_le(&mut buf[..1])?;
+            buf = &mut buf[1..];
+            for idx in 0..self.path.len() {
+                self.path[idx].encode_buf(&mut buf[..self.path[idx].size()])?;
+                buf = &mut buf[self.path[idx].size()..];
+            }
+            Ok(())
+        }
+        fn decode_new(data: &[u8]) -> Result<Self> {
+            let mut res = Self::default();
+            res.decode(data)?;
+            Ok(res)
+        }
+        fn decode(&mut self, data: &[u8]) -> Result<()> {
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
+            let mut data = data;
+            self.position.decode(data)?;
+            data = &data[self.position.size()..];
+            let size = 1;
+            self.unit
+                .decode_le(data.get(..size).ok_or(PError::NotEnoughData)?)?;
+            data = &data[size..];
+            for idx in 0..(2usize as usize) {
+                self.path[idx].decode(data)?;
+                data = &data[self.path[idx].size()..];
+            }
             Ok(())
         }
         fn size(&self) -> usize {
-            self.t.len() + 2
+            self.position.size() + 1 + self.path.iter().map(|i| i.size()).sum::<usize>()
         }
     }
 }
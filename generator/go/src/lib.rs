@@ -2,31 +2,44 @@ use pstruct::types::*;
 
 use fomat_macros::{fomat, wite};
 
+use std::collections::BTreeSet;
 use std::fmt;
 
 pub static GENERATED_HEADER: &str = "// AUTOGENERATED - MAY BE OVERWRITTEN";
 
-struct GoType<'a>(&'a Type<'a>);
+struct GoType<'a>(&'a Type);
 struct GoEndian<'a>(&'a Endian);
-struct GoStruct<'a>(&'a Struct<'a>);
+struct GoStruct<'a>(&'a Struct, &'a [Union]);
+struct GoEnum<'a>(&'a Enum);
+struct GoUnion<'a>(&'a Union);
+// expressions over the fields of the receiver named by the second element;
+// arithmetic goes through the ps.Checked variable named by the third one
+struct GoValue<'a>(&'a Expr, &'a str, &'a str);
+struct GoCondition<'a>(&'a Expr, &'a str, &'a str);
 
 impl fmt::Display for GoType<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match &self.0 {
             Type::U8 => "uint8",
             Type::U16 => "uint16",
-            Type::U32 => "uint32",
-            Type::U64 => "uint64",
+            Type::U32 | Type::VarU32 => "uint32",
+            Type::U64 | Type::U48 | Type::VarU64 => "uint64",
+            Type::U24 => "uint32",
+            Type::U128 => "ps.Uint128",
             Type::I8 => "int8",
             Type::I16 => "int16",
-            Type::I32 => "int32",
-            Type::I64 => "int64",
+            Type::I32 | Type::I24 => "int32",
+            Type::I64 | Type::VarI64 => "int64",
+            Type::I128 => "ps.Int128",
             Type::F32 => "float32",
             Type::F64 => "float64",
+            Type::Bool => "bool",
+            Type::Fixed { .. } if self.0.fixed_size() <= Some(2) => "float32",
+            Type::Fixed { .. } => "float64",
             Type::Byte => "byte",
             Type::String => "string",
             Type::CString => "string",
-            Type::User(user_type) => user_type,
+            Type::User(user_type) | Type::Enum(user_type, _) | Type::Union(user_type) => user_type,
         };
         write!(f, "{}", s)
     }
@@ -45,6 +58,93 @@ impl fmt::Display for GoEndian<'_> {
     }
 }
 
+impl fmt::Display for GoValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let GoValue(expr, var_name, checker) = *self;
+        match expr {
+            Expr::Number(value) => write!(f, "{}", value),
+            Expr::Variable(name) => write!(f, "int64({}.{})", var_name, name),
+            Expr::Binary(op, lhs, rhs) if op.is_arithmetic() => {
+                let method = match op {
+                    BinaryOp::Add => "Add",
+                    BinaryOp::Sub => "Sub",
+                    BinaryOp::Mul => "Mul",
+                    BinaryOp::Div => "Div",
+                    _ => "Rem",
+                };
+                write!(
+                    f,
+                    "{}.{}({}, {})",
+                    checker,
+                    method,
+                    GoValue(lhs, var_name, checker),
+                    GoValue(rhs, var_name, checker)
+                )
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let op = match op {
+                    BinaryOp::BitOr => "|",
+                    BinaryOp::BitXor => "^",
+                    BinaryOp::BitAnd => "&",
+                    _ => unreachable!("not an integer expression"),
+                };
+                write!(
+                    f,
+                    "({} {} {})",
+                    GoValue(lhs, var_name, checker),
+                    op,
+                    GoValue(rhs, var_name, checker)
+                )
+            }
+            Expr::Not(_) => unreachable!("not an integer expression"),
+        }
+    }
+}
+
+// integers hold when they're not zero
+impl fmt::Display for GoCondition<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let GoCondition(expr, var_name, checker) = *self;
+        if !expr.is_boolean() {
+            return write!(f, "({} != 0)", GoValue(expr, var_name, checker));
+        }
+        match expr {
+            Expr::Not(operand) => write!(f, "!{}", GoCondition(operand, var_name, checker)),
+            Expr::Binary(op, lhs, rhs) if op.is_logical() => {
+                let op = match op {
+                    BinaryOp::Or => "||",
+                    _ => "&&",
+                };
+                write!(
+                    f,
+                    "({} {} {})",
+                    GoCondition(lhs, var_name, checker),
+                    op,
+                    GoCondition(rhs, var_name, checker)
+                )
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let op = match op {
+                    BinaryOp::Eq => "==",
+                    BinaryOp::Ne => "!=",
+                    BinaryOp::Lt => "<",
+                    BinaryOp::Le => "<=",
+                    BinaryOp::Gt => ">",
+                    _ => ">=",
+                };
+                write!(
+                    f,
+                    "({} {} {})",
+                    GoValue(lhs, var_name, checker),
+                    op,
+                    GoValue(rhs, var_name, checker)
+                )
+            }
+            _ => unreachable!("not a boolean expression"),
+        }
+    }
+}
+
 fn some_kind_of_uppercase_first_letter(s: &str) -> String {
     let mut c = s.chars(); // TODO something better than this
     match c.next() {
@@ -59,6 +159,10 @@ fn alt(ty: &Type) -> String {
             Type::Byte => {
                 [Type::U8]
             }
+            // named after the float it's held in
+            Type::Fixed { .. } => {
+                "Fixed"(&GoType(ty).to_string()["float".len()..])
+            }
             _ => {
                 [ty]
             }
@@ -86,38 +190,116 @@ fn type_read_border(ty: &Type, endian: Endian) -> String {
 fn is_multibyte(ty: &Type) -> bool {
     !matches!(
         ty,
-        Type::Byte | Type::U8 | Type::I8 | Type::CString | Type::User(_)
+        Type::Byte
+            | Type::U8
+            | Type::I8
+            | Type::Bool
+            | Type::VarU32
+            | Type::VarU64
+            | Type::VarI64
+            | Type::CString
+            | Type::User(_)
+            | Type::Union(_)
     )
 }
 
+// the runtime takes a size of 0 to mean an unsigned varint
+fn prefix_size(ty: &Type) -> usize {
+    ty.fixed_size().unwrap_or(0)
+}
+
 fn item_write_border(item: &Item) -> String {
     match item.kind {
-        Type::Byte | Type::U8 | Type::I8 => String::new(),
+        Type::Byte | Type::U8 | Type::I8 | Type::Bool => String::new(),
+        Type::VarU32 | Type::VarU64 | Type::VarI64 => String::new(),
+        Type::Fixed {
+            signed, frac_bits, ..
+        } => fomat!(
+            ", " (item.kind.fixed_size().unwrap()) ", " (frac_bits) ", " (signed) ", " (item.saturate)
+            ", " (GoEndian(&item.byte_order))
+        ),
         _ => fomat!(", "(GoEndian(&item.byte_order))),
     }
 }
 
 fn item_read_border(item: &Item) -> String {
     match item.kind {
-        Type::Byte | Type::U8 | Type::I8 => String::new(),
+        Type::Byte | Type::U8 | Type::I8 | Type::Bool => String::new(),
+        Type::VarU32 | Type::VarU64 | Type::VarI64 => String::new(),
+        Type::Fixed {
+            signed, frac_bits, ..
+        } => fomat!(
+            (item.kind.fixed_size().unwrap()) ", " (frac_bits) ", " (signed) ", " (GoEndian(&item.byte_order))
+        ),
         _ => fomat!((GoEndian(&item.byte_order))),
     }
 }
 
+fn go_field_type(item: &Item) -> String {
+    if let Some(type_alias) = &item.type_alias {
+        return type_alias.to_owned();
+    }
+    match &item.array {
+        Some(Array::Nested(..)) => fomat!("[]"(go_field_type(&item.row().unwrap()))),
+        Some(_) => {
+            if item.kind == Type::CString {
+                fomat!((GoType(&item.kind)))
+            } else {
+                fomat!("[]"(GoType(&item.kind)))
+            }
+        }
+        None => fomat!((GoType(&item.kind))),
+    } // TODO do something better?
+}
+
+// in the same package the included types are already visible, others need an import
+fn foreign_includes(file: &File) -> impl Iterator<Item = &Include> {
+    let scope = file.scope.to_lowercase();
+    file.includes
+        .iter()
+        .filter(move |include| include.scope.to_lowercase() != scope)
+}
+
+/// Checks that `render_file` can import every included file from another scope, which
+/// needs the import path given by the `go_import` option of that file
+pub fn check_file(file: &File) -> Result<(), String> {
+    match foreign_includes(file).find(|include| include.go_import.is_none()) {
+        Some(include) => Err(format!(
+            "{} is in scope {}, so it needs a go_import option to be included from Go",
+            include.path, include.scope
+        )),
+        None => Ok(()),
+    }
+}
+
 fn file_uses_byte_order(file: &File) -> bool {
+    // enums always take the byte order as a parameter
+    if !file.enums.is_empty() {
+        return true;
+    }
     for s in file.structs.iter() {
         for item in s.items.iter() {
+            // padding is written as raw zero bytes
+            if item.storage == Storage::Padding {
+                continue;
+            }
             if is_multibyte(&item.kind) {
                 return true;
             }
-            if let Some(arr) = &item.array {
-                match arr {
-                    Array::Variable(_, arr_kind) | Array::Unknown(arr_kind) => {
-                        if is_multibyte(arr_kind) {
-                            return true;
-                        }
+            // the runtime takes the byte order for any cstring prefix
+            if item.kind == Type::CString
+                && matches!(
+                    item.array.as_ref().map(Array::innermost),
+                    Some(Array::Unknown(_))
+                )
+            {
+                return true;
+            }
+            for dim in item.array.iter().flat_map(Array::dimensions) {
+                if let Array::Variable(_, arr_kind) | Array::Unknown(arr_kind) = dim {
+                    if is_multibyte(arr_kind) {
+                        return true;
                     }
-                    _ => {}
                 }
             }
         }
@@ -125,19 +307,297 @@ fn file_uses_byte_order(file: &File) -> bool {
     false
 }
 
-fn render_encode_item(item: &Item, var_name: &str) -> String {
+fn find_union<'a>(unions: &'a [Union], ty: &Type) -> &'a Union {
+    match ty {
+        Type::Union(name) => unions.iter().find(|u| u.name == *name).unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+fn bit_mask(width: u32) -> u128 {
+    (1 << width) - 1
+}
+
+fn local_name(item: &Item, var_name: &str, suffix: &str) -> String {
+    fomat!((var_name)(some_kind_of_uppercase_first_letter(&item.name))(
+        suffix
+    ))
+}
+
+// evaluates the condition of optional items up front when it needs checking,
+// returning the code for that along with the condition to test
+fn render_condition(item: &Item, condition: &Expr, var_name: &str) -> (String, String) {
+    if !condition.is_checked() {
+        return (
+            String::new(),
+            fomat!((GoCondition(condition, var_name, ""))),
+        );
+    }
+    let checker = local_name(item, var_name, "Check");
+    let present = local_name(item, var_name, "Present");
+    let prelude = fomat!(
+        "\t" "var " (checker) " ps.Checked" "\n"
+        "\t" (present) " := " (GoCondition(condition, var_name, &checker)) "\n"
+        "\t" "if " (checker) ".Overflow {" "\n"
+        "\t\t" "return ps.ErrOutOfRange" "\n"
+        "\t" "}" "\n"
+    );
+    (prelude, present)
+}
+
+// length of arrays sized by earlier fields, computed by render_array_len for expressions
+fn go_array_len(item: &Item, var_name: &str) -> String {
+    match &item.array {
+        Some(Array::Variable(size_name, _)) => fomat!((var_name) "." (size_name)),
+        Some(Array::Expression(_)) => local_name(item, var_name, "Len"),
+        Some(Array::Derived(name)) => length_local(name, var_name),
+        _ => unreachable!("not an array sized by earlier fields"),
+    }
+}
+
+// local holding the value of length_of items, read before the array they size
+fn length_local(name: &str, var_name: &str) -> String {
+    fomat!((var_name)(some_kind_of_uppercase_first_letter(name)))
+}
+
+// length written for arrays sized by a length_of item; cstrings count their null byte
+fn derived_len(item: &Item, field: &str) -> String {
+    if item.kind == Type::CString {
+        fomat!("len(" (field) ") + 1")
+    } else {
+        fomat!("len(" (field) ")")
+    }
+}
+
+fn render_array_len(item: &Item, var_name: &str) -> String {
+    match &item.array {
+        Some(Array::Expression(expr)) => {
+            let checker = local_name(item, var_name, "Check");
+            let len = local_name(item, var_name, "Len");
+            fomat!(
+                "\t" "var " (checker) " ps.Checked" "\n"
+                "\t" (len) " := " (GoValue(expr, var_name, &checker)) "\n"
+                "\t" "if " (checker) ".Overflow || " (len) " < 0 {" "\n"
+                "\t\t" "return ps.ErrOutOfRange" "\n"
+                "\t" "}" "\n"
+            )
+        }
+        _ => String::new(),
+    }
+}
+
+// strings are either prefixed with their length or padded to a fixed width
+fn render_string_write(item: &Item, value: &str) -> String {
+    match &item.string_format {
+        StringFormat::Prefixed(ty) => fomat!(
+            "stream.WriteStringPrefixed(" (value) ", " (prefix_size(ty)) ", " (GoEndian(&item.byte_order)) ")"
+        ),
+        StringFormat::Fixed(width) => fomat!("stream.WriteStringFixed(" (value) ", " (width) ")"),
+    }
+}
+
+fn render_string_read(item: &Item) -> String {
+    match &item.string_format {
+        StringFormat::Prefixed(ty) => fomat!(
+            "stream.ReadStringPrefixed(" (prefix_size(ty)) ", " (GoEndian(&item.byte_order)) ")"
+        ),
+        StringFormat::Fixed(width) => fomat!("stream.ReadStringFixed(" (width) ")"),
+    }
+}
+
+// rejects lengths over the item's max_array_size, before anything gets allocated
+fn render_bounds_check(item: &Item, len: &str) -> String {
+    match item.max_array_size {
+        Some(max) => fomat!(
+            "\t" "if uint64(" (len) ") > " (max) " {" "\n"
+            "\t\t" "return ps.ErrArrayTooLarge" "\n"
+            "\t" "}" "\n"
+        ),
+        None => String::new(),
+    }
+}
+
+// arrays sized by earlier fields are written with that many elements, so the slice must
+// have exactly that many
+fn render_len_check(field: &str, len: &str) -> String {
+    fomat!(
+        "\t" "if len(" (field) ") != int(" (len) ") {" "\n"
+        "\t\t" "return ps.ErrLengthMismatch" "\n"
+        "\t" "}" "\n"
+    )
+}
+
+// loop index over the rows of multi-dimensional arrays, distinct for every dimension
+fn row_index(row: &Item) -> String {
+    let depth = row.array.as_ref().map_or(0, |arr| arr.dimensions().len());
+    fomat!("idx"(depth))
+}
+
+// number of rows in multi-dimensional arrays, `outer` being the item with only the outer dimension
+fn row_count(outer: &Item, var_name: &str, field: &str) -> String {
+    match &outer.array {
+        Some(Array::Constant(size)) => size.to_string(),
+        Some(Array::Variable(..) | Array::Expression(_)) => go_array_len(outer, var_name),
+        _ => fomat!("len(" (field) ")"),
+    }
+}
+
+// multi-dimensional arrays are encoded one row at a time, each being an array itself
+fn render_encode_rows(item: &Item, unions: &[Union], var_name: &str, field: &str) -> String {
+    let row = item.row().unwrap();
+    let idx = row_index(&row);
+    let outer = match &item.array {
+        Some(Array::Nested(outer, _)) => Item {
+            array: Some((**outer).clone()),
+            ..item.clone()
+        },
+        _ => unreachable!("not a multi-dimensional array"),
+    };
+    fomat!(
+        (render_array_len(&outer, var_name))
+        match &outer.array {
+            Some(Array::Variable(..) | Array::Expression(_)) => {
+                (render_bounds_check(item, &go_array_len(&outer, var_name)))
+                (render_len_check(field, &go_array_len(&outer, var_name)))
+            }
+            Some(Array::Unknown(arr_kind)) => {
+                (render_bounds_check(item, &fomat!("len(" (field) ")")))
+                // the conversion only keeps the value when it fits
+                "\t" "if int(" (GoType(arr_kind)) "(len(" (field) "))) != len(" (field) ") {" "\n"
+                "\t\t" "return ps.ErrOutOfRange" "\n"
+                "\t" "}" "\n"
+                "\t" "err = stream.Write" (alt(arr_kind)) "(" (GoType(arr_kind)) "(len(" (field) "))" (type_write_border(arr_kind, item.byte_order)) ")" "\n"
+                "\t" "if err != nil {" "\n"
+                "\t\t" "return err" "\n"
+                "\t" "}" "\n"
+            }
+            _ => {}
+        }
+        "\t" "for " (idx) " := 0; " (idx) " < int(" (row_count(&outer, var_name, field)) "); " (idx) "++ {" "\n"
+        (render_encode_field(&row, unions, var_name, &fomat!((field) "[" (idx) "]")))
+        "\t" "}" "\n"
+    )
+}
+
+fn render_encode_item(item: &Item, items: &[Item], unions: &[Union], var_name: &str) -> String {
+    let item_kind = alt(&item.kind);
+    match &item.storage {
+        Storage::Field => {}
+        Storage::Padding => {
+            return fomat!(
+                "\t" "err = stream.WriteBytes(make([]byte, " (item.fixed_size().unwrap()) "))" "\n"
+                "\t" "if err != nil {" "\n"
+                "\t\t" "return err" "\n"
+                "\t" "}" "\n"
+            );
+        }
+        Storage::Magic(value) => {
+            return fomat!(
+                "\t" "err = stream.Write" (item_kind) "(" (GoType(&item.kind)) "(" (value) ")" (item_write_border(item)) ")" "\n"
+                "\t" "if err != nil {" "\n"
+                "\t\t" "return err" "\n"
+                "\t" "}" "\n"
+            );
+        }
+        Storage::Bitfield => {
+            let total_width = item.fixed_size().unwrap() * 8;
+            return fomat!(
+                "\t" "{" "\n"
+                "\t\t" "var bits " (GoType(&item.kind)) "\n"
+                for bitfield in &item.bits {
+                    // a field spanning the whole container always fits
+                    if (bitfield.width as usize) < total_width {
+                        "\t\t" "if " (var_name) "." (bitfield.name) " > " (bit_mask(bitfield.width)) " {" "\n"
+                        "\t\t\t" "return ps.ErrOutOfRange" "\n"
+                        "\t\t" "}" "\n"
+                    }
+                    "\t\t" "bits |= " (var_name) "." (bitfield.name) " << " (bitfield.shift) "\n"
+                }
+                "\t\t" "err = stream.Write" (item_kind) "(bits" (item_write_border(item)) ")" "\n"
+                "\t\t" "if err != nil {" "\n"
+                "\t\t\t" "return err" "\n"
+                "\t\t" "}" "\n"
+                "\t" "}" "\n"
+            );
+        }
+        Storage::LengthOf(target) => {
+            let target = items.iter().find(|i| i.name == *target).unwrap();
+            let len = go_array_len(target, var_name);
+            // the conversion only keeps the value when it fits
+            return fomat!(
+                "\t" (len) " := " (derived_len(target, &fomat!((var_name) "." (target.name)))) "\n"
+                "\t" "if int(" (GoType(&item.kind)) "(" (len) ")) != " (len) " {" "\n"
+                "\t\t" "return ps.ErrOutOfRange" "\n"
+                "\t" "}" "\n"
+                "\t" "err = stream.Write" (item_kind) "(" (GoType(&item.kind)) "(" (len) ")" (item_write_border(item)) ")" "\n"
+                "\t" "if err != nil {" "\n"
+                "\t\t" "return err" "\n"
+                "\t" "}" "\n"
+            );
+        }
+    }
+    let field = fomat!((var_name) "." (item.name));
+    match &item.condition {
+        None => render_encode_field(item, unions, var_name, &field),
+        Some(condition) => {
+            let (prelude, condition) = render_condition(item, condition, var_name);
+            fomat!(
+            (prelude)
+            "\t" "if " (condition) " {" "\n"
+            "\t\t" "if " (field) " == nil {" "\n"
+            "\t\t\t" "return ps.ErrMissingField" "\n"
+            "\t\t" "}" "\n"
+            (render_encode_field(item, unions, var_name, &fomat!("(*" (field) ")")))
+            "\t" "}" "\n"
+            )
+        }
+    }
+}
+
+// encodes `field`, which is dereferenced for optional items
+fn render_encode_field(item: &Item, unions: &[Union], var_name: &str, field: &str) -> String {
+    if let Some(Array::Nested(..)) = item.array {
+        return render_encode_rows(item, unions, var_name, field);
+    }
     let item_kind = alt(&item.kind);
     if let Some(arr) = &item.array {
         let emit_for_loop = !matches!(item.kind, Type::CString | Type::Byte | Type::U8);
+        let count_bytes = item.prefix_mode == PrefixMode::Bytes;
         fomat!(
+            (render_array_len(item, var_name))
             match &arr {
-                Array::Unknown(arr_kind) => {
+                // rows of multi-dimensional arrays never get here
+                Array::Constant(_) | Array::Nested(..) => {}
+                Array::Variable(..) | Array::Expression(_) => {
+                    (render_bounds_check(item, &go_array_len(item, var_name)))
+                    // cstrings are padded or cut to the length instead
+                    if item.kind != Type::CString {
+                        (render_len_check(field, &go_array_len(item, var_name)))
+                    }
+                }
+                Array::Unknown(_) | Array::Derived(_) | Array::Rest => {
                     if item.kind == Type::CString {
-                        // don't forget + 1 for null byte in unsized cstrings
-                        "\t" "err = stream.Write" (alt(arr_kind)) "(" (GoType(arr_kind)) "(len(" (var_name) "." (item.name) ") + 1)" (type_write_border(arr_kind, item.byte_order)) ")" "\n"
+                        (render_bounds_check(item, &fomat!("len(" (field) ") + 1")))
                     } else {
-                        "\t" "err = stream.Write" (alt(arr_kind)) "(" (GoType(arr_kind)) "(len(" (var_name) "." (item.name) "))" (type_write_border(arr_kind, item.byte_order)) ")" "\n"
+                        (render_bounds_check(item, &fomat!("len(" (field) ")")))
                     }
+                }
+            }
+            match &arr {
+                Array::Unknown(_) if count_bytes => {
+                    // elements go to a buffer first, which gives the size to write
+                    "\t" "{" "\n"
+                    "\t" "outer := stream" "\n"
+                    "\t" "var elems bytes.Buffer" "\n"
+                    "\t" "stream := ps.NewStreamWithWriter(&elems)" "\n"
+                }
+                // unsized cstrings write their own prefix
+                Array::Unknown(arr_kind) if item.kind != Type::CString => {
+                    // the conversion only keeps the value when it fits
+                    "\t" "if int(" (GoType(arr_kind)) "(len(" (field) "))) != len(" (field) ") {" "\n"
+                    "\t\t" "return ps.ErrOutOfRange" "\n"
+                    "\t" "}" "\n"
+                    "\t" "err = stream.Write" (alt(arr_kind)) "(" (GoType(arr_kind)) "(len(" (field) "))" (type_write_border(arr_kind, item.byte_order)) ")" "\n"
                     "\t" "if err != nil {" "\n"
                     "\t\t" "return err" "\n"
                     "\t" "}" "\n"
@@ -148,8 +608,8 @@ fn render_encode_item(item: &Item, var_name: &str) -> String {
                 "\t" "for idx := 0; idx < int("
                     match &arr {
                         Array::Constant(size)         => { (size) }
-                        Array::Unknown(_)             => { "len("(var_name)"."(item.name)")" }
-                        Array::Variable(size_name, _) => { (var_name)"."(size_name) }
+                        Array::Unknown(_) | Array::Derived(_) | Array::Rest | Array::Nested(..) => { "len("(field)")" }
+                        Array::Variable(..) | Array::Expression(_) => { (go_array_len(item, var_name)) }
                     }
                     "); idx++ {" "\n"
             }
@@ -158,27 +618,38 @@ fn render_encode_item(item: &Item, var_name: &str) -> String {
                 Type::CString => {
                     match &arr {
                         Array::Constant(size) => {
-                            "\t" "err = stream.WriteCString(" (var_name) "." (item.name) ", " (size) ")" "\n"
+                            "\t" "err = stream.WriteCString(" (field) ", " (size) ")" "\n"
                         }
-                        Array::Variable(size_name, _) => {
-                            "\t" "err = stream.WriteCString(" (var_name) "." (item.name) ", int(" (var_name) "." (size_name) "))" "\n"
+                        Array::Variable(..) | Array::Expression(_) => {
+                            "\t" "err = stream.WriteCString(" (field) ", int(" (go_array_len(item, var_name)) "))" "\n"
                         }
-                        Array::Unknown(_) => {
+                        Array::Unknown(arr_kind) => {
+                            "\t" "err = stream.WriteCStringPrefixed(" (field) ", " (prefix_size(arr_kind)) ", " (GoEndian(&item.byte_order)) ")" "\n"
+                        }
+                        Array::Derived(_) => {
                             // don't forget + 1 for null byte
-                            "\t" "err = stream.WriteCString(" (var_name) "." (item.name) ", int(len(" (var_name) "." (item.name) ") + 1))" "\n"
+                            "\t" "err = stream.WriteCString(" (field) ", int(len(" (field) ") + 1))" "\n"
                         }
+                        // only byte arrays can hold the rest of the input
+                        Array::Rest | Array::Nested(..) => {}
                     }
                 }
                 Type::Byte | Type::U8 => {
-                    "\t" "err = stream.WriteBytes(" (var_name) "." (item.name) ")" "\n"
+                    "\t" "err = stream.WriteBytes(" (field) ")" "\n"
                 }
 
                 // regular arrays, inside for loop:
                 Type::User(_) => {
-                    "\t\t" "err = " (var_name) "." (item.name) "[idx].EncodeStream(stream)" "\n"
+                    "\t\t" "err = " (field) "[idx].EncodeStream(stream)" "\n"
+                }
+                Type::Enum(..) => {
+                    "\t\t" "err = " (field) "[idx].Write(stream, " (GoEndian(&item.byte_order)) ")" "\n"
+                }
+                Type::String => {
+                    "\t\t" "err = " (render_string_write(item, &fomat!((field) "[idx]"))) "\n"
                 }
                 _ => {
-                    "\t\t" "err = stream.Write" (item_kind) "(" (var_name) "." (item.name) "[idx]" (item_write_border(item)) ")" "\n"
+                    "\t\t" "err = stream.Write" (item_kind) "(" (field) "[idx]" (item_write_border(item)) ")" "\n"
                 }
             }
             "\t\t" "if err != nil {" "\n"
@@ -187,19 +658,56 @@ fn render_encode_item(item: &Item, var_name: &str) -> String {
             if emit_for_loop {
                 "\t" "}" "\n"
             }
+            match &arr {
+                Array::Unknown(arr_kind) if count_bytes => {
+                    // the conversion only keeps the value when it fits
+                    "\t" "if int(" (GoType(arr_kind)) "(elems.Len())) != elems.Len() {" "\n"
+                    "\t\t" "return ps.ErrOutOfRange" "\n"
+                    "\t" "}" "\n"
+                    "\t" "err = outer.Write" (alt(arr_kind)) "(" (GoType(arr_kind)) "(elems.Len())" (type_write_border(arr_kind, item.byte_order)) ")" "\n"
+                    "\t" "if err != nil {" "\n"
+                    "\t\t" "return err" "\n"
+                    "\t" "}" "\n"
+                    "\t" "err = outer.WriteBytes(elems.Bytes())" "\n"
+                    "\t" "if err != nil {" "\n"
+                    "\t\t" "return err" "\n"
+                    "\t" "}" "\n"
+                    "\t" "}" "\n"
+                }
+                _ => {}
+            }
         )
     } else {
         // not array
         fomat!(
             match &item.kind {
                 Type::User(_) => {
-                    "\t" "err = " (var_name) "." (item.name) ".EncodeStream(stream)" "\n"
+                    "\t" "err = " (field) ".EncodeStream(stream)" "\n"
+                }
+                Type::Enum(..) => {
+                    "\t" "err = " (field) ".Write(stream, " (GoEndian(&item.byte_order)) ")" "\n"
+                }
+                Type::Union(_) => {
+                    "\t" "switch v := " (field) ".(type) {" "\n"
+                    for case in &find_union(unions, &item.kind).cases {
+                        "\t" "case *" (case.kind) ":" "\n"
+                        "\t\t" "if " (var_name) "." (item.tag.as_ref().unwrap()) " != " (case.value) " {" "\n"
+                        "\t\t\t" "return ps.ErrTagMismatch" "\n"
+                        "\t\t" "}" "\n"
+                        "\t\t" "err = v.EncodeStream(stream)" "\n"
+                    }
+                    "\t" "default:" "\n"
+                    "\t\t" "return ps.ErrUnknownVariant" "\n"
+                    "\t" "}" "\n"
                 }
                 Type::CString => {
-                    "\t" "err = stream.WriteCStringUnsized(" (var_name) "." (item.name) ")" "\n"
+                    "\t" "err = stream.WriteCStringUnsized(" (field) ")" "\n"
+                }
+                Type::String => {
+                    "\t" "err = " (render_string_write(item, field)) "\n"
                 }
                 _ => {
-                    "\t" "err = stream.Write" (item_kind) "(" (var_name) "." (item.name) (item_write_border(item)) ")" "\n"
+                    "\t" "err = stream.Write" (item_kind) "(" (field) (item_write_border(item)) ")" "\n"
                 }
             }
             "\t" "if err != nil {" "\n"
@@ -209,76 +717,224 @@ fn render_encode_item(item: &Item, var_name: &str) -> String {
     }
 }
 
-fn render_decode_item(item: &Item, var_name: &str) -> String {
+fn render_decode_rows(item: &Item, unions: &[Union], var_name: &str, field: &str) -> String {
+    let row = item.row().unwrap();
+    let idx = row_index(&row);
+    let outer = match &item.array {
+        Some(Array::Nested(outer, _)) => Item {
+            array: Some((**outer).clone()),
+            ..item.clone()
+        },
+        _ => unreachable!("not a multi-dimensional array"),
+    };
+    let size = local_name(item, var_name, "Size");
+    fomat!(
+        (render_array_len(&outer, var_name))
+        match &outer.array {
+            Some(Array::Variable(..) | Array::Expression(_)) => {
+                (render_bounds_check(item, &go_array_len(&outer, var_name)))
+                "\t" (field) " = make([]" (go_field_type(&row)) ", " (go_array_len(&outer, var_name)) ")" "\n"
+            }
+            Some(Array::Unknown(arr_kind)) => {
+                "\t" (size) ", err := stream.Read" (alt(arr_kind)) "(" (type_read_border(arr_kind, item.byte_order)) ")" "\n"
+                "\t" "if err != nil {" "\n"
+                "\t\t" "return err" "\n"
+                "\t" "}" "\n"
+                (render_bounds_check(item, &size))
+                "\t" (field) " = make([]" (go_field_type(&row)) ", " (size) ")" "\n"
+            }
+            Some(Array::Constant(count)) => {
+                "\t" (field) " = make([]" (go_field_type(&row)) ", " (count) ")" "\n"
+            }
+            _ => {}
+        }
+        "\t" "for " (idx) " := 0; " (idx) " < len(" (field) "); " (idx) "++ {" "\n"
+        (render_decode_field(&row, unions, var_name, &fomat!((field) "[" (idx) "]")))
+        "\t" "}" "\n"
+    )
+}
+
+fn render_decode_item(item: &Item, unions: &[Union], var_name: &str) -> String {
     let item_kind = alt(&item.kind);
+    match &item.storage {
+        Storage::Field => {}
+        Storage::Padding => {
+            return fomat!(
+                "\t" "_, err = stream.ReadBytes(" (item.fixed_size().unwrap()) ")" "\n"
+                "\t" "if err != nil {" "\n"
+                "\t\t" "return err" "\n"
+                "\t" "}" "\n"
+            );
+        }
+        Storage::Magic(value) => {
+            // scoped so several magic items don't redeclare the variable
+            return fomat!(
+                "\t" "{" "\n"
+                "\t\t" "magic, err := stream.Read" (item_kind) "(" (item_read_border(item)) ")" "\n"
+                "\t\t" "if err != nil {" "\n"
+                "\t\t\t" "return err" "\n"
+                "\t\t" "}" "\n"
+                "\t\t" "if magic != " (value) " {" "\n"
+                "\t\t\t" "return ps.ErrBadMagic" "\n"
+                "\t\t" "}" "\n"
+                "\t" "}" "\n"
+            );
+        }
+        Storage::Bitfield => {
+            return fomat!(
+                "\t" "{" "\n"
+                "\t\t" "bits, err := stream.Read" (item_kind) "(" (item_read_border(item)) ")" "\n"
+                "\t\t" "if err != nil {" "\n"
+                "\t\t\t" "return err" "\n"
+                "\t\t" "}" "\n"
+                for bitfield in &item.bits {
+                    "\t\t" (var_name) "." (bitfield.name) " = (bits >> " (bitfield.shift) ") & " (bit_mask(bitfield.width)) "\n"
+                }
+                "\t" "}" "\n"
+            );
+        }
+        Storage::LengthOf(_) => {
+            return fomat!(
+                "\t" (length_local(&item.name, var_name)) ", err := stream.Read" (item_kind) "(" (item_read_border(item)) ")" "\n"
+                "\t" "if err != nil {" "\n"
+                "\t\t" "return err" "\n"
+                "\t" "}" "\n"
+            );
+        }
+    }
+    let field = fomat!((var_name) "." (item.name));
+    match &item.condition {
+        None => render_decode_field(item, unions, var_name, &field),
+        Some(condition) => {
+            let (prelude, condition) = render_condition(item, condition, var_name);
+            fomat!(
+            "\t" (field) " = nil" "\n"
+            (prelude)
+            "\t" "if " (condition) " {" "\n"
+            "\t\t" (field) " = new(" (go_field_type(item)) ")" "\n"
+            (render_decode_field(item, unions, var_name, &fomat!("(*" (field) ")")))
+            "\t" "}" "\n"
+            )
+        }
+    }
+}
+
+// decodes into `field`, which is dereferenced for optional items
+fn render_decode_field(item: &Item, unions: &[Union], var_name: &str, field: &str) -> String {
+    if let Some(Array::Nested(..)) = item.array {
+        return render_decode_rows(item, unions, var_name, field);
+    }
+    let mut item_kind = alt(&item.kind);
+    if item.kind == Type::Bool && item.strict {
+        item_kind.push_str("Strict");
+    }
     if let Some(arr) = &item.array {
         let emit_for_loop = !matches!(item.kind, Type::CString | Type::Byte | Type::U8);
+        let count_bytes = item.prefix_mode == PrefixMode::Bytes;
         fomat!(
+            (render_array_len(item, var_name))
             match &arr {
                 Array::Constant(size) => {
                     if emit_for_loop {
-                        "\t" (var_name) "." (item.name) " = make([]" (GoType(&item.kind)) ", " (size) ")" "\n"
+                        "\t" (field) " = make([]" (GoType(&item.kind)) ", " (size) ")" "\n"
                     }
                 }
-                Array::Variable(size_name, _) => {
+                // only byte arrays can hold the rest of the input, and its size is only known after reading
+                Array::Rest => {}
+                // rows of multi-dimensional arrays never get here
+                Array::Nested(..) => {}
+                Array::Variable(..) | Array::Expression(_) | Array::Derived(_) => {
+                    (render_bounds_check(item, &go_array_len(item, var_name)))
                     if emit_for_loop {
-                        "\t" (var_name) "." (item.name) " = make([]" (GoType(&item.kind)) ", " (var_name) "." (size_name) ")" "\n"
+                        "\t" (field) " = make([]" (GoType(&item.kind)) ", " (go_array_len(item, var_name)) ")" "\n"
                     }
                 }
                 Array::Unknown(arr_kind) => {
-                    "\t" (var_name) (some_kind_of_uppercase_first_letter(item.name)) "Size, err := stream.Read" (alt(arr_kind)) "(" (type_read_border(arr_kind, item.byte_order)) ")" "\n"
+                    "\t" (var_name) (some_kind_of_uppercase_first_letter(&item.name)) "Size, err := stream.Read" (alt(arr_kind)) "(" (type_read_border(arr_kind, item.byte_order)) ")" "\n"
                     "\t" "if err != nil {" "\n"
                     "\t\t" "return err" "\n"
                     "\t" "}" "\n"
-                    if emit_for_loop {
-                        "\t" (var_name) "." (item.name) " = make([]" (GoType(&item.kind)) ", " (var_name) (some_kind_of_uppercase_first_letter(item.name)) "Size)" "\n"
+                    if count_bytes {
+                        // elements are read until the limited stream runs out
+                        "\t" "{" "\n"
+                        "\t" "stream := stream.Limit(int64(" (var_name) (some_kind_of_uppercase_first_letter(&item.name)) "Size))" "\n"
+                        "\t" (field) " = nil" "\n"
+                    } else {
+                        (render_bounds_check(item, &fomat!((var_name) (some_kind_of_uppercase_first_letter(&item.name)) "Size")))
+                        if emit_for_loop {
+                            "\t" (field) " = make([]" (GoType(&item.kind)) ", " (var_name) (some_kind_of_uppercase_first_letter(&item.name)) "Size)" "\n"
+                        }
                     }
                 }
             }
-            if emit_for_loop {
-                "\t" "for idx := 0; idx < int("
-                    match &arr {
-                        Array::Constant(size)        => { (size) }
-                        Array::Unknown(_)            => { (var_name) (some_kind_of_uppercase_first_letter(item.name)) "Size" }
-                        Array::Variable(size_name,_) => { (var_name) "." (size_name) }
-                    }
-                    "); idx++ {" "\n"
+            match (count_bytes, emit_for_loop) {
+                (true, _) => {
+                    "\t" "for idx := 0; stream.Remaining() > 0; idx++ {" "\n"
+                    "\t\t" (field) " = append(" (field) ", *new(" (GoType(&item.kind)) "))" "\n"
+                    (render_bounds_check(item, "idx + 1"))
+                }
+                (false, true) => {
+                    "\t" "for idx := 0; idx < int("
+                        match &arr {
+                            Array::Constant(size)        => { (size) }
+                            Array::Unknown(_)            => { (var_name) (some_kind_of_uppercase_first_letter(&item.name)) "Size" }
+                            Array::Variable(..) | Array::Expression(_) | Array::Derived(_) => { (go_array_len(item, var_name)) }
+                            Array::Rest | Array::Nested(..) => {}
+                        }
+                        "); idx++ {" "\n"
+                }
+                (false, false) => {}
             }
             match &item.kind {
                 // these types have specialized array functions
                 Type::CString => {
                     match &arr {
                         Array::Constant(size) => {
-                            "\t" (var_name) "." (item.name) ", err = stream.ReadCString(" (size) ")" "\n"
+                            "\t" (field) ", err = stream.ReadCString(" (size) ")" "\n"
                         }
-                        Array::Variable(size_name, _) => {
-                            "\t" (var_name) "." (item.name) ", err = stream.ReadCString(int(" (var_name) "." (size_name) "))" "\n"
+                        Array::Variable(..) | Array::Expression(_) | Array::Derived(_) => {
+                            "\t" (field) ", err = stream.ReadCString(int(" (go_array_len(item, var_name)) "))" "\n"
                         }
                         Array::Unknown(_) => {
-                            "\t" (var_name) "." (item.name) ", err = stream.ReadCString(int(" (var_name) (some_kind_of_uppercase_first_letter(item.name)) "Size))" "\n"
+                            "\t" (field) ", err = stream.ReadCString(int(" (var_name) (some_kind_of_uppercase_first_letter(&item.name)) "Size))" "\n"
                         }
+                        Array::Rest | Array::Nested(..) => {}
                     }
                 }
                 Type::Byte | Type::U8 => {
                     match &arr {
                         Array::Constant(size) => {
-                            "\t" (var_name) "." (item.name) ", err = stream.ReadBytes(" (size) ")" "\n"
+                            "\t" (field) ", err = stream.ReadBytes(" (size) ")" "\n"
                         }
-                        Array::Variable(size_name, _) => {
-                            "\t" (var_name) "." (item.name) ", err = stream.ReadBytes(int(" (var_name) "." (size_name) "))" "\n"
+                        Array::Variable(..) | Array::Expression(_) | Array::Derived(_) => {
+                            "\t" (field) ", err = stream.ReadBytes(int(" (go_array_len(item, var_name)) "))" "\n"
                         }
                         Array::Unknown(_) => {
-                            "\t" (var_name) "." (item.name) ", err = stream.ReadBytes(int(" (var_name) (some_kind_of_uppercase_first_letter(item.name)) "Size))" "\n"
+                            "\t" (field) ", err = stream.ReadBytes(int(" (var_name) (some_kind_of_uppercase_first_letter(&item.name)) "Size))" "\n"
+                        }
+                        Array::Rest => {
+                            "\t" (field) ", err = stream.ReadRest()" "\n"
+                            "\t" "if err != nil {" "\n"
+                            "\t\t" "return err" "\n"
+                            "\t" "}" "\n"
+                            (render_bounds_check(item, &fomat!("len(" (field) ")")))
                         }
+                        Array::Nested(..) => {}
                     }
                 }
 
                 // regular arrays, inside for loop:
                 Type::User(_) => {
-                    "\t\terr = " (var_name) "." (item.name) "[idx].DecodeStream(stream)\n"
+                    "\t\terr = " (field) "[idx].DecodeStream(stream)\n"
+                }
+                Type::Enum(..) => {
+                    "\t\terr = " (field) "[idx].Read(stream, " (GoEndian(&item.byte_order)) ")\n"
+                }
+                Type::String => {
+                    "\t\t" (field) "[idx], err = " (render_string_read(item)) "\n"
                 }
                 _ => {
-                    "\t\t" (var_name) "." (item.name) "[idx], err = stream.Read" (item_kind) "(" (item_read_border(item)) ")\n"
+                    "\t\t" (field) "[idx], err = stream.Read" (item_kind) "(" (item_read_border(item)) ")\n"
                 }
             }
             "\t\t" "if err != nil {" "\n"
@@ -287,19 +943,40 @@ fn render_decode_item(item: &Item, var_name: &str) -> String {
             if emit_for_loop {
                 "\t" "}" "\n"
             }
+            if count_bytes {
+                "\t" "}" "\n"
+            }
         )
     } else {
         // not array
         fomat!(
             match &item.kind {
                 Type::User(_) => {
-                    "\t" "err = " (var_name) "." (item.name) ".DecodeStream(stream)" "\n"
+                    "\t" "err = " (field) ".DecodeStream(stream)" "\n"
+                }
+                Type::Enum(..) => {
+                    "\t" "err = " (field) ".Read(stream, " (GoEndian(&item.byte_order)) ")" "\n"
+                }
+                Type::Union(_) => {
+                    "\t" "switch " (var_name) "." (item.tag.as_ref().unwrap()) " {" "\n"
+                    for case in &find_union(unions, &item.kind).cases {
+                        "\t" "case " (case.value) ":" "\n"
+                        "\t\t" "v := &" (case.kind) "{}" "\n"
+                        "\t\t" "err = v.DecodeStream(stream)" "\n"
+                        "\t\t" (field) " = v" "\n"
+                    }
+                    "\t" "default:" "\n"
+                    "\t\t" "return ps.ErrUnknownVariant" "\n"
+                    "\t" "}" "\n"
                 }
                 Type::CString => {
-                    "\t" (var_name) "." (item.name) ", err = stream.ReadCStringUnsized()" "\n"
+                    "\t" (field) ", err = stream.ReadCStringUnsized()" "\n"
+                }
+                Type::String => {
+                    "\t" (field) ", err = " (render_string_read(item)) "\n"
                 }
                 _ => {
-                    "\t" (var_name) "." (item.name) ", err = stream.Read" (item_kind) "(" (item_read_border(item)) ")" "\n"
+                    "\t" (field) ", err = stream.Read" (item_kind) "(" (item_read_border(item)) ")" "\n"
                 }
             }
             "\t" "if err != nil {" "\n"
@@ -309,6 +986,58 @@ fn render_decode_item(item: &Item, var_name: &str) -> String {
     }
 }
 
+impl fmt::Display for GoEnum<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = &self.0.name;
+        let kind = &self.0.kind;
+        wite!(
+            f,
+            "type " (name) " " (GoType(kind)) "\n\n"
+            "const (" "\n"
+            for variant in &self.0.variants {
+                "\t" (name) (variant.name) " " (name) " = " (variant.value) "\n"
+            }
+            ")" "\n\n"
+        )?;
+        wite!(
+            f,
+            "func (e " (name) ") Write(stream *ps.Stream, b binary.ByteOrder) error {" "\n"
+            "\t" "return stream.Write" (alt(kind)) "(" (GoType(kind)) "(e)" if is_multibyte(kind) { ", b" } ")" "\n"
+            "}" "\n\n"
+            "func (e *" (name) ") Read(stream *ps.Stream, b binary.ByteOrder) error {" "\n"
+            "\t" "v, err := stream.Read" (alt(kind)) "(" if is_multibyte(kind) { "b" } ")" "\n"
+            "\t" "if err != nil {" "\n"
+            "\t\t" "return err" "\n"
+            "\t" "}" "\n"
+            "\t" "switch " (name) "(v) {" "\n"
+            "\t" "case "
+                for (idx, variant) in self.0.variants.iter().enumerate() {
+                    if idx > 0 { ", " }
+                    (name) (variant.name)
+                }
+                ":" "\n"
+            "\t" "default:" "\n"
+            "\t\t" "return ps.ErrUnknownVariant" "\n"
+            "\t" "}" "\n"
+            "\t" "*e = " (name) "(v)" "\n"
+            "\t" "return nil" "\n"
+            "}" "\n\n"
+        )
+    }
+}
+
+impl fmt::Display for GoUnion<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // cases are checked with a type switch when encoding
+        wite!(
+            f,
+            "type " (self.0.name) " interface {" "\n"
+            "\t" "ps.Struct" "\n"
+            "}" "\n\n"
+        )
+    }
+}
+
 impl fmt::Display for GoStruct<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let var_name = self
@@ -321,19 +1050,20 @@ impl fmt::Display for GoStruct<'_> {
             f,
             "type " (self.0.name) " struct {" "\n"
             for item in &self.0.items {
-                if let Some(type_alias) = item.type_alias {
-                    "\t" (item.name) "\t" (type_alias) "\n"
-                } else {
-                    match &item.array {
-                        Some(_) => {
-                            if item.kind == Type::CString {
-                                "\t" (item.name) "\t" (GoType(&item.kind)) "\n"
-                            } else {
-                                "\t" (item.name) "\t" "[]" (GoType(&item.kind)) "\n"
-                            }
+                match item.storage {
+                    Storage::Field => {
+                        if item.condition.is_some() {
+                            "\t" (item.name) "\t" "*" (go_field_type(item)) "\n"
+                        } else {
+                            "\t" (item.name) "\t" (go_field_type(item)) "\n"
+                        }
+                    }
+                    Storage::Bitfield => {
+                        for bitfield in &item.bits {
+                            "\t" (bitfield.name) "\t" (GoType(&item.kind)) "\n"
                         }
-                        None => { "\t" (item.name) "\t" (GoType(&item.kind)) "\n" }
-                    } // TODO do something better?
+                    }
+                    Storage::Padding | Storage::Magic(_) | Storage::LengthOf(_) => {}
                 }
             }
             "}" "\n\n"
@@ -342,7 +1072,7 @@ impl fmt::Display for GoStruct<'_> {
             f,
             "func New" (self.0.name) "() " (self.0.name) " {" "\n"
             "\t" "res := " (self.0.name) "{}" "\n"
-            for item in &self.0.items {
+            for item in self.0.items.iter().filter(|x| x.storage == Storage::Field && x.condition.is_none()) {
                 if item.kind != Type::CString {
                     match &item.array {
                         Some(Array::Constant(size)) => {
@@ -375,14 +1105,14 @@ impl fmt::Display for GoStruct<'_> {
             "func (" (var_name) " *" (self.0.name) ") EncodeStream(stream *ps.Stream) error {" "\n"
             "\t" "var err error" "\n"
             for item in &self.0.items {
-                (render_encode_item(item, var_name.as_str()))
+                (render_encode_item(item, &self.0.items, self.1, var_name.as_str()))
             }
             "\t" "return nil" "\n"
             "}" "\n\n"
             "func (" (var_name) " *" (self.0.name) ") DecodeStream(stream *ps.Stream) error {" "\n"
             "\t" "var err error" "\n"
             for item in &self.0.items {
-                (render_decode_item(item, var_name.as_str()))
+                (render_decode_item(item, self.1, var_name.as_str()))
             }
             "\t" "return nil" "\n"
             "}" "\n\n"
@@ -391,6 +1121,19 @@ impl fmt::Display for GoStruct<'_> {
 }
 
 pub fn render_file(file: &File) -> String {
+    let unions: Vec<Union> = file
+        .unions
+        .iter()
+        .chain(file.includes.iter().flat_map(|include| &include.unions))
+        .cloned()
+        .collect();
+    // several files may share a package
+    let include_imports: BTreeSet<(String, &str)> = foreign_includes(file)
+        .filter_map(|include| {
+            let path = include.go_import.as_deref()?;
+            Some((include.scope.to_lowercase(), path))
+        })
+        .collect();
     fomat!(
         (GENERATED_HEADER) "\n\n"
         "package " (file.scope.to_lowercase()) "\n\n"
@@ -401,9 +1144,33 @@ pub fn render_file(file: &File) -> String {
         if file_uses_byte_order(file) {
             r#"import "encoding/binary""# "\n"
         }
-        r#"import ps "github.com/wwared/pstruct/runtime/go""# "\n\n"
+        r#"import ps "github.com/wwared/pstruct/runtime/go""# "\n"
+        for (scope, path) in &include_imports {
+            "import " (scope) " \"" (path) "\"\n"
+        }
+        "\n"
+        if !file.constants.is_empty() {
+            "const (" "\n"
+            for constant in &file.constants {
+                "\t" (constant.name) " = " (constant.value) "\n"
+            }
+            ")" "\n\n"
+        }
+        for include in foreign_includes(file) {
+            "// from " (include.path) "\n"
+            for name in &include.types {
+                "type " (name) " = " (include.scope.to_lowercase()) "." (name) "\n"
+            }
+            "\n"
+        }
+        for definition in &file.enums {
+            (GoEnum(definition))
+        }
+        for definition in &file.unions {
+            (GoUnion(definition))
+        }
         for definition in &file.structs {
-            (GoStruct(definition))
+            (GoStruct(definition, &unions))
         }
     )
 }
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use pstruct::parser::{parse_file, parse_file_with_includes, Source};
+
+    const COMMON: &str = "
+options scope:common go_import:example.com/specs/common
+struct vec3 {
+    x f32
+}";
+
+    const MAIN: &str = "
+include \"common.zs\"
+struct waypoint {
+    position vec3
+}";
+
+    fn sources(common: &str) -> Vec<Source> {
+        vec![Source {
+            path: PathBuf::from("specs/common.zs"),
+            contents: common.to_string(),
+        }]
+    }
+
+    #[test]
+    fn include_imports() {
+        let sources = sources(COMMON);
+        let file = parse_file_with_includes(MAIN, Path::new("specs/main.zs"), &sources).unwrap();
+        assert!(pstruct_go::check_file(&file).is_ok());
+        let rendered = pstruct_go::render_file(&file);
+        assert!(rendered.contains("import common \"example.com/specs/common\"\n"));
+        assert!(rendered.contains("type vec3 = common.vec3\n"));
+
+        // without the import path the alias would point at a package that isn't imported
+        let sources = self::sources(&COMMON.replace(" go_import:example.com/specs/common", ""));
+        let file = parse_file_with_includes(MAIN, Path::new("specs/main.zs"), &sources).unwrap();
+        assert!(pstruct_go::check_file(&file).is_err());
+
+        // nothing to import from the same package
+        let main = format!("options scope:common\n{}", MAIN);
+        let sources = self::sources(COMMON);
+        let file = parse_file_with_includes(&main, Path::new("specs/main.zs"), &sources).unwrap();
+        assert!(pstruct_go::check_file(&file).is_ok());
+        let rendered = pstruct_go::render_file(&file);
+        assert!(!rendered.contains("example.com/specs/common"));
+        assert!(!rendered.contains("type vec3"));
+    }
+
+    #[test]
+    fn array_length_checks() {
+        let test = "
+struct sample {
+    count u8
+    data  [count]u16
+    pairs [count * 2]u8
+}";
+        let rendered = pstruct_go::render_file(&parse_file(test).unwrap());
+        // the encoder checks the slices before writing that many elements
+        let encode = &rendered[rendered.find(") EncodeStream(").unwrap()..];
+        let data_check = encode.find("if len(s.data) != int(s.count) {").unwrap();
+        assert!(data_check < encode.find("s.data[idx]").unwrap());
+        assert!(encode.contains("if len(s.pairs) != int(sPairsLen) {"));
+    }
+}
@@ -1,7 +1,8 @@
 use pstruct::types::*;
 
-use proc_macro2::{Ident, Span, TokenStream};
+use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
+use std::path::Path;
 
 // TODO: variant that takes in raw contents instead of filename
 // TODO: test with multiple pstruct! calls
@@ -10,23 +11,180 @@ pub static GENERATED_HEADER: &str = "// AUTOGENERATED - MAY BE OVERWRITTEN";
 
 pub fn render_file(file: &File) -> String {
     let scope = Ident::new(file.scope.as_str(), Span::call_site());
+    let constants = file.constants.iter().map(const_declaration);
+    let enums = file.enums.iter().map(enum_declaration);
+    let unions = file.unions.iter().map(union_declaration);
     let declarations = file.structs.iter().map(struct_declaration);
+    let includes = file.includes.iter().map(include_declaration);
 
     let tokens = quote!(
-        #[allow(unused_assignments, unused_variables, unused_parens, clippy::derivable_impls, clippy::needless_range_loop, clippy::needless_parens_on_range_literals, clippy::unnecessary_cast, clippy::identity_op)]
+        #[allow(unused_assignments, unused_imports, unused_variables, unused_parens, clippy::derivable_impls, clippy::needless_range_loop, clippy::needless_parens_on_range_literals, clippy::unnecessary_cast, clippy::identity_op, clippy::nonminimal_bool, clippy::double_parens)]
         pub mod #scope {
             use pstruct_rs::*;
             use std::ffi::CString;
+            #(#includes)*
 
+            #(#constants)*
+            #(#enums)*
+            #(#unions)*
             #(#declarations)*
         }
     );
 
-    format!("{}\n\n{}", GENERATED_HEADER, tokens.to_string())
+    format!("{}\n\n{}", GENERATED_HEADER, tokens)
+}
+
+// the generated file of an include is expected to be a sibling module named after it
+fn include_declaration(include: &Include) -> TokenStream {
+    let stem = Path::new(&include.path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(&include.path);
+    let module = Ident::new(stem, Span::call_site());
+    let scope = Ident::new(include.scope.as_str(), Span::call_site());
+    let types = include
+        .types
+        .iter()
+        .map(|name| Ident::new(name, Span::call_site()));
+    quote!(use super::super::#module::#scope::{#(#types),*};)
+}
+
+fn const_declaration(decl: &Constant) -> TokenStream {
+    let name = Ident::new(&decl.name, Span::call_site());
+    let value = decl.value;
+    quote!(pub const #name: usize = #value;)
+}
+
+fn enum_declaration(decl: &Enum) -> TokenStream {
+    let enum_name = Ident::new(&decl.name, Span::call_site());
+    let repr = quote_type(&decl.kind);
+    let variants = decl.variants.iter().enumerate().map(|(idx, variant)| {
+        let name = Ident::new(&variant.name, Span::call_site());
+        let value = Literal::i128_unsuffixed(variant.value);
+        // the first variant is the default
+        if idx == 0 {
+            quote!(#[default] #name = #value,)
+        } else {
+            quote!(#name = #value,)
+        }
+    });
+    let arms = decl.variants.iter().map(|variant| {
+        let name = Ident::new(&variant.name, Span::call_site());
+        let value = Literal::i128_unsuffixed(variant.value);
+        quote!(#value => Ok(Self::#name),)
+    });
+    let encode_le = encode_primitive(
+        &decl.kind,
+        Endian::Little,
+        quote!((*self as #repr)),
+        quote!(buf),
+    );
+    let encode_be = encode_primitive(
+        &decl.kind,
+        Endian::Big,
+        quote!((*self as #repr)),
+        quote!(buf),
+    );
+    let decode_le = decode_primitive(&decl.kind, Endian::Little, quote!(value), quote!(data));
+    let decode_be = decode_primitive(&decl.kind, Endian::Big, quote!(value), quote!(data));
+    quote!(
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        #[repr(#repr)]
+        pub enum #enum_name {
+            #(#variants)*
+        }
+
+        impl std::convert::TryFrom<#repr> for #enum_name {
+            type Error = PError;
+
+            fn try_from(value: #repr) -> Result<Self> {
+                match value {
+                    #(#arms)*
+                    _ => Err(PError::UnknownVariant),
+                }
+            }
+        }
+
+        impl Primitive for #enum_name {
+            fn encode_le(&self, buf: &mut [u8]) -> Result<()> {
+                #encode_le
+            }
+            fn encode_be(&self, buf: &mut [u8]) -> Result<()> {
+                #encode_be
+            }
+            fn decode_le(&mut self, data: &[u8]) -> Result<()> {
+                let mut value: #repr = 0;
+                #decode_le?;
+                *self = <Self as std::convert::TryFrom<#repr>>::try_from(value)?;
+                Ok(())
+            }
+            fn decode_be(&mut self, data: &[u8]) -> Result<()> {
+                let mut value: #repr = 0;
+                #decode_be?;
+                *self = <Self as std::convert::TryFrom<#repr>>::try_from(value)?;
+                Ok(())
+            }
+        }
+    )
+}
+
+fn union_declaration(decl: &Union) -> TokenStream {
+    let union_name = Ident::new(&decl.name, Span::call_site());
+    let names = decl
+        .cases
+        .iter()
+        .map(|case| Ident::new(&case.kind, Span::call_site()))
+        .collect::<Vec<_>>();
+    let values = decl
+        .cases
+        .iter()
+        .map(|case| Literal::i128_unsuffixed(case.value))
+        .collect::<Vec<_>>();
+    let default = &names[0];
+    quote!(
+        #[derive(Debug)]
+        pub enum #union_name {
+            #(#names(#names),)*
+        }
+
+        impl Default for #union_name {
+            fn default() -> Self {
+                Self::#default(Default::default())
+            }
+        }
+
+        impl #union_name {
+            pub fn tag(&self) -> i128 {
+                match self {
+                    #(Self::#names(_) => #values,)*
+                }
+            }
+
+            pub fn decode_tagged(&mut self, tag: i128, data: &[u8]) -> Result<()> {
+                *self = match tag {
+                    #(#values => Self::#names(#names::decode_new(data)?),)*
+                    _ => return Err(PError::UnknownVariant),
+                };
+                Ok(())
+            }
+
+            pub fn encode_buf(&self, buf: &mut [u8]) -> Result<()> {
+                match self {
+                    #(Self::#names(inner) => inner.encode_buf(buf),)*
+                }
+            }
+
+            pub fn size(&self) -> usize {
+                match self {
+                    #(Self::#names(inner) => inner.size(),)*
+                }
+            }
+        }
+    )
 }
 
 fn struct_declaration(decl: &Struct) -> TokenStream {
-    let struct_name = Ident::new(decl.name, Span::call_site());
+    let struct_name = Ident::new(&decl.name, Span::call_site());
     let fields = decl.items.iter().map(item_declaration);
     let defaults = decl.items.iter().map(item_default);
     let trait_impls = trait_impl(decl);
@@ -49,7 +207,7 @@ fn struct_declaration(decl: &Struct) -> TokenStream {
 }
 
 fn trait_impl(decl: &Struct) -> TokenStream {
-    let struct_name = Ident::new(decl.name, Span::call_site());
+    let struct_name = Ident::new(&decl.name, Span::call_site());
     let size = size_fn(decl);
     let encode = encode_fn(decl);
     let decode = decode_fn(decl);
@@ -62,26 +220,118 @@ fn trait_impl(decl: &Struct) -> TokenStream {
     )
 }
 
+// the next `len` bytes of the input, failing instead of panicking when there are fewer
+fn take_data(len: TokenStream) -> TokenStream {
+    quote!(data.get(..#len).ok_or(PError::NotEnoughData)?)
+}
+
 fn decode_item(item: &Item) -> TokenStream {
-    let var_id = Ident::new(item.name, Span::call_site());
+    match item.storage {
+        Storage::Field => {}
+        Storage::Padding => {
+            let size = item.fixed_size().unwrap();
+            return quote!(
+                data = data.get(#size..).ok_or(PError::NotEnoughData)?;
+            );
+        }
+        Storage::Magic(value) => {
+            let size = item.fixed_size().unwrap();
+            let ty = quote_type(&item.kind);
+            let value = Literal::i128_unsuffixed(value);
+            let decode = decode_primitive(
+                &item.kind,
+                item.byte_order,
+                quote!(magic),
+                take_data(quote!(#size)),
+            );
+            return quote!(
+                {
+                    let mut magic: #ty = 0;
+                    #decode?;
+                    if magic != #value {
+                        return Err(PError::BadMagic);
+                    }
+                    data = &data[#size..];
+                }
+            );
+        }
+        Storage::Bitfield => {
+            let size = item.fixed_size().unwrap();
+            let ty = quote_type(&item.kind);
+            let decode_fn = match item.byte_order {
+                Endian::Little => quote!(decode_le),
+                Endian::Big => quote!(decode_be),
+            };
+            let fields = item.bits.iter().map(|bitfield| {
+                let name = Ident::new(&bitfield.name, Span::call_site());
+                let shift = bitfield.shift;
+                let mask = Literal::u128_unsuffixed(bit_mask(bitfield.width));
+                quote!(self.#name = (bits >> #shift) & #mask;)
+            });
+            return quote!(
+                {
+                    let mut bits: #ty = 0;
+                    bits.#decode_fn(data.get(..#size).ok_or(PError::NotEnoughData)?)?;
+                    #(#fields)*
+                    data = &data[#size..];
+                }
+            );
+        }
+        Storage::LengthOf(_) => {
+            let size = item.fixed_size().unwrap();
+            let ty = quote_type(&item.kind);
+            let local = length_local(&item.name);
+            let decode = decode_primitive(
+                &item.kind,
+                item.byte_order,
+                quote!(#local),
+                take_data(quote!(#size)),
+            );
+            return quote!(
+                let mut #local: #ty = 0;
+                #decode?;
+                data = &data[#size..];
+            );
+        }
+    }
+    let var_id = Ident::new(&item.name, Span::call_site());
+    match &item.condition {
+        None => decode_field(item, &quote!(self.#var_id)),
+        Some(condition) => {
+            let condition = condition_check(condition, true);
+            let ty = item_type(item);
+            let default = default_value(item);
+            let decode = decode_field(item, &quote!(value));
+            quote!(
+                self.#var_id = None;
+                if #condition {
+                    let mut value: #ty = #default;
+                    #decode
+                    self.#var_id = Some(value);
+                }
+            )
+        }
+    }
+}
+
+// decodes into `field`, which is a local instead of the struct field for optional items
+fn decode_field(item: &Item, field: &TokenStream) -> TokenStream {
+    if let Some(Array::Nested(outer, _)) = &item.array {
+        return decode_rows(item, outer, field);
+    }
+    if item.prefix_mode == PrefixMode::Bytes {
+        return decode_bytes_prefixed(item, field);
+    }
     let var = if item.array.is_none() || item.kind == Type::CString {
-        quote!(#var_id)
+        quote!(#field)
     } else {
-        quote!(#var_id[idx])
+        quote!(#field[idx])
     };
     let size = if item.array.is_none() {
-        item_size(item)
+        field_size(item, field)
     } else {
         type_size(&item.kind, &var)
     };
-    let arr_sz_decode_fn = match item.byte_order {
-        Endian::Little => {
-            quote!(decode_le)
-        }
-        Endian::Big => {
-            quote!(decode_be)
-        }
-    };
     let mut decode_fn = match item.byte_order {
         Endian::Little => {
             quote!(decode_le)
@@ -94,44 +344,65 @@ fn decode_item(item: &Item) -> TokenStream {
         decode_fn = quote!(decode);
     }
     let single_item = match item.kind {
-        Type::String => {
-            quote!(
-                let mut tmp_len: u16 = 0;
-                tmp_len.#arr_sz_decode_fn(&data[..2])?;
-                data = &data[2..];
-                let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
-                tmp_buf.#decode_fn(&data[..(tmp_len as usize)])?;
-                self.#var = String::from_utf8(tmp_buf).unwrap();
-                data = &data[(tmp_len as usize)..];
-            )
-        }
+        Type::String => match &item.string_format {
+            StringFormat::Prefixed(ty) => {
+                let prefix = decode_prefix(ty, item.byte_order);
+                quote!(
+                    #prefix
+                    let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
+                    tmp_buf.#decode_fn(data.get(..(tmp_len as usize)).ok_or(PError::NotEnoughData)?)?;
+                    #var = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+                    data = &data[(tmp_len as usize)..];
+                )
+            }
+            StringFormat::Fixed(width) => quote!(
+                let mut tmp_buf: Vec<u8> = vec![0; #width];
+                tmp_buf.#decode_fn(data.get(..#width).ok_or(PError::NotEnoughData)?)?;
+                let end = tmp_buf.iter().rposition(|c| *c != b' ').map_or(0, |idx| idx + 1);
+                tmp_buf.truncate(end);
+                #var = String::from_utf8(tmp_buf).map_err(|_| PError::InvalidUtf8)?;
+                data = &data[#width..];
+            ),
+        },
         Type::CString => {
             assert!(item.array.is_some(), "invalid non-array cstring");
             let arr = item.array.as_ref().unwrap();
             match arr {
                 Array::Constant(size) => quote!(
                     let mut tmp_buf: Vec<u8> = vec![0; #size];
-                    tmp_buf.#decode_fn(&data[..(#size)])?;
+                    tmp_buf.#decode_fn(data.get(..(#size)).ok_or(PError::NotEnoughData)?)?;
                     let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
-                    self.#var = CString::new(tmp_buf).unwrap();
+                    #var = CString::new(tmp_buf).unwrap();
                     data = &data[#size..];
                 ),
-                Array::Variable(size_name, _) => quote!(
-                    let mut tmp_buf: Vec<u8> = vec![0; (self.#size_name as usize)];
-                    tmp_buf.#decode_fn(&data[..(self.#size_name as usize)])?;
+                Array::Variable(..) | Array::Expression(_) | Array::Derived(_) => {
+                    let len = array_len(arr, true);
+                    let bounds_check = bounds_check(item, quote!(tmp_len));
+                    quote!(
+                    let tmp_len = #len;
+                    #bounds_check
+                    let mut tmp_buf: Vec<u8> = vec![0; tmp_len];
+                    tmp_buf.#decode_fn(data.get(..tmp_len).ok_or(PError::NotEnoughData)?)?;
                     let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
-                    self.#var = CString::new(tmp_buf).unwrap();
-                    data = &data[(self.#size_name as usize)..];
-                ),
-                Array::Unknown(_) => quote!(
-                    let tmp_len: u8 = data[0];
-                    data = &data[1..];
+                    #var = CString::new(tmp_buf).unwrap();
+                    data = &data[tmp_len..];
+                    )
+                }
+                Array::Unknown(ty) => {
+                    let prefix = decode_prefix(ty, item.byte_order);
+                    let bounds_check = bounds_check(item, quote!(tmp_len as usize));
+                    quote!(
+                    #prefix
+                    #bounds_check
                     let mut tmp_buf: Vec<u8> = vec![0; tmp_len as usize];
-                    tmp_buf.#decode_fn(&data[..(tmp_len as usize)])?;
+                    tmp_buf.#decode_fn(data.get(..(tmp_len as usize)).ok_or(PError::NotEnoughData)?)?;
                     let tmp_buf = tmp_buf.splitn(2, |c| *c == b'\0').next().unwrap();
-                    self.#var = CString::new(tmp_buf).unwrap();
+                    #var = CString::new(tmp_buf).unwrap();
                     data = &data[(tmp_len as usize)..];
-                ),
+                    )
+                }
+                // neither holds cstrings directly; rows are split off before getting here
+                Array::Rest | Array::Nested(..) => unreachable!(),
             }
         }
         Type::User(_) => {
@@ -140,14 +411,39 @@ fn decode_item(item: &Item) -> TokenStream {
             // filled in yet. for now, just pass in the rest of the buffer for
             // reading, then update the buffer by truncating based on what was read
             quote!(
-                self.#var.#decode_fn(data)?;
+                #var.#decode_fn(data)?;
                 data = &data[#size..];
             )
         }
+        Type::Union(_) => {
+            let tag = Ident::new(item.tag.as_ref().unwrap(), Span::call_site());
+            quote!(
+                #var.decode_tagged(self.#tag as i128, data)?;
+                data = &data[#size..];
+            )
+        }
+        Type::VarU32 | Type::VarU64 | Type::VarI64 => {
+            let ty = quote_type(&item.kind);
+            quote!(
+                let (value, size) = <#ty as Varint>::decode_var(data)?;
+                #var = value;
+                data = &data[size..];
+            )
+        }
+        Type::Bool if item.strict => quote!(
+            let bytes = data.get(..1).ok_or(PError::NotEnoughData)?;
+            if bytes[0] > 1 {
+                return Err(PError::InvalidBool);
+            }
+            #var.#decode_fn(bytes)?;
+            data = &data[1..];
+        ),
         _ => {
+            let decode =
+                decode_primitive(&item.kind, item.byte_order, var, take_data(quote!(size)));
             quote!(
                 let size = #size; // TODO: this is because #decode_fn borrows mutably, and #size might borrow immutably
-                self.#var.#decode_fn(&data[..size])?;
+                #decode?;
                 data = &data[size..];
             )
         }
@@ -159,32 +455,42 @@ fn decode_item(item: &Item) -> TokenStream {
             Some(Array::Constant(_)) => {
                 quote!()
             }
-            Some(Array::Variable(name, _)) => {
-                let arr_len = Ident::new(name, Span::call_site());
+            Some(arr @ (Array::Variable(..) | Array::Expression(_) | Array::Derived(_))) => {
+                let len = array_len(arr, true);
+                let bounds_check = bounds_check(item, quote!(tmp_len));
                 // NOTE: in this case, we already decoded the length previously, so
                 // just initialize the vec
                 // TODO: use .push and stuff instead of this gross stuff
                 quote!(
-                    self.#var_id = Vec::with_capacity(self.#arr_len as usize);
-                    for idx in 0..(self.#arr_len as usize) {
-                        self.#var_id.push(Default::default());
+                    let tmp_len = #len;
+                    #bounds_check
+                    #field = Vec::with_capacity(tmp_len);
+                    for idx in 0..tmp_len {
+                        #field.push(Default::default());
                     }
                 )
             }
             Some(Array::Unknown(ty)) => {
-                let arr_ty = quote_type(ty);
-                let arr_sz = type_size(ty, &quote!(compile_error!("SHOULD NEVER HAPPEN")));
+                let prefix = decode_prefix(ty, item.byte_order);
+                let bounds_check = bounds_check(item, quote!(tmp_len as usize));
                 // TODO: use .push and stuff instead of this gross stuff
                 quote!(
-                    let mut tmp_len: #arr_ty = 0;
-                    tmp_len.#arr_sz_decode_fn(&data[..#arr_sz])?;
-                    data = &data[#arr_sz..];
-                    self.#var_id = Vec::with_capacity(tmp_len as usize);
+                    #prefix
+                    #bounds_check
+                    #field = Vec::with_capacity(tmp_len as usize);
                     for idx in 0..(tmp_len as usize) {
-                        self.#var_id.push(Default::default());
+                        #field.push(Default::default());
                     }
                 )
             }
+            Some(Array::Rest) => {
+                let bounds_check = bounds_check(item, quote!(tmp_len));
+                quote!(
+                    let tmp_len = data.len();
+                    #bounds_check
+                    #field = vec![0; tmp_len];
+                )
+            }
             _ => {
                 unreachable!()
             }
@@ -193,13 +499,15 @@ fn decode_item(item: &Item) -> TokenStream {
             Some(Array::Constant(sz)) => {
                 quote!(#sz)
             }
-            Some(Array::Unknown(_)) => {
+            Some(
+                Array::Unknown(_)
+                | Array::Variable(..)
+                | Array::Expression(_)
+                | Array::Derived(_)
+                | Array::Rest,
+            ) => {
                 quote!(tmp_len)
             }
-            Some(Array::Variable(name, _)) => {
-                let var = Ident::new(name, Span::call_site());
-                quote!(self.#var)
-            }
             _ => {
                 unreachable!()
             }
@@ -213,6 +521,429 @@ fn decode_item(item: &Item) -> TokenStream {
     }
 }
 
+fn op_tokens(op: BinaryOp) -> TokenStream {
+    match op {
+        BinaryOp::Or => quote!(||),
+        BinaryOp::And => quote!(&&),
+        BinaryOp::Eq => quote!(==),
+        BinaryOp::Ne => quote!(!=),
+        BinaryOp::Lt => quote!(<),
+        BinaryOp::Le => quote!(<=),
+        BinaryOp::Gt => quote!(>),
+        BinaryOp::Ge => quote!(>=),
+        BinaryOp::BitOr => quote!(|),
+        BinaryOp::BitXor => quote!(^),
+        BinaryOp::BitAnd => quote!(&),
+        BinaryOp::Add => quote!(+),
+        BinaryOp::Sub => quote!(-),
+        BinaryOp::Mul => quote!(*),
+        BinaryOp::Div => quote!(/),
+        BinaryOp::Rem => quote!(%),
+    }
+}
+
+// renders expressions producing integers as i128, arithmetic goes through checked_value instead
+fn expr_value(expr: &Expr) -> TokenStream {
+    match expr {
+        Expr::Number(value) => {
+            let value = Literal::i128_suffixed(*value);
+            quote!(#value)
+        }
+        Expr::Variable(name) => {
+            let name = Ident::new(name, Span::call_site());
+            quote!((self.#name as i128))
+        }
+        Expr::Binary(op, lhs, rhs) if !op.is_boolean() && !op.is_arithmetic() => {
+            let (lhs, rhs, op) = (expr_value(lhs), expr_value(rhs), op_tokens(*op));
+            quote!((#lhs #op #rhs))
+        }
+        _ => unreachable!("not an unchecked integer expression"),
+    }
+}
+
+// renders expressions producing integers as Option<i128>, which is None when arithmetic fails
+fn checked_value(expr: &Expr) -> TokenStream {
+    if !expr.is_checked() {
+        let value = expr_value(expr);
+        return quote!(Some(#value));
+    }
+    match expr {
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (checked_value(lhs), checked_value(rhs));
+            let apply = match op {
+                BinaryOp::Add => quote!(lhs.checked_add(rhs)),
+                BinaryOp::Sub => quote!(lhs.checked_sub(rhs)),
+                BinaryOp::Mul => quote!(lhs.checked_mul(rhs)),
+                BinaryOp::Div => quote!(lhs.checked_div(rhs)),
+                BinaryOp::Rem => quote!(lhs.checked_rem(rhs)),
+                op => {
+                    let op = op_tokens(*op);
+                    quote!(Some(lhs #op rhs))
+                }
+            };
+            quote!(#lhs.zip(#rhs).and_then(|(lhs, rhs)| #apply))
+        }
+        _ => unreachable!("not an integer expression"),
+    }
+}
+
+// renders conditions without arithmetic as bool, integers hold when they're not zero
+fn condition_value(expr: &Expr) -> TokenStream {
+    if !expr.is_boolean() {
+        let value = expr_value(expr);
+        return quote!((#value != 0));
+    }
+    match expr {
+        Expr::Not(operand) => {
+            let operand = condition_value(operand);
+            quote!(!#operand)
+        }
+        Expr::Binary(op, lhs, rhs) if op.is_logical() => {
+            let (lhs, rhs, op) = (condition_value(lhs), condition_value(rhs), op_tokens(*op));
+            quote!((#lhs #op #rhs))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs, op) = (expr_value(lhs), expr_value(rhs), op_tokens(*op));
+            quote!((#lhs #op #rhs))
+        }
+        _ => unreachable!("not a boolean expression"),
+    }
+}
+
+// renders conditions as Option<bool>, which is None when arithmetic fails
+fn checked_condition(expr: &Expr) -> TokenStream {
+    if !expr.is_checked() {
+        let value = condition_value(expr);
+        return quote!(Some(#value));
+    }
+    if !expr.is_boolean() {
+        let value = checked_value(expr);
+        return quote!(#value.map(|value| value != 0));
+    }
+    match expr {
+        Expr::Not(operand) => {
+            let operand = checked_condition(operand);
+            quote!(#operand.map(|value| !value))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = if op.is_logical() {
+                (checked_condition(lhs), checked_condition(rhs))
+            } else {
+                (checked_value(lhs), checked_value(rhs))
+            };
+            let op = op_tokens(*op);
+            quote!(#lhs.zip(#rhs).map(|(lhs, rhs)| lhs #op rhs))
+        }
+        _ => unreachable!("not a boolean expression"),
+    }
+}
+
+// failing arithmetic is an error where one can be returned, otherwise the condition doesn't hold
+fn condition_check(expr: &Expr, fallible: bool) -> TokenStream {
+    if !expr.is_checked() {
+        return condition_value(expr);
+    }
+    let value = checked_condition(expr);
+    if fallible {
+        quote!(#value.ok_or(PError::OutOfRange)?)
+    } else {
+        quote!(#value.unwrap_or(false))
+    }
+}
+
+// length of arrays sized by earlier fields; failing arithmetic is an error
+// where one can be returned, otherwise the array counts as empty
+fn array_len(arr: &Array, fallible: bool) -> TokenStream {
+    match arr {
+        Array::Variable(name, _) => {
+            let name = Ident::new(name, Span::call_site());
+            quote!((self.#name as usize))
+        }
+        Array::Expression(expr) => {
+            let value = checked_value(expr);
+            let len = quote!(#value.and_then(|len| <usize as ::core::convert::TryFrom<i128>>::try_from(len).ok()));
+            if fallible {
+                quote!(#len.ok_or(PError::OutOfRange)?)
+            } else {
+                quote!(#len.unwrap_or(0))
+            }
+        }
+        // only decoding reads the length, encoding takes it from the array
+        Array::Derived(name) => {
+            let local = length_local(name);
+            quote!((#local as usize))
+        }
+        _ => unreachable!("not an array sized by earlier fields"),
+    }
+}
+
+// local holding the length read for Array::Derived, so later items can use it
+fn length_local(name: &str) -> Ident {
+    Ident::new(&format!("len_{}", name), Span::call_site())
+}
+
+// length written for arrays sized by a length_of item; cstrings count their null byte
+fn derived_len(item: &Item, field: &TokenStream) -> TokenStream {
+    if item.kind == Type::CString {
+        quote!((#field.as_bytes().len() + 1))
+    } else {
+        quote!(#field.len())
+    }
+}
+
+fn bit_mask(width: u32) -> u128 {
+    (1 << width) - 1
+}
+
+// `var.decode_le(data)` or its big endian version; narrow integers also pass their width
+fn decode_primitive(
+    ty: &Type,
+    byte_order: Endian,
+    var: TokenStream,
+    data: TokenStream,
+) -> TokenStream {
+    if let (Some(raw), Type::Fixed { frac_bits, .. }) = (ty.raw_type(), ty) {
+        let raw_ty = quote_type(&raw);
+        let float = quote_type(ty);
+        let decode = decode_primitive(&raw, byte_order, quote!(raw), data);
+        return quote!({
+            let mut raw: #raw_ty = 0;
+            #decode.map(|()| #var = <#float as Fixed>::from_fixed(raw, #frac_bits))
+        });
+    }
+    match (ty.is_narrow(), byte_order) {
+        (false, Endian::Little) => quote!(#var.decode_le(#data)),
+        (false, Endian::Big) => quote!(#var.decode_be(#data)),
+        (true, Endian::Little) => {
+            let width = ty.fixed_size().unwrap();
+            quote!(#var.decode_narrow_le(#width, #data))
+        }
+        (true, Endian::Big) => {
+            let width = ty.fixed_size().unwrap();
+            quote!(#var.decode_narrow_be(#width, #data))
+        }
+    }
+}
+
+// same as `decode_primitive`, but encoding into `buf`; fixed-point numbers go through
+// `encode_field` instead, since whether they saturate depends on the item
+fn encode_primitive(
+    ty: &Type,
+    byte_order: Endian,
+    var: TokenStream,
+    buf: TokenStream,
+) -> TokenStream {
+    match (ty.is_narrow(), byte_order) {
+        (false, Endian::Little) => quote!(#var.encode_le(#buf)),
+        (false, Endian::Big) => quote!(#var.encode_be(#buf)),
+        (true, Endian::Little) => {
+            let width = ty.fixed_size().unwrap();
+            quote!(#var.encode_narrow_le(#width, #buf))
+        }
+        (true, Endian::Big) => {
+            let width = ty.fixed_size().unwrap();
+            quote!(#var.encode_narrow_be(#width, #buf))
+        }
+    }
+}
+
+// reads a length prefix of type `ty` into a new `tmp_len` local
+fn decode_prefix(ty: &Type, byte_order: Endian) -> TokenStream {
+    let arr_ty = quote_type(ty);
+    if ty.is_varint() {
+        return quote!(
+            let (tmp_len, len_size) = <#arr_ty as Varint>::decode_var(data)?;
+            data = &data[len_size..];
+        );
+    }
+    let arr_sz = type_size(ty, &quote!(compile_error!("SHOULD NEVER HAPPEN")));
+    let decode = decode_primitive(ty, byte_order, quote!(tmp_len), take_data(quote!(#arr_sz)));
+    quote!(
+        let mut tmp_len: #arr_ty = 0;
+        #decode?;
+        data = &data[#arr_sz..];
+    )
+}
+
+// writes `len` as a length prefix of type `ty`, failing when it doesn't fit
+fn encode_prefix(ty: &Type, byte_order: Endian, len: TokenStream) -> TokenStream {
+    let arr_ty = quote_type(ty);
+    let write = if ty.is_varint() {
+        quote!(
+            let len_size = tmp_len.encode_var(buf)?;
+            buf = &mut buf[len_size..];
+        )
+    } else {
+        let arr_sz = type_size(ty, &quote!(compile_error!("SHOULD NEVER HAPPEN")));
+        let encode = encode_primitive(ty, byte_order, quote!(tmp_len), quote!(&mut buf[..#arr_sz]));
+        quote!(
+            #encode?;
+            buf = &mut buf[#arr_sz..];
+        )
+    };
+    quote!(
+        let tmp_len = <#arr_ty as ::core::convert::TryFrom<usize>>::try_from(#len)
+            .map_err(|_| PError::OutOfRange)?;
+        #write
+    )
+}
+
+// size in bytes of `len` written as a length prefix of type `ty`
+fn prefix_size(ty: &Type, len: TokenStream) -> TokenStream {
+    if ty.is_varint() {
+        let arr_ty = quote_type(ty);
+        quote!(((#len) as #arr_ty).var_len())
+    } else {
+        type_size(ty, &quote!(compile_error!("SHOULD NEVER HAPPEN")))
+    }
+}
+
+// arrays prefixed with their size in bytes get elements decoded until it's used up,
+// which must happen exactly at the end of an element
+fn decode_bytes_prefixed(item: &Item, field: &TokenStream) -> TokenStream {
+    let prefix = match &item.array {
+        Some(Array::Unknown(ty)) => decode_prefix(ty, item.byte_order),
+        _ => unreachable!("only length-prefixed arrays can count bytes"),
+    };
+    let ty = quote_type(&item.kind);
+    let size = type_size(&item.kind, &quote!(value));
+    let bounds_check = bounds_check(item, quote!(#field.len()));
+    let decode = match item.kind {
+        Type::User(_) => quote!(value.decode(elems)),
+        _ => decode_primitive(&item.kind, item.byte_order, quote!(value), quote!(elems)),
+    };
+    // same check as for single strict bools, elems is never empty here
+    let check = match item.kind {
+        Type::Bool if item.strict => quote!(if elems[0] > 1 {
+            return Err(PError::InvalidBool);
+        }),
+        _ => quote!(),
+    };
+    let decode_value = if item.kind.is_varint() {
+        quote!(let (value, size) = <#ty as Varint>::decode_var(elems)?;)
+    } else {
+        quote!(
+            #check
+            let mut value: #ty = Default::default();
+            #decode?;
+            let size = #size;
+        )
+    };
+    quote!(
+        #prefix
+        if (tmp_len as usize) > data.len() {
+            return Err(PError::NotEnoughData);
+        }
+        let mut elems = &data[..(tmp_len as usize)];
+        data = &data[(tmp_len as usize)..];
+        #field = Vec::new();
+        while !elems.is_empty() {
+            #decode_value
+            if size > elems.len() {
+                return Err(PError::NotEnoughData);
+            }
+            elems = &elems[size..];
+            #field.push(value);
+            #bounds_check
+        }
+    )
+}
+
+// loop index over the rows of multi-dimensional arrays, distinct for every dimension
+fn row_index(row: &Item) -> Ident {
+    let depth = row.array.as_ref().map_or(0, |arr| arr.dimensions().len());
+    Ident::new(&format!("idx{}", depth), Span::call_site())
+}
+
+// multi-dimensional arrays are decoded one row at a time, each being an array itself
+fn decode_rows(item: &Item, outer: &Array, field: &TokenStream) -> TokenStream {
+    let row = item.row().unwrap();
+    let row_idx = row_index(&row);
+    let decode_row = decode_field(&row, &quote!(#field[#row_idx]));
+    let default = default_value(&row);
+    let prefix_len = match outer {
+        Array::Constant(_) => quote!(),
+        Array::Variable(..) | Array::Expression(_) => {
+            let len = array_len(outer, true);
+            let bounds_check = bounds_check(item, quote!(tmp_len));
+            quote!(
+                let tmp_len = #len;
+                #bounds_check
+                #field = Vec::with_capacity(tmp_len);
+                for _ in 0..tmp_len {
+                    #field.push(#default);
+                }
+            )
+        }
+        Array::Unknown(ty) => {
+            let prefix = decode_prefix(ty, item.byte_order);
+            let bounds_check = bounds_check(item, quote!(tmp_len as usize));
+            quote!(
+                #prefix
+                #bounds_check
+                #field = Vec::with_capacity(tmp_len as usize);
+                for _ in 0..(tmp_len as usize) {
+                    #field.push(#default);
+                }
+            )
+        }
+        _ => unreachable!("multi-dimensional arrays have no other sizes"),
+    };
+    quote!(
+        #prefix_len
+        for #row_idx in 0..#field.len() {
+            #decode_row
+        }
+    )
+}
+
+fn encode_rows(item: &Item, outer: &Array, field: &TokenStream) -> TokenStream {
+    let row = item.row().unwrap();
+    let row_idx = row_index(&row);
+    let encode_row = encode_field(&row, &quote!(#field[#row_idx]));
+    let prefix_len = match outer {
+        Array::Constant(_) => quote!(),
+        Array::Variable(..) | Array::Expression(_) => {
+            let len = array_len(outer, true);
+            let bounds_check = bounds_check(item, quote!(tmp_len));
+            quote!(
+                let tmp_len = #len;
+                #bounds_check
+                if #field.len() != tmp_len {
+                    return Err(PError::LengthMismatch);
+                }
+            )
+        }
+        Array::Unknown(ty) => {
+            let prefix = encode_prefix(ty, item.byte_order, quote!(#field.len()));
+            let bounds_check = bounds_check(item, quote!(#field.len()));
+            quote!(
+                #bounds_check
+                #prefix
+            )
+        }
+        _ => unreachable!("multi-dimensional arrays have no other sizes"),
+    };
+    quote!(
+        #prefix_len
+        for #row_idx in 0..#field.len() {
+            #encode_row
+        }
+    )
+}
+
+// rejects lengths over the item's max_array_size, before anything gets allocated
+fn bounds_check(item: &Item, len: TokenStream) -> TokenStream {
+    match item.max_array_size {
+        Some(max) => quote!(
+            if (#len) > #max {
+                return Err(PError::ArrayTooLarge);
+            }
+        ),
+        None => quote!(),
+    }
+}
+
 fn decode_fn(decl: &Struct) -> TokenStream {
     let items = decl.items.iter().map(decode_item);
     quote!(
@@ -222,7 +953,9 @@ fn decode_fn(decl: &Struct) -> TokenStream {
             Ok(res)
         }
         fn decode(&mut self, data: &[u8]) -> Result<()> {
-            assert!(data.len() >= self.size(), "todo improve errors");
+            if data.len() < self.size() {
+                return Err(PError::NotEnoughData);
+            }
             let mut data = data;
             #(#items)*
             Ok(())
@@ -230,26 +963,126 @@ fn decode_fn(decl: &Struct) -> TokenStream {
     )
 }
 
-fn encode_item(item: &Item) -> TokenStream {
-    let var_id = Ident::new(item.name, Span::call_site());
+fn encode_item(item: &Item, decl: &Struct) -> TokenStream {
+    match &item.storage {
+        Storage::Field => {}
+        Storage::Padding => {
+            let size = item.fixed_size().unwrap();
+            return quote!(
+                buf[..#size].fill(0);
+                buf = &mut buf[#size..];
+            );
+        }
+        Storage::Magic(value) => {
+            let size = item.fixed_size().unwrap();
+            let ty = quote_type(&item.kind);
+            let value = Literal::i128_unsuffixed(*value);
+            let encode = encode_primitive(
+                &item.kind,
+                item.byte_order,
+                quote!(magic),
+                quote!(&mut buf[..#size]),
+            );
+            return quote!(
+                {
+                    let magic: #ty = #value;
+                    #encode?;
+                    buf = &mut buf[#size..];
+                }
+            );
+        }
+        Storage::Bitfield => {
+            let size = item.fixed_size().unwrap();
+            let ty = quote_type(&item.kind);
+            let encode_fn = match item.byte_order {
+                Endian::Little => quote!(encode_le),
+                Endian::Big => quote!(encode_be),
+            };
+            let fields = item.bits.iter().map(|bitfield| {
+                let name = Ident::new(&bitfield.name, Span::call_site());
+                let shift = bitfield.shift;
+                // a field spanning the whole container always fits
+                let range_check = if bitfield.width as usize == size * 8 {
+                    quote!()
+                } else {
+                    let mask = Literal::u128_unsuffixed(bit_mask(bitfield.width));
+                    quote!(
+                        if self.#name > #mask {
+                            return Err(PError::OutOfRange);
+                        }
+                    )
+                };
+                quote!(
+                    #range_check
+                    bits |= self.#name << #shift;
+                )
+            });
+            return quote!(
+                {
+                    let mut bits: #ty = 0;
+                    #(#fields)*
+                    bits.#encode_fn(&mut buf[..#size])?;
+                    buf = &mut buf[#size..];
+                }
+            );
+        }
+        Storage::LengthOf(target) => {
+            let size = item.fixed_size().unwrap();
+            let ty = quote_type(&item.kind);
+            let target = decl.items.iter().find(|i| i.name == *target).unwrap();
+            let target_id = Ident::new(&target.name, Span::call_site());
+            let len = derived_len(target, &quote!(self.#target_id));
+            let encode = encode_primitive(
+                &item.kind,
+                item.byte_order,
+                quote!(len),
+                quote!(&mut buf[..#size]),
+            );
+            return quote!(
+                {
+                    let len = <#ty as ::core::convert::TryFrom<usize>>::try_from(#len)
+                        .map_err(|_| PError::OutOfRange)?;
+                    #encode?;
+                    buf = &mut buf[#size..];
+                }
+            );
+        }
+    }
+    let var_id = Ident::new(&item.name, Span::call_site());
+    match &item.condition {
+        None => encode_field(item, &quote!(self.#var_id)),
+        Some(condition) => {
+            let condition = condition_check(condition, true);
+            let encode = encode_field(item, &quote!(value));
+            quote!(
+                if #condition {
+                    match &self.#var_id {
+                        Some(value) => {
+                            #encode
+                        }
+                        None => return Err(PError::MissingField),
+                    }
+                }
+            )
+        }
+    }
+}
+
+// encodes `field`, which is a local instead of the struct field for optional items
+fn encode_field(item: &Item, field: &TokenStream) -> TokenStream {
+    if let Some(Array::Nested(outer, _)) = &item.array {
+        return encode_rows(item, outer, field);
+    }
     let var = if item.array.is_none() || item.kind == Type::CString {
-        quote!(#var_id)
+        quote!(#field)
     } else {
-        quote!(#var_id[idx])
+        quote!(#field[idx])
     };
     let size = if item.array.is_none() {
-        item_size(item)
+        field_size(item, field)
     } else {
         type_size(&item.kind, &var)
     };
-    let arr_sz_encode_fn = match item.byte_order {
-        Endian::Little => {
-            quote!(encode_le)
-        }
-        Endian::Big => {
-            quote!(encode_be)
-        }
-    };
     let mut encode_fn = match item.byte_order {
         Endian::Little => {
             quote!(encode_le)
@@ -262,48 +1095,110 @@ fn encode_item(item: &Item) -> TokenStream {
         encode_fn = quote!(encode_buf)
     }
     let single_item = match item.kind {
-        Type::String => {
-            // TODO implement for &[T] somehow so this doesn't need a clone?
-            quote!(
-                (self.#var.len() as u16).#arr_sz_encode_fn(&mut buf[..2])?;
-                buf = &mut buf[2..];
-                self.#var.as_bytes().to_vec().#encode_fn(&mut buf[..self.#var.len()])?;
-                buf = &mut buf[self.#var.len()..];
-            )
-        }
+        // TODO implement for &[T] somehow so this doesn't need a clone?
+        Type::String => match &item.string_format {
+            StringFormat::Prefixed(ty) => {
+                let prefix = encode_prefix(ty, item.byte_order, quote!(#var.len()));
+                quote!(
+                    #prefix
+                    #var.as_bytes().to_vec().#encode_fn(&mut buf[..#var.len()])?;
+                    buf = &mut buf[#var.len()..];
+                )
+            }
+            StringFormat::Fixed(width) => quote!(
+                if #var.len() > #width {
+                    return Err(PError::OutOfRange);
+                }
+                #var.as_bytes().to_vec().#encode_fn(&mut buf[..#var.len()])?;
+                buf[#var.len()..#width].fill(b' ');
+                buf = &mut buf[#width..];
+            ),
+        },
         Type::CString => {
             assert!(item.array.is_some(), "invalid non-array cstring");
             let arr = item.array.as_ref().unwrap();
             match arr {
                 Array::Constant(size) => quote!(
-                    assert!(self.#var.as_bytes().len() < #size, "cstring too large");
-                    self.#var.as_bytes().to_vec().#encode_fn(&mut buf[..self.#var.as_bytes().len()])?;
-                    for idx in self.#var.as_bytes().len()..#size {
+                    assert!(#var.as_bytes().len() < #size, "cstring too large");
+                    #var.as_bytes().to_vec().#encode_fn(&mut buf[..#var.as_bytes().len()])?;
+                    for idx in #var.as_bytes().len()..#size {
                         buf[idx] = 0;
                     }
                     buf = &mut buf[#size..];
                 ),
-                Array::Variable(size_name, _) => quote!(
-                    assert!(self.#var.as_bytes().len() < self.#size_name as usize, "cstring too large");
-                    self.#var.as_bytes().to_vec().#encode_fn(&mut buf[..self.#var.as_bytes().len()])?;
-                    for idx in self.#var.as_bytes().len()..(self.#size_name as usize) {
+                Array::Variable(..) | Array::Expression(_) | Array::Derived(_) => {
+                    // a derived length always has room for the cstring and its null byte
+                    let (len, size_check) = match arr {
+                        Array::Derived(_) => (derived_len(item, &var), quote!()),
+                        _ => (
+                            array_len(arr, true),
+                            quote!(assert!(#var.as_bytes().len() < tmp_len, "cstring too large");),
+                        ),
+                    };
+                    let bounds_check = bounds_check(item, quote!(tmp_len));
+                    quote!(
+                    let tmp_len = #len;
+                    #bounds_check
+                    #size_check
+                    #var.as_bytes().to_vec().#encode_fn(&mut buf[..#var.as_bytes().len()])?;
+                    for idx in #var.as_bytes().len()..tmp_len {
                         buf[idx] = 0;
                     }
-                    buf = &mut buf[(self.#size_name as usize)..];
-                ),
-                Array::Unknown(_) => quote!(
-                    assert!(self.#var.as_bytes().len() < 255, "cstring too large");
-                    buf[0] = (self.#var.as_bytes().len() + 1) as u8;
-                    buf = &mut buf[1..];
-                    self.#var.as_bytes().to_vec().#encode_fn(&mut buf[..self.#var.as_bytes().len()])?;
-                    buf[self.#var.as_bytes().len()] = 0;
-                    buf = &mut buf[(self.#var.as_bytes().len() + 1)..];
-                ),
+                    buf = &mut buf[tmp_len..];
+                    )
+                }
+                Array::Unknown(ty) => {
+                    let prefix =
+                        encode_prefix(ty, item.byte_order, quote!(#var.as_bytes().len() + 1));
+                    let bounds_check = bounds_check(item, quote!(#var.as_bytes().len() + 1));
+                    quote!(
+                    #bounds_check
+                    #prefix
+                    #var.as_bytes().to_vec().#encode_fn(&mut buf[..#var.as_bytes().len()])?;
+                    buf[#var.as_bytes().len()] = 0;
+                    buf = &mut buf[(#var.as_bytes().len() + 1)..];
+                    )
+                }
+                Array::Rest | Array::Nested(..) => unreachable!(),
             }
         }
+        Type::Union(_) => {
+            let tag = Ident::new(item.tag.as_ref().unwrap(), Span::call_site());
+            quote!(
+                if #var.tag() != self.#tag as i128 {
+                    return Err(PError::TagMismatch);
+                }
+                #var.encode_buf(&mut buf[..#size])?;
+                buf = &mut buf[#size..];
+            )
+        }
+        Type::VarU32 | Type::VarU64 | Type::VarI64 => quote!(
+            let size = #var.encode_var(buf)?;
+            buf = &mut buf[size..];
+        ),
+        Type::Fixed { frac_bits, .. } => {
+            let raw = item.kind.raw_type().unwrap();
+            let raw_ty = quote_type(&raw);
+            let saturate = item.saturate;
+            let encode = encode_primitive(
+                &raw,
+                item.byte_order,
+                quote!(raw),
+                quote!(&mut buf[..#size]),
+            );
+            quote!(
+                let raw: #raw_ty = #var.to_fixed(#frac_bits, #saturate)?;
+                #encode?;
+                buf = &mut buf[#size..];
+            )
+        }
         _ => {
+            let encode = match item.kind {
+                Type::User(_) => quote!(#var.#encode_fn(&mut buf[..#size])),
+                _ => encode_primitive(&item.kind, item.byte_order, var, quote!(&mut buf[..#size])),
+            };
             quote!(
-                self.#var.#encode_fn(&mut buf[..#size])?;
+                #encode?;
                 buf = &mut buf[#size..];
             )
         }
@@ -315,29 +1210,50 @@ fn encode_item(item: &Item) -> TokenStream {
             Some(Array::Constant(_)) => {
                 quote!()
             }
-            Some(Array::Variable(name, _)) => {
-                let arr_len = Ident::new(name, Span::call_site());
+            Some(arr @ (Array::Variable(..) | Array::Expression(_))) => {
+                let len = array_len(arr, true);
+                let bounds_check = bounds_check(item, quote!(tmp_len));
                 // NOTE: in this case, we already encoded the length previously, so
-                // just assert the vec length matches
+                // just check the vec length matches
                 quote!(
-                    assert!(self.#var_id.len() == self.#arr_len as usize, "todo improve errors");
+                    let tmp_len = #len;
+                    #bounds_check
+                    if #field.len() != tmp_len {
+                        return Err(PError::LengthMismatch);
+                    }
+                )
+            }
+            // the length written earlier was taken from the vec itself
+            Some(Array::Derived(_)) => bounds_check(item, quote!(#field.len())),
+            Some(Array::Unknown(ty)) if item.prefix_mode == PrefixMode::Bytes => {
+                let bounds_check = bounds_check(item, quote!(#field.len()));
+                let elem_size = type_size(&item.kind, &quote!(elem));
+                let prefix = encode_prefix(
+                    ty,
+                    item.byte_order,
+                    quote!(#field.iter().map(|elem| #elem_size).sum::<usize>()),
+                );
+                quote!(
+                    #bounds_check
+                    #prefix
                 )
             }
             Some(Array::Unknown(ty)) => {
-                let arr_ty = quote_type(ty);
-                let arr_sz = type_size(ty, &quote!(compile_error!("SHOULD NEVER HAPPEN")));
+                let prefix = encode_prefix(ty, item.byte_order, quote!(#field.len()));
+                let bounds_check = bounds_check(item, quote!(#field.len()));
                 quote!(
-                    (self.#var_id.len() as #arr_ty).#arr_sz_encode_fn(&mut buf[..#arr_sz])?;
-                    buf = &mut buf[#arr_sz..];
+                    #bounds_check
+                    #prefix
                 )
             }
+            Some(Array::Rest) => bounds_check(item, quote!(#field.len())),
             _ => {
                 unreachable!()
             }
         };
         quote!(
             #prefix_len
-            for idx in 0..self.#var_id.len() {
+            for idx in 0..#field.len() {
                 #single_item
             }
         )
@@ -345,7 +1261,7 @@ fn encode_item(item: &Item) -> TokenStream {
 }
 
 fn encode_fn(decl: &Struct) -> TokenStream {
-    let items = decl.items.iter().map(encode_item);
+    let items = decl.items.iter().map(|item| encode_item(item, decl));
     quote!(
         fn encode(&self) -> Result<Vec<u8>> {
             let mut res = vec![0; self.size()];
@@ -364,7 +1280,7 @@ fn encode_fn(decl: &Struct) -> TokenStream {
 
 fn type_size(ty: &Type, var: &TokenStream) -> TokenStream {
     match ty {
-        Type::U8 | Type::Byte => {
+        Type::U8 | Type::Byte | Type::Bool => {
             quote!(1)
         }
         Type::U16 => {
@@ -388,87 +1304,141 @@ fn type_size(ty: &Type, var: &TokenStream) -> TokenStream {
         Type::I64 => {
             quote!(8)
         }
+        Type::U24 | Type::I24 => {
+            quote!(3)
+        }
+        Type::U48 => {
+            quote!(6)
+        }
+        Type::U128 | Type::I128 => {
+            quote!(16)
+        }
         Type::F32 => {
             quote!(4)
         }
         Type::F64 => {
             quote!(8)
         }
+        Type::Fixed { .. } => {
+            let size = ty.fixed_size().unwrap();
+            quote!(#size)
+        }
         // These are all variable sized types
+        Type::VarU32 | Type::VarU64 | Type::VarI64 => {
+            quote!(#var.var_len())
+        }
         // TODO: treat them differently so it's easier to tell when a type is what
         Type::String => {
-            quote!(self.#var.len())
+            quote!(#var.len())
         }
         Type::CString => {
-            // quote!(self.#var.as_bytes().len())
+            // quote!(#var.as_bytes().len())
             // TODO: technically this is incorrect, the size is whatever is set
             // in the array field -- not sure if worth passing it in here
             // or just asserting that this never gets used
             quote!(compile_error!("OOPS LOL"))
         }
-        Type::User(_) => {
-            quote!(self.#var.size())
+        Type::User(_) | Type::Union(_) => {
+            quote!(#var.size())
         }
+        Type::Enum(_, kind) => type_size(kind, var),
     }
 }
 
 fn item_size(item: &Item) -> TokenStream {
-    let var = Ident::new(item.name, Span::call_site());
-    let var = quote!(#var);
-    let mut size = type_size(&item.kind, &var);
-    if item.kind == Type::String {
-        // NOTE: this is always prefixed with u16 length, to keep implementation parity
-        size = quote!(#size + 2);
-    }
-    // TODO: refactor this bit
-    let prefix_len = match &item.array {
-        Some(Array::Unknown(ty)) => {
-            let arr_sz = type_size(ty, &quote!(compile_error!("SHOULD NEVER HAPPEN")));
-            quote!(#arr_sz + )
-        }
-        _ => {
-            quote!()
+    if item.storage != Storage::Field {
+        let size = item.fixed_size().unwrap();
+        return quote!(#size);
+    }
+    let var_id = Ident::new(&item.name, Span::call_site());
+    match &item.condition {
+        None => field_size(item, &quote!(self.#var_id)),
+        Some(condition) => {
+            let condition = condition_check(condition, false);
+            let size = field_size(item, &quote!(value));
+            quote!((if #condition {
+                self.#var_id.as_ref().map_or(0, |value| #size)
+            } else {
+                0
+            }))
         }
+    }
+}
+
+// size of `field`, which is a local instead of the struct field for optional items
+fn field_size(item: &Item, field: &TokenStream) -> TokenStream {
+    let var = field;
+    if let Some(Array::Nested(outer, _)) = &item.array {
+        let row = item.row().unwrap();
+        let row_size = field_size(&row, &quote!(row));
+        let rows_size = quote!(#var.iter().map(|row| #row_size).sum::<usize>());
+        return match &**outer {
+            Array::Unknown(ty) => {
+                let prefix = prefix_size(ty, quote!(#var.len()));
+                quote!((#prefix + #rows_size))
+            }
+            _ => quote!((#rows_size)),
+        };
+    }
+    let elem_size = |var: &TokenStream| match item.kind {
+        Type::String => string_size(item, var),
+        _ => type_size(&item.kind, var),
     };
-    match &item.array {
-        Some(arr) => {
-            // this handles variable-sized elements; is there a better way?
-            match item.kind {
-                Type::User(_) => {
-                    quote!((#prefix_len self.#var.iter().map(|i| i.size()).sum::<usize>()))
-                }
-                Type::CString => match arr {
-                    Array::Constant(size) => quote!(#size),
-                    Array::Variable(size_name, _) => quote!((self.#size_name as usize)),
-                    Array::Unknown(_) => quote!((self.#var.as_bytes().len() + 2)), // Remember: + 1 for size, + 1 for null
-                },
-                Type::String => {
-                    quote!((#prefix_len self.#var.iter().map(|i| i.len() + 2).sum::<usize>()))
-                }
-                _ => {
-                    // Constant-sized elements
-                    match arr {
-                        Array::Constant(sz) => {
-                            quote!((#sz * (#size)))
-                        }
-                        Array::Variable(idx, _) => {
-                            let idx = Ident::new(idx, Span::call_site());
-                            quote!((self.#idx as usize * (#size)))
-                        }
-                        Array::Unknown(ty) => {
-                            // TODO: validate if this is correct (i feel like it is)
-                            let var = Ident::new(item.name, Span::call_site());
-                            let arr_sz =
-                                type_size(ty, &quote!(compile_error!("SHOULD NEVER HAPPEN")));
-                            quote!(self.#var.len() * (#size) + #arr_sz)
-                        }
-                    }
-                }
+    let size = elem_size(var);
+    let arr = match &item.array {
+        Some(arr) => arr,
+        None => return quote!(#size),
+    };
+    if item.kind == Type::CString {
+        return match arr {
+            Array::Constant(size) => quote!(#size),
+            Array::Variable(..) | Array::Expression(_) => array_len(arr, false),
+            Array::Derived(_) => derived_len(item, var),
+            // Remember: + 1 for null
+            Array::Unknown(ty) => {
+                let prefix = prefix_size(ty, quote!(#var.as_bytes().len() + 1));
+                quote!((#prefix + #var.as_bytes().len() + 1))
             }
+            Array::Rest | Array::Nested(..) => unreachable!(),
+        };
+    }
+    let elems_size = match (item.kind.fixed_size(), arr) {
+        // this handles variable-sized elements; is there a better way?
+        (None, _) => {
+            let size = elem_size(&quote!(i));
+            quote!(#var.iter().map(|i| #size).sum::<usize>())
         }
-        None => {
-            quote!(#size)
+        (Some(_), Array::Constant(sz)) => quote!((#sz * (#size))),
+        (Some(_), Array::Variable(..) | Array::Expression(_)) => {
+            let len = array_len(arr, false);
+            quote!((#len * (#size)))
+        }
+        (Some(_), Array::Unknown(_) | Array::Derived(_) | Array::Rest) => {
+            quote!((#var.len() * (#size)))
+        }
+        (Some(_), Array::Nested(..)) => unreachable!(),
+    };
+    match arr {
+        Array::Unknown(ty) => {
+            let len = match item.prefix_mode {
+                PrefixMode::Count => quote!(#var.len()),
+                PrefixMode::Bytes => elems_size.clone(),
+            };
+            let prefix = prefix_size(ty, len);
+            quote!((#prefix + #elems_size))
         }
+        _ => elems_size,
+    }
+}
+
+// strings are either prefixed with their length or padded to a fixed width
+fn string_size(item: &Item, var: &TokenStream) -> TokenStream {
+    match &item.string_format {
+        StringFormat::Prefixed(ty) => {
+            let prefix = prefix_size(ty, quote!(#var.len()));
+            quote!((#var.len() + #prefix))
+        }
+        StringFormat::Fixed(width) => quote!(#width),
     }
 }
 
@@ -482,9 +1452,37 @@ fn size_fn(decl: &Struct) -> TokenStream {
 }
 
 fn item_default(item: &Item) -> TokenStream {
-    let name = Ident::new(item.name, Span::call_site());
+    if item.storage == Storage::Bitfield {
+        let names = item
+            .bits
+            .iter()
+            .map(|bitfield| Ident::new(&bitfield.name, Span::call_site()));
+        return quote!(#(#names: 0,)*);
+    }
+    if item.storage != Storage::Field {
+        return quote!();
+    }
+    let name = Ident::new(&item.name, Span::call_site());
+    if item.condition.is_some() {
+        return quote!(#name: None,);
+    }
+    let def = default_value(item);
+    quote!(#name: #def,)
+}
+
+fn default_value(item: &Item) -> TokenStream {
+    if let Some(Array::Nested(outer, _)) = &item.array {
+        let row = default_value(&item.row().unwrap());
+        return match **outer {
+            Array::Constant(sz) => {
+                let rows = (0..sz).map(|_| &row);
+                quote!([#(#rows),*])
+            }
+            _ => quote!(vec![]),
+        };
+    }
     let def = quote!(Default::default());
-    let def = if item.kind == Type::CString {
+    if item.kind == Type::CString {
         def
     } else {
         match item.array {
@@ -501,8 +1499,7 @@ fn item_default(item: &Item) -> TokenStream {
             }
             None => def,
         }
-    };
-    quote!(#name: #def,)
+    }
 }
 
 fn quote_type(ty: &Type) -> TokenStream {
@@ -513,37 +1510,53 @@ fn quote_type(ty: &Type) -> TokenStream {
         Type::U16 => {
             quote!(u16)
         }
-        Type::U32 => {
+        Type::U32 | Type::U24 | Type::VarU32 => {
             quote!(u32)
         }
-        Type::U64 => {
+        Type::U64 | Type::U48 | Type::VarU64 => {
             quote!(u64)
         }
+        Type::U128 => {
+            quote!(u128)
+        }
         Type::I8 => {
             quote!(i8)
         }
         Type::I16 => {
             quote!(i16)
         }
-        Type::I32 => {
+        Type::I32 | Type::I24 => {
             quote!(i32)
         }
-        Type::I64 => {
+        Type::I64 | Type::VarI64 => {
             quote!(i64)
         }
+        Type::I128 => {
+            quote!(i128)
+        }
         Type::F32 => {
             quote!(f32)
         }
         Type::F64 => {
             quote!(f64)
         }
+        // f32 holds every 16 bit value exactly
+        Type::Fixed { .. } if ty.fixed_size() <= Some(2) => {
+            quote!(f32)
+        }
+        Type::Fixed { .. } => {
+            quote!(f64)
+        }
+        Type::Bool => {
+            quote!(bool)
+        }
         Type::String => {
             quote!(String)
         }
         Type::CString => {
             quote!(CString)
         }
-        Type::User(user_ty) => {
+        Type::User(user_ty) | Type::Enum(user_ty, _) | Type::Union(user_ty) => {
             let user_ty = Ident::new(user_ty, Span::call_site());
             quote!(#user_ty)
         }
@@ -551,9 +1564,35 @@ fn quote_type(ty: &Type) -> TokenStream {
 }
 
 fn item_declaration(item: &Item) -> TokenStream {
-    let name = Ident::new(item.name, Span::call_site());
+    if item.storage == Storage::Bitfield {
+        let ty = quote_type(&item.kind);
+        let names = item
+            .bits
+            .iter()
+            .map(|bitfield| Ident::new(&bitfield.name, Span::call_site()));
+        return quote!(#(pub #names: #ty,)*);
+    }
+    if item.storage != Storage::Field {
+        return quote!();
+    }
+    let name = Ident::new(&item.name, Span::call_site());
+    let ty = item_type(item);
+    if item.condition.is_some() {
+        return quote!(pub #name: Option<#ty>,);
+    }
+    quote!(pub #name: #ty,)
+}
+
+fn item_type(item: &Item) -> TokenStream {
+    if let Some(Array::Nested(outer, _)) = &item.array {
+        let row = item_type(&item.row().unwrap());
+        return match **outer {
+            Array::Constant(sz) => quote!([#row; #sz]),
+            _ => quote!(Vec<#row>),
+        };
+    }
     let ty = quote_type(&item.kind);
-    let ty = if item.kind == Type::CString {
+    if item.kind == Type::CString {
         quote!(#ty)
     } else {
         match item.array {
@@ -565,6 +1604,5 @@ fn item_declaration(item: &Item) -> TokenStream {
             }
             None => ty,
         }
-    };
-    quote!(pub #name: #ty,)
+    }
 }
@@ -0,0 +1,59 @@
+use pest::error::LineColLocation;
+use pstruct::parser::{Diagnostics, Error};
+use std::path::Path;
+
+/// Renders every error codespan style, each message followed by the line it points at;
+/// `path` is used for errors that don't come from an included file
+pub fn render(diagnostics: &Diagnostics, path: &Path) -> String {
+    let mut out = String::new();
+    for err in &diagnostics.0 {
+        out.push_str(&render_error(err, path));
+        out.push('\n');
+    }
+    let count = diagnostics.0.len();
+    out.push_str(&format!(
+        "{} error{} found",
+        count,
+        if count == 1 { "" } else { "s" }
+    ));
+    out
+}
+
+fn render_error(err: &Error, path: &Path) -> String {
+    let ((line, col), end) = match err.line_col {
+        LineColLocation::Pos(pos) => (pos, None),
+        LineColLocation::Span(start, end) => (start, Some(end)),
+    };
+    let text = err.line().trim_end_matches(['\r', '\n']);
+    // spans over several lines are only underlined up to the end of the first one
+    let width = match end {
+        Some((end_line, end_col)) if end_line == line => end_col.saturating_sub(col),
+        Some(_) => text.chars().count().saturating_sub(col - 1),
+        None => 1,
+    };
+    // tabs are kept so the underline stays aligned with the text above it
+    let padding: String = text
+        .chars()
+        .take(col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let path = match err.path() {
+        Some(path) => path.to_string(),
+        None => path.display().to_string(),
+    };
+    let gutter = " ".repeat(line.to_string().len());
+    format!(
+        "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+        err.variant.message(),
+        gutter,
+        path,
+        line,
+        col,
+        gutter,
+        line,
+        text,
+        gutter,
+        padding,
+        "^".repeat(width.max(1))
+    )
+}
@@ -1,8 +1,11 @@
 use io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{error, fmt, fs, io, process};
 
-use pstruct::parser;
+use pstruct::parser::{self, Diagnostics};
+use pstruct::types::File;
+
+mod diagnostics;
 
 use gumdrop::Options;
 use walkdir::WalkDir;
@@ -148,6 +151,21 @@ fn main() -> Result<(), Error> {
     Ok(())
 }
 
+// every error is printed as found, the returned one only says which file had them
+fn parse_spec(path: &Path) -> Result<File, Error> {
+    let file_contents = fs::read_to_string(path)?;
+    let parsed = parser::load_includes(&file_contents, path)
+        .map_err(Diagnostics::from)
+        .and_then(|sources| parser::parse_file_with_includes(&file_contents, path, &sources));
+    match parsed {
+        Ok(file) => Ok(file),
+        Err(errors) => {
+            eprintln!("{}", diagnostics::render(&errors, path));
+            Err(format!("could not parse {}", path.display()).into())
+        }
+    }
+}
+
 fn render_go_files(opts: &CliOptions, files: &[PathBuf]) -> Result<(), Error> {
     let mut rendered_files = vec![];
 
@@ -165,19 +183,19 @@ fn render_go_files(opts: &CliOptions, files: &[PathBuf]) -> Result<(), Error> {
         if output.exists() {
             let file = fs::read_to_string(&output)?;
             if !file.starts_with(pstruct_go::GENERATED_HEADER) {
-                return Err(Error::from(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{} does not seem to be autogenerated -- aborting",
-                        output.to_string_lossy()
-                    ),
-                )));
+                return Err(Error::from(io::Error::other(format!(
+                    "{} does not seem to be autogenerated -- aborting",
+                    output.to_string_lossy()
+                ))));
             }
         }
 
         println!("{} -> {}", file.to_str().unwrap(), output.to_string_lossy());
-        let file_contents = fs::read_to_string(file)?;
-        let file = parser::parse_file(file_contents.as_str())?;
+        let path = file;
+        let file = parse_spec(path)?;
+        if let Err(msg) = pstruct_go::check_file(&file) {
+            return Err(format!("{}: {}", path.display(), msg).into());
+        }
         let rendered_file = pstruct_go::render_file(&file);
         if opts.print_output {
             println!("{}", rendered_file);
@@ -223,19 +241,15 @@ fn render_rust_files(opts: &CliOptions, files: &[PathBuf]) -> Result<(), Error>
         if output.exists() {
             let file = fs::read_to_string(&output)?;
             if !file.starts_with(pstruct_rust::GENERATED_HEADER) {
-                return Err(Error::from(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{} does not seem to be autogenerated -- aborting",
-                        output.to_string_lossy()
-                    ),
-                )));
+                return Err(Error::from(io::Error::other(format!(
+                    "{} does not seem to be autogenerated -- aborting",
+                    output.to_string_lossy()
+                ))));
             }
         }
 
         println!("{} -> {}", file.to_str().unwrap(), output.to_string_lossy());
-        let file_contents = fs::read_to_string(file)?;
-        let file = parser::parse_file(file_contents.as_str())?;
+        let file = parse_spec(file)?;
         let mut rendered_file = pstruct_rust::render_file(&file);
 
         if !opts.disable_auto_format {
@@ -1,116 +1,595 @@
 #![allow(clippy::upper_case_acronyms)]
 
-use pest::error::ErrorVariant;
+use pest::error::{ErrorVariant, InputLocation};
 use pest::iterators::Pair;
+use pest::pratt_parser::{Assoc, Op, PrattParser};
 use pest::Parser;
 use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs};
 
 use crate::types::*;
 
 // TODO: cstrings (and maybe arrays too?) do a "double read" of fields; this feels wrong, it should be read once earlier in the loop
 // I'm not sure that I'm handling this right for regular arrays like [x]u8
 
-pub type Error = pest::error::Error<Rule>;
+/// Boxed since pest errors are large and go through every parsing function
+pub type Error = Box<pest::error::Error<Rule>>;
 
 fn make_error<S: Into<String>>(msg: S, span: pest::Span) -> Error {
-    Error::new_from_span(
+    Box::new(pest::error::Error::new_from_span(
         ErrorVariant::CustomError {
             message: msg.into(),
         },
         span,
-    )
+    ))
+}
+
+// errors found after parsing point at what they're about through the spans kept in the AST
+fn make_error_at<S: Into<String>>(msg: S, span: Span, input: &str) -> Error {
+    make_error(msg, pest::Span::new(input, span.start, span.end).unwrap())
+}
+
+fn span_of(span: pest::Span) -> Span {
+    Span {
+        start: span.start(),
+        end: span.end(),
+    }
+}
+
+/// Every error found in a file, in the order they appear in it
+#[derive(Debug)]
+pub struct Diagnostics(pub Vec<Error>);
+
+impl Diagnostics {
+    fn new(mut errors: Vec<Error>) -> Self {
+        // errors from included files carry their path, so they end up grouped by file
+        errors.sort_by_key(|err| {
+            let start = match err.location {
+                InputLocation::Pos(pos) => pos,
+                InputLocation::Span((start, _)) => start,
+            };
+            (err.path().map(str::to_string), start)
+        });
+        Diagnostics(errors)
+    }
+}
+
+impl From<Error> for Diagnostics {
+    fn from(err: Error) -> Self {
+        Diagnostics(vec![err])
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, err) in self.0.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+// keywords of the languages code is generated for, which can't be used as names there
+const RUST_KEYWORDS: &[&str] = &[
+    "Self", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if",
+    "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+const GO_KEYWORDS: &[&str] = &[
+    "break",
+    "case",
+    "chan",
+    "const",
+    "continue",
+    "default",
+    "defer",
+    "else",
+    "fallthrough",
+    "for",
+    "func",
+    "go",
+    "goto",
+    "if",
+    "import",
+    "interface",
+    "map",
+    "package",
+    "range",
+    "return",
+    "select",
+    "struct",
+    "switch",
+    "type",
+    "var",
+];
+
+fn check_identifier(name: &str, span: pest::Span) -> Result<(), Error> {
+    let languages = match (RUST_KEYWORDS.contains(&name), GO_KEYWORDS.contains(&name)) {
+        (true, true) => "Rust and Go",
+        (true, false) => "Rust",
+        (false, true) => "Go",
+        (false, false) => return Ok(()),
+    };
+    Err(make_error(
+        format!("{} is a reserved word in {}", name, languages),
+        span,
+    ))
+}
+
+// names the generated Rust module uses from the prelude and its imports, which a type
+// defined there would shadow
+const RUST_TYPE_NAMES: &[&str] = &[
+    "CString",
+    "Default",
+    "Fixed",
+    "FixedRaw",
+    "Narrow",
+    "Option",
+    "PError",
+    "Primitive",
+    "Pstruct",
+    "Result",
+    "String",
+    "Varint",
+    "Vec",
+];
+// packages the generated Go files import and predeclared names they use, which a type
+// defined there would shadow
+const GO_TYPE_NAMES: &[&str] = &[
+    "binary", "bool", "byte", "bytes", "error", "float32", "float64", "int", "int16", "int32",
+    "int64", "int8", "len", "make", "ps", "string", "uint16", "uint32", "uint64", "uint8",
+];
+// methods of the generated Go structs, which share a namespace with their fields
+const GO_FIELD_NAMES: &[&str] = &["Decode", "DecodeStream", "Encode", "EncodeStream"];
+
+fn check_type_name(name: &str, span: pest::Span) -> Result<(), Error> {
+    check_identifier(name, span)?;
+    if RUST_TYPE_NAMES.contains(&name) {
+        return Err(make_error(
+            format!("{} is already used by the generated Rust code", name),
+            span,
+        ));
+    }
+    if GO_TYPE_NAMES.contains(&name) {
+        return Err(make_error(
+            format!("{} is already used by the generated Go code", name),
+            span,
+        ));
+    }
+    Ok(())
+}
+
+fn check_field_name(name: &str, span: pest::Span) -> Result<(), Error> {
+    check_identifier(name, span)?;
+    if GO_FIELD_NAMES.contains(&name) {
+        return Err(make_error(
+            format!("{} is a method of the generated Go structs", name),
+            span,
+        ));
+    }
+    Ok(())
 }
 
 #[derive(Parser)]
 #[grammar = "struct.pest"]
 struct StructParser;
 
-struct FileOptions<'a> {
-    array_size_type: Type<'a>,
+#[derive(Clone)]
+struct FileOptions {
+    // unset so strings can keep their own default
+    array_size_type: Option<Type>,
     endian: Endian,
+    strict: bool,
+    saturate: bool,
 
     scope_name: String,
-    raw_imports: Vec<&'a str>,
+    raw_imports: Vec<String>,
+    go_import: Option<String>,
 }
 
 struct ItemOptions<'a> {
-    array_size_type: Option<Type<'a>>,
+    array_size_type: Option<Type>,
     endian: Endian,
     type_alias: Option<&'a str>,
+    tag: Option<&'a str>,
+    max_array_size: Option<usize>,
+    length_of: Option<&'a str>,
+    prefix_mode: Option<PrefixMode>,
+    width: Option<usize>,
+    strict: Option<bool>,
+    saturate: Option<bool>,
 }
 
 // CLEANUP: implement Default instead of these?
-fn default_file_options() -> FileOptions<'static> {
+fn default_file_options() -> FileOptions {
     FileOptions {
-        array_size_type: Type::U8,
+        array_size_type: None,
         scope_name: "main".to_owned(),
         endian: Endian::Little,
+        strict: false,
+        saturate: false,
         raw_imports: vec![],
+        go_import: None,
     }
 }
 
-fn default_item_options(file_options: &FileOptions<'_>) -> ItemOptions<'static> {
+fn default_item_options(file_options: &FileOptions) -> ItemOptions<'static> {
     ItemOptions {
         array_size_type: None,
         endian: file_options.endian,
         type_alias: None,
+        tag: None,
+        max_array_size: None,
+        length_of: None,
+        prefix_mode: None,
+        width: None,
+        strict: None,
+        saturate: None,
     }
 }
 
 // unwraps look spooky but the grammar says it's fine
-fn parse_definition<'a>(
-    pair: Pair<'a, Rule>,
-    file_options: &FileOptions<'a>,
-) -> Result<Struct<'a>, Error> {
+// items with errors are left out of the struct and their errors added to `errors`, so
+// that the rest of the items are still checked
+fn parse_definition(
+    pair: Pair<'_, Rule>,
+    constants: &[Constant],
+    file_options: &FileOptions,
+    errors: &mut Vec<Error>,
+) -> Struct {
     assert!(pair.as_rule() == Rule::definition, "expected definition");
     let mut inner_rules = pair.into_inner();
     // struct_name -> identifier -> as_str
-    let name = inner_rules
-        .next()
-        .unwrap()
-        .into_inner()
-        .next()
-        .unwrap()
-        .as_str();
+    let name_pair = inner_rules.next().unwrap().into_inner().next().unwrap();
+    let name = name_pair.as_str();
+    if let Err(err) = check_type_name(name, name_pair.as_span()) {
+        errors.push(err);
+    }
+    let mut item_pairs = inner_rules.peekable();
+    let file_options = match item_pairs.next_if(|p| p.as_rule() == Rule::struct_options) {
+        Some(options_pair) => match parse_struct_options(options_pair, file_options) {
+            Ok(options) => options,
+            Err(err) => {
+                errors.push(err);
+                file_options.clone()
+            }
+        },
+        None => file_options.clone(),
+    };
+    let file_options = &file_options;
     let mut items: Vec<Item> = vec![];
     // all other rules are for items
-    for item_pair in inner_rules {
-        let next_item = parse_item(item_pair, &items, file_options)?;
+    for item_pair in item_pairs {
+        if matches!(
+            items.last(),
+            Some(Item {
+                array: Some(Array::Rest),
+                ..
+            })
+        ) {
+            errors.push(make_error(
+                "only the last item can hold the rest of the input",
+                item_pair.as_span(),
+            ));
+        }
+        let err_span = item_pair.as_span();
+        let parsed = match item_pair.as_rule() {
+            Rule::bitfield_item => parse_bitfield_item(item_pair, constants, file_options),
+            _ => parse_item(item_pair, &items, constants, file_options),
+        };
+        let next_item = match parsed {
+            Ok(item) => item,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+        // bitfield members become fields of their own, the bitfield itself has no name
+        let duplicate = field_names(&next_item)
+            .into_iter()
+            .find(|item_name| items.iter().flat_map(field_names).any(|n| n == *item_name));
+        if let Some(item_name) = duplicate {
+            errors.push(make_error(
+                format!("{}.{}: field defined twice", name, item_name),
+                err_span,
+            ));
+            continue;
+        }
         items.push(next_item);
     }
-    Ok(Struct { name, items })
+    let options = StructOptions {
+        endian: file_options.endian,
+        array_size_type: file_options.array_size_type.clone(),
+        strict: file_options.strict,
+        saturate: file_options.saturate,
+    };
+    Struct {
+        name: name.to_string(),
+        span: span_of(name_pair.as_span()),
+        options,
+        items,
+    }
+}
+
+// names an item takes up in the generated struct
+fn field_names(item: &Item) -> Vec<&str> {
+    match item.storage {
+        Storage::Padding => vec![],
+        Storage::Bitfield => item.bits.iter().map(|b| b.name.as_str()).collect(),
+        _ => vec![item.name.as_str()],
+    }
 }
 
-fn parse_extern_definition(pair: Pair<Rule>) -> Result<&str, Error> {
+fn parse_extern_definition(pair: Pair<'_, Rule>) -> Result<&str, Error> {
     assert!(
         pair.as_rule() == Rule::extern_definition,
         "expected extern definition"
     );
     let mut inner_rules = pair.into_inner();
-    let name = inner_rules.next().unwrap().as_str();
-    Ok(name)
+    let name_pair = inner_rules.next().unwrap();
+    check_type_name(name_pair.as_str(), name_pair.as_span())?;
+    Ok(name_pair.as_str())
+}
+
+fn parse_const_definition(pair: Pair<'_, Rule>) -> Result<Constant, Error> {
+    assert!(
+        pair.as_rule() == Rule::const_definition,
+        "expected const definition"
+    );
+    let mut inner_rules = pair.into_inner();
+    let name_pair = inner_rules.next().unwrap();
+    let name = name_pair.as_str();
+    check_identifier(name, name_pair.as_span())?;
+    let value_pair = inner_rules.next().unwrap();
+    let err_span = value_pair.as_span();
+    let value = parse_number(value_pair)?;
+    match usize::try_from(value) {
+        Ok(value) => Ok(Constant {
+            name: name.to_string(),
+            value,
+        }),
+        Err(_) => Err(make_error(
+            format!("{}: constants must be non-negative", name),
+            err_span,
+        )),
+    }
+}
+
+fn parse_integer(text: &str) -> Option<i128> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i128::from_str_radix(bin, 2)
+    } else {
+        digits.parse::<i128>()
+    };
+    match value {
+        Ok(value) if negative => Some(-value),
+        Ok(value) => Some(value),
+        Err(_) => None,
+    }
+}
+
+fn parse_number(pair: Pair<'_, Rule>) -> Result<i128, Error> {
+    assert!(pair.as_rule() == Rule::number, "expected number");
+    match parse_integer(pair.as_str()) {
+        Some(value) => Ok(value),
+        None => Err(make_error(
+            format!("number {} is too large", pair.as_str()),
+            pair.as_span(),
+        )),
+    }
+}
+
+// the range of values representable by an integer type; 128-bit integers are left out
+// since lengths, tags and expressions never get that wide
+fn integer_range(kind: &Type) -> Option<(i128, i128)> {
+    match kind {
+        Type::U8 => Some((0, u8::MAX as i128)),
+        Type::U16 => Some((0, u16::MAX as i128)),
+        Type::U24 => Some((0, (1 << 24) - 1)),
+        Type::I24 => Some((-(1 << 23), (1 << 23) - 1)),
+        Type::U48 => Some((0, (1 << 48) - 1)),
+        Type::U32 => Some((0, u32::MAX as i128)),
+        Type::U64 => Some((0, u64::MAX as i128)),
+        Type::I8 => Some((i8::MIN as i128, i8::MAX as i128)),
+        Type::I16 => Some((i16::MIN as i128, i16::MAX as i128)),
+        Type::I32 => Some((i32::MIN as i128, i32::MAX as i128)),
+        Type::I64 => Some((i64::MIN as i128, i64::MAX as i128)),
+        Type::VarU32 => Some((0, u32::MAX as i128)),
+        Type::VarU64 => Some((0, u64::MAX as i128)),
+        Type::VarI64 => Some((i64::MIN as i128, i64::MAX as i128)),
+        _ => None,
+    }
+}
+
+fn parse_enum_definition(pair: Pair<'_, Rule>) -> Result<Enum, Error> {
+    assert!(
+        pair.as_rule() == Rule::enum_definition,
+        "expected enum definition"
+    );
+    let mut inner_rules = pair.into_inner();
+    // enum_name -> identifier ~ identifier
+    let mut name_rules = inner_rules.next().unwrap().into_inner();
+    let name_pair = name_rules.next().unwrap();
+    let name = name_pair.as_str();
+    check_type_name(name, name_pair.as_span())?;
+    let kind_pair = name_rules.next().unwrap();
+    let kind = parse_item_type(kind_pair.as_str());
+    if kind.is_varint() {
+        return Err(make_error(
+            "enum type cannot be a varint",
+            kind_pair.as_span(),
+        ));
+    }
+    let (min, max) = match integer_range(&kind) {
+        Some(range) => range,
+        None => {
+            return Err(make_error(
+                "enum type must be integer valued",
+                kind_pair.as_span(),
+            ))
+        }
+    };
+
+    let mut variants: Vec<Variant> = vec![];
+    // all other rules are for variants
+    for variant_pair in inner_rules {
+        let err_span = variant_pair.as_span();
+        let mut variant_inner = variant_pair.into_inner();
+        let variant_name = variant_inner.next().unwrap().as_str();
+        check_identifier(variant_name, err_span)?;
+        let value = parse_number(variant_inner.next().unwrap())?;
+        if value < min || value > max {
+            return Err(make_error(
+                format!(
+                    "{}::{}: value {} does not fit in {}",
+                    name,
+                    variant_name,
+                    value,
+                    kind_pair.as_str()
+                ),
+                err_span,
+            ));
+        }
+        if variants.iter().any(|v| v.name == variant_name) {
+            return Err(make_error(
+                format!("{}::{}: variant defined twice", name, variant_name),
+                err_span,
+            ));
+        }
+        if let Some(other) = variants.iter().find(|v| v.value == value) {
+            return Err(make_error(
+                format!(
+                    "{}::{}: value {} already used by {}::{}",
+                    name, variant_name, value, name, other.name
+                ),
+                err_span,
+            ));
+        }
+        variants.push(Variant {
+            name: variant_name.to_string(),
+            value,
+        });
+    }
+    Ok(Enum {
+        name: name.to_string(),
+        span: span_of(name_pair.as_span()),
+        kind,
+        variants,
+    })
+}
+
+fn parse_union_definition(pair: Pair<'_, Rule>) -> Result<Union, Error> {
+    assert!(
+        pair.as_rule() == Rule::union_definition,
+        "expected union definition"
+    );
+    let mut inner_rules = pair.into_inner();
+    // union_name -> identifier
+    let name_pair = inner_rules.next().unwrap().into_inner().next().unwrap();
+    let name = name_pair.as_str();
+    check_type_name(name, name_pair.as_span())?;
+
+    let mut cases: Vec<Case> = vec![];
+    // all other rules are for cases
+    for case_pair in inner_rules {
+        let err_span = case_pair.as_span();
+        let mut case_inner = case_pair.into_inner();
+        let value = parse_number(case_inner.next().unwrap())?;
+        let kind = case_inner.next().unwrap().as_str();
+        if !matches!(parse_item_type(kind), Type::User(_)) {
+            return Err(make_error(
+                format!("{}: case {} must be a struct type", name, value),
+                err_span,
+            ));
+        }
+        if cases.iter().any(|c| c.value == value) {
+            return Err(make_error(
+                format!("{}: case {} defined twice", name, value),
+                err_span,
+            ));
+        }
+        // the type names become the variant names in generated code
+        if cases.iter().any(|c| c.kind == kind) {
+            return Err(make_error(
+                format!("{}: type {} used by more than one case", name, kind),
+                err_span,
+            ));
+        }
+        cases.push(Case {
+            value,
+            kind: kind.to_string(),
+            span: span_of(err_span),
+        });
+    }
+    Ok(Union {
+        name: name.to_string(),
+        span: span_of(name_pair.as_span()),
+        cases,
+    })
+}
+
+// `q16.16` or `uq8.8`, which must add up to the width of an integer type
+fn parse_fixed_type(type_name: &str) -> Option<Type> {
+    let (signed, rest) = match type_name.strip_prefix('u') {
+        Some(rest) => (false, rest),
+        None => (true, type_name),
+    };
+    let (int_bits, frac_bits) = rest.strip_prefix('q')?.split_once('.')?;
+    let kind = Type::Fixed {
+        signed,
+        int_bits: int_bits.parse().ok()?,
+        frac_bits: frac_bits.parse().ok()?,
+    };
+    kind.raw_type()?;
+    Some(kind)
 }
 
 fn parse_item_type(type_name: &str) -> Type {
+    if let Some(kind) = parse_fixed_type(type_name) {
+        return kind;
+    }
     match type_name {
         "u8" => Type::U8,
         "u16" => Type::U16,
         "u32" => Type::U32,
         "u64" => Type::U64,
+        "u24" => Type::U24,
+        "i24" => Type::I24,
+        "u48" => Type::U48,
+        "u128" => Type::U128,
+        "i128" => Type::I128,
         "i8" => Type::I8,
         "i16" => Type::I16,
         "i32" => Type::I32,
         "i64" => Type::I64,
+        "varu32" => Type::VarU32,
+        "varu64" => Type::VarU64,
+        "vari64" => Type::VarI64,
         "f32" => Type::F32,
         "f64" => Type::F64,
+        "bool" => Type::Bool,
         "byte" => Type::Byte,
         "string" => Type::String,
         "cstring" => Type::CString,
-        _ => Type::User(type_name),
+        _ => Type::User(type_name.to_string()),
     }
 }
 
-fn parse_single_option(option: Pair<Rule>) -> (&str, &str) {
+fn parse_single_option(option: Pair<'_, Rule>) -> (&str, &str) {
     assert!(option.as_rule() == Rule::option, "expected option");
     let mut inner = option.into_inner();
     let key = inner.next().unwrap().as_str();
@@ -118,13 +597,29 @@ fn parse_single_option(option: Pair<Rule>) -> (&str, &str) {
     (key, value)
 }
 
-fn parse_file_options<'a>(
-    pair: Pair<'a, Rule>,
-    defaults: FileOptions<'a>,
-) -> Result<FileOptions<'a>, Error> {
+// struct options override the file's for the items of that struct only
+fn parse_struct_options(
+    pair: Pair<'_, Rule>,
+    file_options: &FileOptions,
+) -> Result<FileOptions, Error> {
+    let options_pair = pair.clone().into_inner().next().unwrap();
+    for option in options_pair.into_inner() {
+        let err_span = option.as_span();
+        let (key, _) = parse_single_option(option);
+        if matches!(key, "scope" | "import" | "go_import") {
+            return Err(make_error(
+                format!("{} is only valid for files", key),
+                err_span,
+            ));
+        }
+    }
+    parse_file_options(pair, file_options.clone())
+}
+
+fn parse_file_options(pair: Pair<'_, Rule>, defaults: FileOptions) -> Result<FileOptions, Error> {
     let mut res = defaults;
     assert!(
-        pair.as_rule() == Rule::file_options,
+        pair.as_rule() == Rule::file_options || pair.as_rule() == Rule::struct_options,
         "expected file options"
     );
     let pair = pair.into_inner().next().unwrap();
@@ -141,18 +636,7 @@ fn parse_file_options<'a>(
                 res.scope_name = value.to_owned();
             }
             "prefix" | "array_size_type" => {
-                // TODO put this type parsing in its own fn?
-                let kind = parse_item_type(value);
-                match kind {
-                    Type::Byte | Type::String | Type::User(_) => {
-                        return Err(make_error(
-                            "array_size_type must be integer valued",
-                            err_span,
-                        ));
-                    }
-                    _ => {}
-                };
-                res.array_size_type = kind;
+                res.array_size_type = Some(parse_size_type(value, err_span)?);
             }
             "endian" => {
                 // TODO put this endianness parsing in its own fn?
@@ -168,7 +652,16 @@ fn parse_file_options<'a>(
                 };
             }
             "import" => {
-                res.raw_imports.push(value);
+                res.raw_imports.push(value.to_string());
+            }
+            "go_import" => {
+                res.go_import = Some(value.to_string());
+            }
+            "strict" => {
+                res.strict = parse_flag(value, err_span)?;
+            }
+            "saturate" => {
+                res.saturate = parse_flag(value, err_span)?;
             }
             _ => return Err(make_error(format!("unknown option {}", key), err_span)),
         }
@@ -176,9 +669,33 @@ fn parse_file_options<'a>(
     Ok(res)
 }
 
+// the same integer types are allowed for lengths at file, struct and item level
+fn parse_size_type(value: &str, err_span: pest::Span) -> Result<Type, Error> {
+    let kind = parse_item_type(value);
+    match integer_range(&kind) {
+        Some(_) => Ok(kind),
+        None => Err(make_error(
+            "array_size_type must be integer valued",
+            err_span,
+        )),
+    }
+}
+
+fn parse_flag(value: &str, err_span: pest::Span) -> Result<bool, Error> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(make_error(
+            format!("expected true or false, got {}", value),
+            err_span,
+        )),
+    }
+}
+
 fn parse_item_options<'a>(
     pair: Pair<'a, Rule>,
-    file_options: &FileOptions<'a>,
+    constants: &[Constant],
+    file_options: &FileOptions,
 ) -> Result<ItemOptions<'a>, Error> {
     let mut res = default_item_options(file_options);
     assert!(pair.as_rule() == Rule::inline_options, "expected options");
@@ -186,29 +703,21 @@ fn parse_item_options<'a>(
         let err_span = option.as_span();
         let (key, value) = parse_single_option(option);
         match key {
-            // "max_array_size" => {
-            //     let size = value.parse::<usize>().unwrap();
-            //     res.max_array_size = Some(size);
-            // }, // TODO bounded
-            "prefix" | "array_size_type" => {
-                let kind = parse_item_type(value);
-                match kind {
-                    Type::U8
-                    | Type::U16
-                    | Type::U32
-                    | Type::U64
-                    | Type::I8
-                    | Type::I16
-                    | Type::I32
-                    | Type::I64 => { /* ok */ }
-                    _ => {
-                        return Err(make_error(
-                            "array_size_type must be integer valued",
-                            err_span,
-                        ));
-                    }
+            "max" | "max_array_size" => {
+                let size = match constants.iter().find(|c| c.name == value) {
+                    Some(constant) => Some(constant.value),
+                    None => parse_integer(value).and_then(|v| usize::try_from(v).ok()),
                 };
-                res.array_size_type = Some(kind);
+                if size.is_none() {
+                    return Err(make_error(
+                        format!("invalid array size {}", value),
+                        err_span,
+                    ));
+                }
+                res.max_array_size = size;
+            }
+            "prefix" | "array_size_type" => {
+                res.array_size_type = Some(parse_size_type(value, err_span)?);
             }
             "endian" => {
                 res.endian = match value {
@@ -225,171 +734,1231 @@ fn parse_item_options<'a>(
             "alias" => {
                 res.type_alias = Some(value);
             }
+            "tag" => {
+                res.tag = Some(value);
+            }
+            "length_of" => {
+                res.length_of = Some(value);
+            }
+            "width" => {
+                let width = match constants.iter().find(|c| c.name == value) {
+                    Some(constant) => Some(constant.value),
+                    None => parse_integer(value).and_then(|v| usize::try_from(v).ok()),
+                };
+                match width {
+                    Some(width) if width > 0 => res.width = Some(width),
+                    _ => {
+                        return Err(make_error(
+                            format!("invalid string width {}", value),
+                            err_span,
+                        ))
+                    }
+                }
+            }
+            "prefix_mode" => {
+                res.prefix_mode = match value {
+                    "count" => Some(PrefixMode::Count),
+                    "bytes" => Some(PrefixMode::Bytes),
+                    _ => {
+                        return Err(make_error(
+                            format!("unknown prefix mode {}", value),
+                            err_span,
+                        ))
+                    }
+                };
+            }
+            "strict" => {
+                res.strict = Some(parse_flag(value, err_span)?);
+            }
+            "saturate" => {
+                res.saturate = Some(parse_flag(value, err_span)?);
+            }
             _ => return Err(make_error(format!("unknown option {}", key), err_span)),
         }
     }
     Ok(res)
 }
 
-fn parse_item<'a>(
+// parses one dimension of an array, `name` being the item it belongs to
+fn parse_array<'a>(
     pair: Pair<'a, Rule>,
-    environment: &[Item<'a>],
-    file_options: &FileOptions<'a>,
-) -> Result<Item<'a>, Error> {
+    name: &'a str,
+    conditional: bool,
+    environment: &[Item],
+    constants: &[Constant],
+    item_options: &ItemOptions<'a>,
+    file_options: &FileOptions,
+) -> Result<Array, Error> {
+    let err_span = pair.as_span();
+    let arr_pair = match pair.into_inner().next() {
+        Some(arr_pair) => arr_pair,
+        None => {
+            return Ok(Array::Unknown(
+                item_options
+                    .array_size_type
+                    .clone()
+                    .or_else(|| file_options.array_size_type.clone())
+                    .unwrap_or(Type::U8),
+            ))
+        }
+    };
+    if arr_pair.as_rule() == Rule::rest {
+        if conditional {
+            return Err(make_error(
+                "rest of input items cannot be conditional",
+                arr_pair.as_span(),
+            ));
+        }
+        return Ok(Array::Rest);
+    }
+    let expr_pair = arr_pair.into_inner().next().unwrap();
+    // length_of items aren't stored, so they can't be used in expressions
+    let length_item = environment
+        .iter()
+        .find(|i| i.name == expr_pair.as_str() && matches!(i.storage, Storage::LengthOf(_)));
+    if let Some(length_item) = length_item {
+        if length_item.storage != Storage::LengthOf(name.to_string()) {
+            return Err(make_error(
+                format!("{} is the length of another array", length_item.name),
+                err_span,
+            ));
+        }
+        if conditional {
+            return Err(make_error(
+                "arrays sized by length_of items cannot be conditional",
+                err_span,
+            ));
+        }
+        return Ok(Array::Derived(length_item.name.clone()));
+    }
+    match parse_expression(expr_pair, environment, constants)? {
+        // constants were already replaced by their value
+        Expr::Number(size) => match usize::try_from(size) {
+            Ok(size) if size > 0 => Ok(Array::Constant(size)),
+            _ => Err(make_error(format!("invalid array size {}", size), err_span)),
+        },
+        Expr::Variable(var) => {
+            // find the type of previously declared variable
+            let (other_item, _) = find_stored(environment, &var).unwrap();
+            // user types might still turn out to be enums, check_array_lengths has the last word
+            let is_integer = matches!(other_item.kind, Type::User(_))
+                || integer_range(&other_item.kind).is_some();
+            if other_item.array.is_some() || !is_integer {
+                return Err(make_error(
+                    format!(
+                        "{} cannot be the length of {}, only integer fields can",
+                        var, name
+                    ),
+                    err_span,
+                ));
+            }
+            Ok(Array::Variable(var, other_item.kind.clone()))
+        }
+        expr if expr.is_boolean() => Err(make_error("array sizes must be numbers", err_span)),
+        expr => Ok(Array::Expression(expr)),
+    }
+}
+
+fn parse_item(
+    pair: Pair<'_, Rule>,
+    environment: &[Item],
+    constants: &[Constant],
+    file_options: &FileOptions,
+) -> Result<Item, Error> {
     assert!(pair.as_rule() == Rule::struct_item, "expected struct item");
+    let span = span_of(pair.as_span());
     let mut inner_rules = pair.into_inner();
-    let name = inner_rules.next().unwrap().as_str();
+    let name_pair = inner_rules.next().unwrap();
+    let mut storage = match name_pair.as_rule() {
+        Rule::padding => Storage::Padding,
+        _ => {
+            check_field_name(name_pair.as_str(), name_pair.as_span())?;
+            Storage::Field
+        }
+    };
+    let name = name_pair.as_str();
     let type_pair = inner_rules.next().unwrap();
     assert!(
         type_pair.as_rule() == Rule::type_decl,
         "expected type declaration"
     );
 
-    let item_options = if let Some(opts_pair) = inner_rules.next() {
-        parse_item_options(opts_pair, file_options)?
+    let mut next_pair = inner_rules.next();
+    let mut magic_span = None;
+    if let Some(magic_pair) = next_pair
+        .clone()
+        .filter(|p| p.as_rule() == Rule::magic_value)
+    {
+        magic_span = Some(magic_pair.as_span());
+        let value = parse_number(magic_pair.into_inner().next().unwrap())?;
+        storage = Storage::Magic(value);
+        next_pair = inner_rules.next();
+    }
+
+    let mut condition = None;
+    if let Some(condition_pair) = next_pair.clone().filter(|p| p.as_rule() == Rule::condition) {
+        if storage != Storage::Field {
+            return Err(make_error(
+                "only fields can be conditional",
+                condition_pair.as_span(),
+            ));
+        }
+        let expr_pair = condition_pair.into_inner().next().unwrap();
+        condition = Some(parse_expression(expr_pair, environment, constants)?);
+        next_pair = inner_rules.next();
+    }
+
+    let item_options = if let Some(opts_pair) = next_pair {
+        let err_span = opts_pair.as_span();
+        let item_options = parse_item_options(opts_pair, constants, file_options)?;
+        if let Some(tag) = item_options.tag {
+            if find_stored(environment, tag).is_none() {
+                return Err(make_error(
+                    format!("undeclared identifier {}", tag),
+                    err_span,
+                ));
+            }
+        }
+        item_options
     } else {
         default_item_options(file_options)
     };
 
-    let array: Option<Array>;
-    let item_type: Type;
-    let mut type_inner = type_pair.into_inner();
-    let first_elem = type_inner.next().unwrap();
-    let err_span = first_elem.as_span();
-    match first_elem.as_rule() {
-        Rule::array_brackets => {
-            if let Some(arr_pair) = first_elem.into_inner().next() {
-                let arr_str = arr_pair.as_str();
-                array = match arr_str.parse::<usize>() {
-                    Ok(size) => Some(Array::Constant(size)),
-                    Err(_) => {
-                        // find the type of previously declared variable
-                        if item_options.array_size_type.is_some() {
-                            return Err(make_error(
-                                "cannot declare type for array with known size",
-                                err_span,
-                            ));
-                        }
-                        let other_item = environment.iter().find(|i| i.name == arr_str);
-                        if let Some(other_item) = other_item {
-                            Some(Array::Variable(arr_str, other_item.kind.clone()))
-                        } else {
-                            return Err(make_error(
-                                format!("undeclared identifier {}", arr_str),
-                                err_span,
-                            ));
-                        }
-                    }
-                };
-            } else {
-                array = Some(Array::Unknown(
-                    item_options
-                        .array_size_type
-                        .unwrap_or_else(|| file_options.array_size_type.clone()),
-                ));
+    let type_pairs: Vec<_> = type_pair.into_inner().collect();
+    let (type_name_pair, brackets) = type_pairs.split_last().unwrap();
+    let err_span = type_pairs[0].as_span();
+    let mut dims = vec![];
+    for brackets_pair in brackets {
+        dims.push(parse_array(
+            brackets_pair.clone(),
+            name,
+            condition.is_some(),
+            environment,
+            constants,
+            &item_options,
+            file_options,
+        )?);
+    }
+    if item_options.array_size_type.is_some()
+        && !dims.is_empty()
+        && !dims.iter().any(|dim| matches!(dim, Array::Unknown(_)))
+    {
+        return Err(make_error(
+            "cannot declare type for array with known size",
+            err_span,
+        ));
+    }
+    if dims.len() > 1 {
+        for dim in &dims {
+            match dim {
+                Array::Rest => {
+                    return Err(make_error(
+                        "rest of input items cannot have more than one dimension",
+                        err_span,
+                    ));
+                }
+                Array::Derived(_) => {
+                    return Err(make_error(
+                        "arrays sized by length_of items cannot have more than one dimension",
+                        err_span,
+                    ));
+                }
+                _ => {}
             }
-            item_type = parse_item_type(type_inner.next().unwrap().as_str());
-        }
-        Rule::item_identifier => {
-            array = None;
-            item_type = parse_item_type(first_elem.as_str());
         }
-        _ => {
+    }
+    // the outermost dimension comes first
+    let array = dims
+        .into_iter()
+        .rev()
+        .reduce(|inner, outer| Array::Nested(Box::new(outer), Box::new(inner)));
+    let item_type = parse_item_type(type_name_pair.as_str());
+    if type_name_pair.as_rule() == Rule::fixed_type && item_type.raw_type().is_none() {
+        return Err(make_error(
+            "fixed-point types must be 8, 16, 32 or 64 bits wide",
+            type_name_pair.as_span(),
+        ));
+    }
+    if item_options.max_array_size.is_some()
+        && !matches!(&array, Some(array) if array.constant_len().is_none())
+    {
+        return Err(make_error(
+            "max_array_size is only valid for arrays without a known size",
+            err_span,
+        ));
+    }
+    if item_options.prefix_mode.is_some() && !matches!(array, Some(Array::Unknown(_))) {
+        return Err(make_error(
+            "prefix_mode is only valid for length-prefixed arrays",
+            err_span,
+        ));
+    }
+    let prefix_mode = match item_type {
+        // the size in bytes is the element count here
+        Type::Byte | Type::U8 => PrefixMode::Count,
+        Type::String | Type::CString if item_options.prefix_mode == Some(PrefixMode::Bytes) => {
             return Err(make_error(
-                "expected array specifier or struct item",
+                "byte length prefixes are not supported for strings",
                 err_span,
-            ))
+            ));
         }
+        _ => item_options.prefix_mode.unwrap_or(PrefixMode::Count),
     };
-    Ok(Item {
-        name,
-        kind: item_type,
-        array,
-        byte_order: item_options.endian,
-        type_alias: item_options.type_alias,
-    })
-}
-
-pub fn parse_file(file_contents: &str) -> Result<File, Error> {
-    let parse_res = StructParser::parse(Rule::file, file_contents)?;
-
-    let mut definitions = vec![];
-    let mut defined_structs = BTreeSet::new();
-    let mut defined_vars = BTreeSet::new();
-    let mut extern_types = BTreeSet::new();
-
-    let mut file_options = default_file_options();
-
-    for pair in parse_res {
-        if pair.as_rule() == Rule::EOI {
-            break;
-        }
-
-        if pair.as_rule() == Rule::file_options {
-            file_options = parse_file_options(pair, file_options)?;
-            continue;
+    if let (Type::CString, Some(Array::Unknown(prefix))) =
+        (&item_type, array.as_ref().map(Array::innermost))
+    {
+        if !matches!(
+            prefix,
+            Type::U8
+                | Type::U16
+                | Type::U24
+                | Type::U32
+                | Type::U48
+                | Type::U64
+                | Type::VarU32
+                | Type::VarU64
+        ) {
+            return Err(make_error(
+                "cstring length prefixes must be unsigned",
+                err_span,
+            ));
         }
-
-        if pair.as_rule() == Rule::extern_definition {
-            let name = parse_extern_definition(pair)?;
-            if defined_structs.contains(name) {
-                let error_span = pest::Span::new(name, 0, name.len()).unwrap(); // TODO improve message?
+    }
+    if matches!(array, Some(Array::Rest)) && !matches!(item_type, Type::Byte | Type::U8) {
+        return Err(make_error(
+            "only byte arrays can hold the rest of the input",
+            err_span,
+        ));
+    }
+    let string_format = match (&item_type, item_options.width) {
+        (Type::String, Some(width)) => {
+            if array.is_none() && item_options.array_size_type.is_some() {
                 return Err(make_error(
-                    format!("{}: type defined as both struct and extern", name),
-                    error_span,
+                    "fixed width strings have no length prefix",
+                    err_span,
                 ));
             }
-            extern_types.insert(name);
-            continue;
+            StringFormat::Fixed(width)
         }
+        (Type::String, None) => {
+            // the prefix option of string arrays is the array's own
+            let prefix = match array {
+                None => item_options.array_size_type.clone(),
+                Some(_) => None,
+            }
+            .or_else(|| file_options.array_size_type.clone())
+            .unwrap_or(Type::U16);
+            if !matches!(
+                prefix,
+                Type::U8
+                    | Type::U16
+                    | Type::U24
+                    | Type::U32
+                    | Type::U48
+                    | Type::U64
+                    | Type::VarU32
+                    | Type::VarU64
+            ) {
+                return Err(make_error(
+                    "string length prefixes must be unsigned",
+                    err_span,
+                ));
+            }
+            StringFormat::Prefixed(prefix)
+        }
+        (_, Some(_)) => {
+            return Err(make_error("width is only valid for strings", err_span));
+        }
+        (_, None) => StringFormat::Prefixed(Type::U16),
+    };
+    if item_options.strict.is_some() && item_type != Type::Bool {
+        return Err(make_error("strict is only valid for bools", err_span));
+    }
+    if item_options.saturate.is_some() && !matches!(item_type, Type::Fixed { .. }) {
+        return Err(make_error(
+            "saturate is only valid for fixed-point numbers",
+            err_span,
+        ));
+    }
+    let mut item = Item {
+        name: name.to_string(),
+        span,
+        storage,
+        kind: item_type,
+        array,
+        byte_order: item_options.endian,
+        type_alias: item_options.type_alias.map(str::to_string),
+        tag: item_options.tag.map(str::to_string),
+        max_array_size: item_options.max_array_size,
+        prefix_mode,
+        string_format,
+        strict: item_options.strict.unwrap_or(file_options.strict),
+        saturate: item_options.saturate.unwrap_or(file_options.saturate),
+        bits: vec![],
+        condition,
+    };
+    // user types are still unresolved here, so this also rules out enums
+    if item.storage == Storage::Padding && item.fixed_size().is_none() {
+        return Err(make_error(
+            "padding must have a known size and a primitive type",
+            err_span,
+        ));
+    }
+    if let Some(target) = item_options.length_of {
+        if item.storage != Storage::Field || item.condition.is_some() {
+            return Err(make_error(
+                "only plain fields can be the length of an array",
+                err_span,
+            ));
+        }
+        if item.array.is_some() || integer_range(&item.kind).is_none() {
+            return Err(make_error(
+                "length_of items must have an integer type",
+                err_span,
+            ));
+        }
+        if item.kind.is_varint() {
+            return Err(make_error("length_of items cannot be varints", err_span));
+        }
+        item.storage = Storage::LengthOf(target.to_string());
+    }
+    if let (&Storage::Magic(value), Some(magic_span)) = (&item.storage, magic_span) {
+        match (&item.array, integer_range(&item.kind)) {
+            _ if item.kind.is_varint() => {
+                return Err(make_error("magic values cannot be varints", magic_span));
+            }
+            (None, Some((min, max))) => {
+                if value < min || value > max {
+                    return Err(make_error(
+                        format!("magic value {} does not fit in {}", value, name),
+                        magic_span,
+                    ));
+                }
+            }
+            _ => {
+                return Err(make_error(
+                    "magic values must have an integer type",
+                    magic_span,
+                ));
+            }
+        }
+    }
+    Ok(item)
+}
 
-        let def = parse_definition(pair, &file_options)?;
-
-        if def.items.is_empty() {
-            eprintln!("Ignoring empty struct definition '{}'", def.name);
+fn parse_bitfield_item(
+    pair: Pair<'_, Rule>,
+    constants: &[Constant],
+    file_options: &FileOptions,
+) -> Result<Item, Error> {
+    assert!(
+        pair.as_rule() == Rule::bitfield_item,
+        "expected bitfield item"
+    );
+    let span = span_of(pair.as_span());
+    let mut inner_rules = pair.into_inner();
+    let type_pair = inner_rules.next().unwrap();
+    let kind = parse_item_type(type_pair.as_str());
+    let total_width = match kind {
+        Type::U8 | Type::U16 | Type::U32 | Type::U64 => kind.fixed_size().unwrap() as u32 * 8,
+        _ => {
+            return Err(make_error(
+                "bitfields must be stored in an unsigned integer",
+                type_pair.as_span(),
+            ))
+        }
+    };
+    let mut item_options = default_item_options(file_options);
+    let mut bits: Vec<Bitfield> = vec![];
+    let mut used_width = 0;
+    for member_pair in inner_rules {
+        if member_pair.as_rule() == Rule::inline_options {
+            let err_span = member_pair.as_span();
+            item_options = parse_item_options(member_pair, constants, file_options)?;
+            if item_options.type_alias.is_some()
+                || item_options.tag.is_some()
+                || item_options.max_array_size.is_some()
+                || item_options.length_of.is_some()
+                || item_options.prefix_mode.is_some()
+                || item_options.width.is_some()
+                || item_options.strict.is_some()
+                || item_options.saturate.is_some()
+            {
+                return Err(make_error("bitfields only accept endian options", err_span));
+            }
             continue;
         }
+        let err_span = member_pair.as_span();
+        let mut member_rules = member_pair.into_inner();
+        let name = member_rules.next().unwrap().as_str();
+        check_field_name(name, err_span)?;
+        let width = parse_number(member_rules.next().unwrap())?;
+        if width < 1 || used_width + width > total_width as i128 {
+            return Err(make_error(
+                format!("{} does not fit in a {} bit field", name, total_width),
+                err_span,
+            ));
+        }
+        used_width += width;
+        bits.push(Bitfield {
+            name: name.to_string(),
+            width: width as u32,
+            shift: total_width - used_width as u32,
+        });
+    }
+    Ok(Item {
+        name: "bits".to_string(),
+        span,
+        storage: Storage::Bitfield,
+        kind,
+        array: None,
+        byte_order: item_options.endian,
+        type_alias: None,
+        tag: None,
+        max_array_size: None,
+        prefix_mode: PrefixMode::Count,
+        string_format: StringFormat::Prefixed(Type::U16),
+        strict: false,
+        saturate: false,
+        bits,
+        condition: None,
+    })
+}
+
+fn parse_expression(
+    pair: Pair<'_, Rule>,
+    environment: &[Item],
+    constants: &[Constant],
+) -> Result<Expr, Error> {
+    assert!(pair.as_rule() == Rule::expression, "expected expression");
+    // from lowest to highest precedence, same as rust
+    let pratt = PrattParser::new()
+        .op(Op::infix(Rule::op_or, Assoc::Left))
+        .op(Op::infix(Rule::op_and, Assoc::Left))
+        .op(Op::infix(Rule::op_eq, Assoc::Left)
+            | Op::infix(Rule::op_ne, Assoc::Left)
+            | Op::infix(Rule::op_lt, Assoc::Left)
+            | Op::infix(Rule::op_le, Assoc::Left)
+            | Op::infix(Rule::op_gt, Assoc::Left)
+            | Op::infix(Rule::op_ge, Assoc::Left))
+        .op(Op::infix(Rule::op_bit_or, Assoc::Left))
+        .op(Op::infix(Rule::op_bit_xor, Assoc::Left))
+        .op(Op::infix(Rule::op_bit_and, Assoc::Left))
+        .op(Op::infix(Rule::op_add, Assoc::Left) | Op::infix(Rule::op_sub, Assoc::Left))
+        .op(Op::infix(Rule::op_mul, Assoc::Left)
+            | Op::infix(Rule::op_div, Assoc::Left)
+            | Op::infix(Rule::op_rem, Assoc::Left))
+        .op(Op::prefix(Rule::op_not));
+    pratt
+        .map_primary(|primary| match primary.as_rule() {
+            Rule::number => Ok(Expr::Number(parse_number(primary)?)),
+            Rule::item_identifier => {
+                let name = primary.as_str();
+                if find_stored(environment, name).is_some() {
+                    Ok(Expr::Variable(name.to_string()))
+                } else if let Some(constant) = constants.iter().find(|c| c.name == name) {
+                    Ok(Expr::Number(constant.value as i128))
+                } else {
+                    Err(make_error(
+                        format!(
+                            "{} is neither a constant nor a field declared before this one",
+                            name
+                        ),
+                        primary.as_span(),
+                    ))
+                }
+            }
+            Rule::expression => parse_expression(primary, environment, constants),
+            _ => unreachable!(),
+        })
+        .map_prefix(|_, operand| Ok(Expr::Not(Box::new(operand?))))
+        .map_infix(|lhs, op_pair, rhs| {
+            let op = match op_pair.as_rule() {
+                Rule::op_or => BinaryOp::Or,
+                Rule::op_and => BinaryOp::And,
+                Rule::op_eq => BinaryOp::Eq,
+                Rule::op_ne => BinaryOp::Ne,
+                Rule::op_lt => BinaryOp::Lt,
+                Rule::op_le => BinaryOp::Le,
+                Rule::op_gt => BinaryOp::Gt,
+                Rule::op_ge => BinaryOp::Ge,
+                Rule::op_bit_or => BinaryOp::BitOr,
+                Rule::op_bit_xor => BinaryOp::BitXor,
+                Rule::op_bit_and => BinaryOp::BitAnd,
+                Rule::op_add => BinaryOp::Add,
+                Rule::op_sub => BinaryOp::Sub,
+                Rule::op_mul => BinaryOp::Mul,
+                Rule::op_div => BinaryOp::Div,
+                Rule::op_rem => BinaryOp::Rem,
+                _ => unreachable!(),
+            };
+            let (lhs, rhs) = (lhs?, rhs?);
+            if !op.is_logical() && (lhs.is_boolean() || rhs.is_boolean()) {
+                return Err(make_error(
+                    format!("{} expects numbers, not conditions", op_pair.as_str()),
+                    op_pair.as_span(),
+                ));
+            }
+            // fold constants so sizes like [N * 2] stay known
+            if let (Expr::Number(lhs), Expr::Number(rhs)) = (&lhs, &rhs) {
+                if !op.is_boolean() {
+                    return match fold_constants(op, *lhs, *rhs) {
+                        Some(value) => Ok(Expr::Number(value)),
+                        None => Err(make_error(
+                            format!("cannot compute {} {} {}", lhs, op_pair.as_str(), rhs),
+                            op_pair.as_span(),
+                        )),
+                    };
+                }
+            }
+            Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+        })
+        .parse(pair.into_inner())
+}
+
+fn fold_constants(op: BinaryOp, lhs: i128, rhs: i128) -> Option<i128> {
+    match op {
+        BinaryOp::BitOr => Some(lhs | rhs),
+        BinaryOp::BitXor => Some(lhs ^ rhs),
+        BinaryOp::BitAnd => Some(lhs & rhs),
+        BinaryOp::Add => lhs.checked_add(rhs),
+        BinaryOp::Sub => lhs.checked_sub(rhs),
+        BinaryOp::Mul => lhs.checked_mul(rhs),
+        BinaryOp::Div => lhs.checked_div(rhs),
+        BinaryOp::Rem => lhs.checked_rem(rhs),
+        _ => None,
+    }
+}
+
+// variables in expressions must hold integers, which is only known once user types are resolved
+// Go evaluates expressions as int64, so every value in them has to fit one
+fn check_expression(def: &Struct, item: &Item, expr: &Expr, input: &str) -> Result<(), Error> {
+    match expr {
+        Expr::Number(value) if i64::try_from(*value).is_err() => Err(make_error_at(
+            format!(
+                "{}.{}: {} does not fit in an i64",
+                def.name, item.name, value
+            ),
+            item.span,
+            input,
+        )),
+        Expr::Number(_) => Ok(()),
+        Expr::Variable(name) => {
+            // parse_item already checked that the variable exists
+            let (other_item, width) = find_stored(&def.items, name).unwrap();
+            let range = match &other_item.kind {
+                Type::Enum(_, kind) => integer_range(kind),
+                kind => integer_range(kind),
+            };
+            let max = match range {
+                Some((_, max)) if other_item.array.is_none() => max,
+                _ => {
+                    return Err(make_error_at(
+                        format!(
+                            "{}.{}: {} must be integer valued",
+                            def.name, item.name, name
+                        ),
+                        item.span,
+                        input,
+                    ))
+                }
+            };
+            // bitfield members are narrower than their container
+            let wide = width.is_none_or(|width| width >= 64);
+            if max > i64::MAX as i128 && wide {
+                return Err(make_error_at(
+                    format!(
+                        "{}.{}: {} can be larger than an i64",
+                        def.name, item.name, name
+                    ),
+                    item.span,
+                    input,
+                ));
+            }
+            Ok(())
+        }
+        Expr::Not(operand) => check_expression(def, item, operand, input),
+        Expr::Binary(_, lhs, rhs) => {
+            check_expression(def, item, lhs, input)?;
+            check_expression(def, item, rhs, input)
+        }
+    }
+}
+
+// lengths must be integer fields of the same struct, declared before the array; struct
+// types are only told apart from enums once user types are resolved
+fn check_array_lengths(def: &Struct, idx: usize, input: &str) -> Result<(), Error> {
+    let item = &def.items[idx];
+    for dim in item.array.iter().flat_map(Array::dimensions) {
+        let (var, kind) = match dim {
+            Array::Variable(var, kind) => (var, kind),
+            _ => continue,
+        };
+        let is_integer = matches!(kind, Type::Enum(..)) || integer_range(kind).is_some();
+        let message = match find_stored(&def.items[..idx], var) {
+            None => format!(
+                "{}.{}: length {} is not a field declared before it",
+                def.name, item.name, var
+            ),
+            Some((length_item, _)) if length_item.array.is_some() || !is_integer => format!(
+                "{}.{}: length {} must be an integer field",
+                def.name, item.name, var
+            ),
+            Some(_) => continue,
+        };
+        return Err(make_error_at(message, item.span, input));
+    }
+    Ok(())
+}
+
+// structs whose last item holds the rest of the input, directly or through another struct,
+// and unions with a case like that
+fn rest_structs(structs: &[Struct], unions: &[Union], includes: &[Include]) -> BTreeSet<String> {
+    let mut names: BTreeSet<String> = includes
+        .iter()
+        .flat_map(|include| include.rest_types.iter().cloned())
+        .collect();
+    loop {
+        let found = names.len();
+        for def in structs {
+            let ends_in_rest = match def.items.last() {
+                Some(Item {
+                    array: Some(Array::Rest),
+                    ..
+                }) => true,
+                Some(Item {
+                    kind: Type::User(name) | Type::Union(name),
+                    array: None,
+                    ..
+                }) => names.contains(name),
+                _ => false,
+            };
+            if ends_in_rest {
+                names.insert(def.name.clone());
+            }
+        }
+        for def in unions {
+            if def.cases.iter().any(|case| names.contains(&case.kind)) {
+                names.insert(def.name.clone());
+            }
+        }
+        if names.len() == found {
+            return names;
+        }
+    }
+}
+
+// finds a value stored in the struct, either a regular field or a member of a
+// bitfield, which is as wide as given and has the type of its container
+fn find_stored<'b>(items: &'b [Item], name: &str) -> Option<(&'b Item, Option<u32>)> {
+    items.iter().find_map(|i| match i.storage {
+        // optional fields might not have a value
+        Storage::Field if i.name == name && i.condition.is_none() => Some((i, None)),
+        Storage::Bitfield => i
+            .bits
+            .iter()
+            .find(|b| b.name == name)
+            .map(|b| (i, Some(b.width))),
+        _ => None,
+    })
+}
+
+fn resolve_user_type(kind: &mut Type, enums: &[Enum], unions: &[Union]) {
+    if let Type::User(name) = kind {
+        if let Some(def) = enums.iter().find(|e| e.name == *name) {
+            *kind = Type::Enum(def.name.clone(), Box::new(def.kind.clone()));
+        } else if let Some(def) = unions.iter().find(|u| u.name == *name) {
+            *kind = Type::Union(def.name.clone());
+        }
+    }
+}
 
-        for item in &def.items {
-            defined_vars.insert(item.name);
+// unions need a tag holding an integer (or enum) that can hold all case values
+fn check_union_tag(def: &Struct, item: &Item, unions: &[Union], input: &str) -> Result<(), Error> {
+    let error_span = pest::Span::new(input, item.span.start, item.span.end).unwrap();
+    let (union_name, tag) = match (&item.kind, &item.tag) {
+        (Type::Union(union_name), Some(tag)) => (union_name, tag),
+        (Type::Union(_), None) => {
+            return Err(make_error(
+                format!("{}.{}: union fields need a tag option", def.name, item.name),
+                error_span,
+            ));
+        }
+        (_, Some(_)) => {
+            return Err(make_error(
+                format!(
+                    "{}.{}: only union fields can have a tag",
+                    def.name, item.name
+                ),
+                error_span,
+            ));
+        }
+        (_, None) => return Ok(()),
+    };
+    if item.array.is_some() {
+        return Err(make_error(
+            format!("{}.{}: union fields cannot be arrays", def.name, item.name),
+            error_span,
+        ));
+    }
+    if item.condition.is_some() {
+        return Err(make_error(
+            format!(
+                "{}.{}: union fields cannot be conditional",
+                def.name, item.name
+            ),
+            error_span,
+        ));
+    }
+    // parse_item already checked that the tag exists
+    let (tag_item, width) = find_stored(&def.items, tag).unwrap();
+    let tag_range = match (&tag_item.kind, width) {
+        (_, Some(width)) => Some((0, (1 << width) - 1)),
+        (Type::Enum(_, kind), None) => integer_range(kind),
+        (kind, None) => integer_range(kind),
+    };
+    let (min, max) = match (&tag_item.array, tag_range) {
+        (None, Some(range)) => range,
+        _ => {
+            return Err(make_error(
+                format!(
+                    "{}.{}: tag {} must be integer valued",
+                    def.name, item.name, tag
+                ),
+                error_span,
+            ));
         }
-        if extern_types.contains(def.name) {
-            let error_span = pest::Span::new(def.name, 0, def.name.len()).unwrap(); // TODO improve message?
+    };
+    let union_def = unions.iter().find(|u| u.name == *union_name).unwrap();
+    for case in &union_def.cases {
+        if case.value < min || case.value > max {
             return Err(make_error(
-                format!("{}: type defined as both struct and extern", def.name),
+                format!(
+                    "{}.{}: case {} of {} does not fit in tag {}",
+                    def.name, item.name, case.value, union_name, tag
+                ),
                 error_span,
             ));
         }
-        defined_structs.insert(def.name);
+    }
+    Ok(())
+}
+
+/// A file pulled in with `include`, read ahead of parsing so the parser does no IO itself
+#[derive(Debug)]
+pub struct Source {
+    /// Directory of the including file joined with the path as written
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+// errors keep the path of the file they come from, which is the innermost one
+fn in_file(err: Error, path: &Path) -> Error {
+    match err.path() {
+        Some(_) => err,
+        None => Box::new(err.with_path(&path.to_string_lossy())),
+    }
+}
+
+fn include_path(dir: &Path, path_pair: &Pair<'_, Rule>) -> PathBuf {
+    dir.join(path_pair.as_str())
+}
+
+/// Reads every file included by `file_contents`, directly or through other included
+/// files; `path` is where `file_contents` came from, which relative includes start at
+pub fn load_includes(file_contents: &str, path: &Path) -> Result<Vec<Source>, Error> {
+    let mut visiting = vec![];
+    if let Ok(canonical) = fs::canonicalize(path) {
+        visiting.push(canonical);
+    }
+    let mut sources = vec![];
+    load_includes_into(file_contents, path, &mut visiting, &mut sources)?;
+    Ok(sources)
+}
+
+fn load_includes_into(
+    file_contents: &str,
+    path: &Path,
+    visiting: &mut Vec<PathBuf>,
+    sources: &mut Vec<Source>,
+) -> Result<(), Error> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let parse_res = StructParser::parse(Rule::file, file_contents).map_err(Error::new)?;
+    for pair in parse_res.filter(|p| p.as_rule() == Rule::include_definition) {
+        let path_pair = pair.into_inner().next().unwrap();
+        let included_path = include_path(dir, &path_pair);
+        let read_error = |err: std::io::Error| {
+            make_error(
+                format!("cannot read {}: {}", included_path.display(), err),
+                path_pair.as_span(),
+            )
+        };
+        let canonical = fs::canonicalize(&included_path).map_err(read_error)?;
+        if visiting.contains(&canonical) {
+            return Err(make_error(
+                format!("{} includes itself", path_pair.as_str()),
+                path_pair.as_span(),
+            ));
+        }
+        if sources.iter().any(|source| source.path == included_path) {
+            continue;
+        }
+        let contents = fs::read_to_string(&included_path).map_err(read_error)?;
+        visiting.push(canonical);
+        load_includes_into(&contents, &included_path, visiting, sources)
+            .map_err(|err| in_file(err, &included_path))?;
+        visiting.pop();
+        sources.push(Source {
+            path: included_path,
+            contents,
+        });
+    }
+    Ok(())
+}
+
+pub fn parse_file(file_contents: &str) -> Result<File, Diagnostics> {
+    parse_file_with_includes(file_contents, Path::new(""), &[])
+}
+
+// name of a struct, enum or union definition, even when the rest of it has errors
+fn definition_name<'i>(pair: &Pair<'i, Rule>) -> Pair<'i, Rule> {
+    let name_pair = pair.clone().into_inner().next().unwrap();
+    name_pair.into_inner().next().unwrap()
+}
+
+/// Same as `parse_file`, taking included files from the `sources` that `load_includes` read
+pub fn parse_file_with_includes(
+    file_contents: &str,
+    path: &Path,
+    sources: &[Source],
+) -> Result<File, Diagnostics> {
+    let parse_res = StructParser::parse(Rule::file, file_contents).map_err(Error::new)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut errors = vec![];
+    let mut definitions = vec![];
+    // structs with errors or defined twice, kept so that the rest of their items are checked
+    let mut rejected = vec![];
+    let mut enums: Vec<Enum> = vec![];
+    let mut unions: Vec<Union> = vec![];
+    let mut constants: Vec<Constant> = vec![];
+    let mut defined_structs = BTreeSet::new();
+    // every struct, enum and union of this file, including those with errors
+    let mut defined_types = BTreeSet::new();
+    let mut extern_types = BTreeSet::new();
+    // types whose definition had errors, so that using them isn't reported as well
+    let mut invalid_types = BTreeSet::new();
+    let mut includes = vec![];
+    let mut included_structs = BTreeSet::new();
+    let mut included_enums: Vec<Enum> = vec![];
+    let mut included_unions: Vec<Union> = vec![];
+
+    let mut file_options = default_file_options();
+
+    for pair in parse_res {
+        if pair.as_rule() == Rule::EOI {
+            break;
+        }
+
+        if pair.as_rule() == Rule::file_options {
+            match parse_file_options(pair, file_options.clone()) {
+                Ok(options) => file_options = options,
+                Err(err) => errors.push(err),
+            }
+            continue;
+        }
+
+        if pair.as_rule() == Rule::include_definition {
+            let path_pair = pair.into_inner().next().unwrap();
+            let included_path = include_path(dir, &path_pair);
+            let source = match sources.iter().find(|source| source.path == included_path) {
+                Some(source) => source,
+                None => {
+                    errors.push(make_error(
+                        format!("{} was not loaded", path_pair.as_str()),
+                        path_pair.as_span(),
+                    ));
+                    continue;
+                }
+            };
+            let included = match parse_file_with_includes(&source.contents, &source.path, sources) {
+                Ok(included) => included,
+                Err(diagnostics) => {
+                    let in_source = |err| in_file(err, &source.path);
+                    errors.extend(diagnostics.0.into_iter().map(in_source));
+                    continue;
+                }
+            };
+            let types = included
+                .structs
+                .iter()
+                .map(|def| def.name.clone())
+                .chain(included.enums.iter().map(|def| def.name.clone()))
+                .chain(included.unions.iter().map(|def| def.name.clone()))
+                .collect();
+            included_structs.extend(included.structs.iter().map(|def| def.name.clone()));
+            let rest_types = rest_structs(&included.structs, &included.unions, &included.includes)
+                .into_iter()
+                .collect();
+            included_enums.extend(included.enums);
+            included_unions.extend(included.unions.iter().cloned());
+            includes.push(Include {
+                path: path_pair.as_str().to_string(),
+                span: span_of(path_pair.as_span()),
+                scope: included.scope,
+                go_import: included.go_import,
+                types,
+                unions: included.unions,
+                rest_types,
+            });
+            continue;
+        }
+
+        if pair.as_rule() == Rule::extern_definition {
+            let name_span = pair.clone().into_inner().next().unwrap().as_span();
+            let name = match parse_extern_definition(pair) {
+                Ok(name) => name,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            if defined_structs.contains(name) {
+                errors.push(make_error(
+                    format!("{}: type defined as both struct and extern", name),
+                    name_span,
+                ));
+            } else if defined_types.contains(name) {
+                errors.push(make_error(
+                    format!("{}: type defined twice", name),
+                    name_span,
+                ));
+            }
+            extern_types.insert(name.to_string());
+            continue;
+        }
+
+        if pair.as_rule() == Rule::const_definition {
+            let name_span = pair.clone().into_inner().next().unwrap().as_span();
+            let def = match parse_const_definition(pair) {
+                Ok(def) => def,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            if constants.iter().any(|c| c.name == def.name) {
+                errors.push(make_error(
+                    format!("{}: constant defined twice", def.name),
+                    name_span,
+                ));
+                continue;
+            }
+            constants.push(def);
+            continue;
+        }
+
+        if pair.as_rule() == Rule::enum_definition {
+            let name_pair = definition_name(&pair);
+            let name = name_pair.as_str().to_string();
+            let duplicate = defined_types.contains(&name) || extern_types.contains(&name);
+            if duplicate {
+                errors.push(make_error(
+                    format!("{}: type defined twice", name),
+                    name_pair.as_span(),
+                ));
+            }
+            defined_types.insert(name.clone());
+            match parse_enum_definition(pair) {
+                Ok(def) if !duplicate => enums.push(def),
+                Ok(_) => {}
+                Err(err) => {
+                    errors.push(err);
+                    invalid_types.insert(name);
+                }
+            }
+            continue;
+        }
+
+        if pair.as_rule() == Rule::union_definition {
+            let name_pair = definition_name(&pair);
+            let name = name_pair.as_str().to_string();
+            let duplicate = defined_types.contains(&name) || extern_types.contains(&name);
+            if duplicate {
+                errors.push(make_error(
+                    format!("{}: type defined twice", name),
+                    name_pair.as_span(),
+                ));
+            }
+            defined_types.insert(name.clone());
+            match parse_union_definition(pair) {
+                Ok(def) if !duplicate => unions.push(def),
+                Ok(_) => {}
+                Err(err) => {
+                    errors.push(err);
+                    invalid_types.insert(name);
+                }
+            }
+            continue;
+        }
+
+        let name_pair = definition_name(&pair);
+        let name = name_pair.as_str().to_string();
+        let duplicate = if extern_types.contains(&name) {
+            errors.push(make_error(
+                format!("{}: type defined as both struct and extern", name),
+                name_pair.as_span(),
+            ));
+            true
+        } else if defined_types.contains(&name) {
+            errors.push(make_error(
+                format!("{}: type defined twice", name),
+                name_pair.as_span(),
+            ));
+            true
+        } else {
+            false
+        };
+        defined_types.insert(name.clone());
+        // the items are checked even for a duplicate, so that all their errors are reported
+        let error_count = errors.len();
+        let def = parse_definition(pair, &constants, &file_options, &mut errors);
+        if errors.len() > error_count {
+            invalid_types.insert(name);
+            rejected.push(def);
+            continue;
+        }
+        if duplicate {
+            rejected.push(def);
+            continue;
+        }
+        if def.items.is_empty() {
+            eprintln!("Ignoring empty struct definition '{}'", def.name);
+            continue;
+        }
+        defined_structs.insert(def.name.clone());
         definitions.push(def);
     }
-    for def in &definitions {
-        for item in &def.items {
+    for include in &includes {
+        for name in &include.types {
+            if defined_types.contains(name) || extern_types.contains(name) {
+                errors.push(make_error_at(
+                    format!("{}: type also defined in {}", name, include.path),
+                    include.span,
+                    file_contents,
+                ));
+            }
+        }
+    }
+    // included types are only used, so they go after this file's own
+    let known_enums: Vec<Enum> = enums.iter().cloned().chain(included_enums).collect();
+    let known_unions: Vec<Union> = unions.iter().cloned().chain(included_unions).collect();
+    let is_defined = |name: &String| {
+        defined_structs.contains(name)
+            || extern_types.contains(name)
+            || included_structs.contains(name)
+            || invalid_types.contains(name)
+    };
+    // enums and unions may be declared after their use, so resolve them only now
+    for def in definitions.iter_mut().chain(&mut rejected) {
+        for item in &mut def.items {
+            resolve_user_type(&mut item.kind, &known_enums, &known_unions);
+            if let Some(array) = &mut item.array {
+                for dim in array.dimensions_mut() {
+                    if let Array::Variable(_, kind) = dim {
+                        resolve_user_type(kind, &known_enums, &known_unions);
+                    }
+                }
+            }
+        }
+    }
+    for def in &unions {
+        for case in &def.cases {
+            if !is_defined(&case.kind) {
+                errors.push(make_error_at(
+                    format!("{}: undefined type {}", def.name, case.kind),
+                    case.span,
+                    file_contents,
+                ));
+            }
+        }
+    }
+    let rest_types = rest_structs(&definitions, &unions, &includes);
+    for def in definitions.iter().chain(&rejected) {
+        for (idx, item) in def.items.iter().enumerate() {
+            // nothing could be read after a struct that takes the rest of the input
+            if let Type::User(name) | Type::Union(name) = &item.kind {
+                let is_last = idx + 1 == def.items.len() && item.array.is_none();
+                if rest_types.contains(name) && !is_last {
+                    errors.push(make_error_at(
+                        format!(
+                            "{}.{}: {} holds the rest of the input, so only the last item can have it",
+                            def.name, item.name, name
+                        ),
+                        item.span,
+                        file_contents,
+                    ));
+                }
+            }
+            // a union whose definition had errors can't be checked against
+            let invalid_union =
+                matches!(&item.kind, Type::User(name) if invalid_types.contains(name));
+            if !invalid_union {
+                if let Err(err) = check_union_tag(def, item, &known_unions, file_contents) {
+                    errors.push(err);
+                }
+            }
+            let expressions = item.condition.iter().chain(
+                item.array
+                    .iter()
+                    .flat_map(Array::dimensions)
+                    .filter_map(|dim| match dim {
+                        Array::Expression(expr) => Some(expr),
+                        _ => None,
+                    }),
+            );
+            for expr in expressions {
+                if let Err(err) = check_expression(def, item, expr, file_contents) {
+                    errors.push(err);
+                }
+            }
+            if let Storage::LengthOf(target) = &item.storage {
+                let sized = def.items.iter().any(|i| {
+                    i.name == *target
+                        && matches!(&i.array, Some(Array::Derived(n)) if *n == item.name)
+                });
+                if !sized {
+                    errors.push(make_error_at(
+                        format!(
+                            "{}.{}: {} is not an array sized by {}",
+                            def.name, item.name, target, item.name
+                        ),
+                        item.span,
+                        file_contents,
+                    ));
+                }
+            }
             // check for undefined types
             if let Type::User(typ) = &item.kind {
-                if !defined_structs.contains(typ) && !extern_types.contains(typ) {
-                    let error_span = pest::Span::new(typ, 0, typ.len()).unwrap(); // TODO improve message?
-                    return Err(make_error(
+                if !is_defined(typ) {
+                    errors.push(make_error_at(
                         format!("{}: undefined type {}", def.name, typ),
-                        error_span,
+                        item.span,
+                        file_contents,
                     ));
                 }
             }
-            // check for undefined variables
-            if let Some(Array::Variable(var, _)) = &item.array {
-                if !defined_vars.contains(var) {
-                    let error_span = pest::Span::new(var, 0, var.len()).unwrap(); // TODO improve message?
-                    return Err(make_error(
-                        format!("{}: undefined variable {}", def.name, var),
-                        error_span,
-                    ));
-                }
+            if let Err(err) = check_array_lengths(def, idx, file_contents) {
+                errors.push(err);
             }
         }
     }
+    if !errors.is_empty() {
+        return Err(Diagnostics::new(errors));
+    }
     let names = defined_structs
         .iter()
-        .cloned()
+        .map(String::as_str)
         .collect::<Vec<&str>>()
         .join(", ");
     println!("{} definitions: {}", defined_structs.len(), names);
@@ -397,6 +1966,11 @@ pub fn parse_file(file_contents: &str) -> Result<File, Error> {
     Ok(File {
         scope: file_options.scope_name,
         raw_imports: file_options.raw_imports,
+        go_import: file_options.go_import,
+        includes,
+        constants,
+        enums,
+        unions,
         structs: definitions,
     })
 }
@@ -404,6 +1978,8 @@ pub fn parse_file(file_contents: &str) -> Result<File, Error> {
 #[cfg(test)]
 #[test]
 fn parser_tests() {
+    use pest::error::LineColLocation;
+
     // TODO: check for an easy way to remove the repetition
     // let test = include_str!("../specs/simple.zs");
     // let res = StructParser::parse(Rule::file, test);
@@ -501,7 +2077,7 @@ struct ship {
 
     let test = "
 struct player {
-    hp []u8
+    hp u8
     sp [hp]i16
 }
 struct ship {
@@ -840,4 +2416,1665 @@ struct player
     let res = parse_file(test);
     dbg!(&res);
     assert!(res.is_ok(), "item_type with underscores");
+
+    let test = "
+enum Kind : u16 {
+    A = 1
+    B = 0x2
+}
+
+struct player {
+    kind Kind
+    kinds []Kind
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "enum declaration");
+    let res = parse_file(test);
+    assert!(res.is_ok(), "enum declaration");
+    let file = res.unwrap();
+    assert_eq!(file.enums.len(), 1);
+    assert_eq!(file.enums[0].variants[1].value, 2);
+    assert_eq!(
+        file.structs[0].items[0].kind,
+        Type::Enum("Kind".to_string(), Box::new(Type::U16))
+    );
+
+    let test = "
+struct player {
+    kind Kind
+}
+enum Kind:i8 { A = -1, B = 2, }";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "single line enum declared after use");
+    let res = parse_file(test);
+    assert!(res.is_ok(), "single line enum declared after use");
+
+    let test = "
+enum Kind : u8 {}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_err(), "enum needs variants");
+    let res = parse_file(test);
+    assert!(res.is_err(), "enum needs variants");
+
+    let test = "
+enum Kind : f32 { A = 1 }";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "enum must be integer valued");
+    let res = parse_file(test);
+    assert!(res.is_err(), "enum must be integer valued");
+
+    let test = "
+enum Kind : u8 { A = 1, B = 256 }";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "enum value out of range");
+    let res = parse_file(test);
+    assert!(res.is_err(), "enum value out of range");
+
+    let test = "
+enum Kind : u8 { A = 1, B = 1 }";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "duplicate enum value");
+    let res = parse_file(test);
+    assert!(res.is_err(), "duplicate enum value");
+
+    let test = "
+enum Kind : u8 { A = 1, A = 2 }";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "duplicate enum variant");
+    let res = parse_file(test);
+    assert!(res.is_err(), "duplicate enum variant");
+
+    let test = "
+enum Kind : u8 { A = 1 }
+struct Kind {
+    a u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "enum and struct with same name");
+    let res = parse_file(test);
+    assert!(res.is_err(), "enum and struct with same name");
+
+    let test = "
+union Payload {
+    1 => Login
+    2 => Logout
+}
+enum Kind : u8 { A = 1, B = 2 }
+struct Login {
+    name string
+}
+struct Logout {
+    reason u8
+}
+struct Message {
+    kind u8
+    body Payload tag:kind
+    other Payload tag kind
+    ekind Kind
+    ebody Payload tag:ekind
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "tagged unions");
+    let res = parse_file(test);
+    assert!(res.is_ok(), "tagged unions");
+    let file = res.unwrap();
+    assert_eq!(file.unions[0].cases.len(), 2);
+    assert_eq!(
+        file.structs[2].items[1].kind,
+        Type::Union("Payload".to_string())
+    );
+    assert_eq!(file.structs[2].items[1].tag.as_deref(), Some("kind"));
+
+    let test = "
+union Payload { 1 => Login }
+struct Login {
+    name string
+}
+struct Message {
+    body Payload
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "union needs a tag");
+    let res = parse_file(test);
+    assert!(res.is_err(), "union needs a tag");
+
+    let test = "
+union Payload { 1 => Login }
+struct Login {
+    name string
+}
+struct Message {
+    body Payload tag:kind
+    kind u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "union tag must come first");
+    let res = parse_file(test);
+    assert!(res.is_err(), "union tag must come first");
+
+    let test = "
+union Payload { 1 => Login }
+struct Login {
+    name string
+}
+struct Message {
+    kind f32
+    body Payload tag:kind
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "union tag must be integer valued");
+    let res = parse_file(test);
+    assert!(res.is_err(), "union tag must be integer valued");
+
+    let test = "
+union Payload { 256 => Login }
+struct Login {
+    name string
+}
+struct Message {
+    kind u8
+    body Payload tag:kind
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "union case must fit in the tag");
+    let res = parse_file(test);
+    assert!(res.is_err(), "union case must fit in the tag");
+
+    let test = "
+union Payload { 1 => Login, 2 => Missing }
+struct Login {
+    name string
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "union case with undefined type");
+    let res = parse_file(test);
+    assert!(res.is_err(), "union case with undefined type");
+
+    let test = "
+union Payload { 1 => Login, 1 => Logout }
+struct Login {
+    name string
+}
+struct Logout {
+    name string
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "duplicate union case");
+    let res = parse_file(test);
+    assert!(res.is_err(), "duplicate union case");
+
+    let test = "
+struct Message {
+    kind u8
+    body u32 tag:kind
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "tag on a non-union field");
+    let res = parse_file(test);
+    assert!(res.is_err(), "tag on a non-union field");
+
+    let test = "
+const MAX_NAME = 32
+const COUNT = 0x4
+
+struct player {
+    name [MAX_NAME]cstring
+    scores [COUNT]u16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "constants as array sizes");
+    let res = parse_file(test);
+    assert!(res.is_ok(), "constants as array sizes");
+    let file = res.unwrap();
+    assert_eq!(file.constants.len(), 2);
+    assert!(matches!(
+        file.structs[0].items[1].array,
+        Some(Array::Constant(4))
+    ));
+
+    let test = "
+struct player {
+    name [MAX_NAME]cstring
+}
+const MAX_NAME = 32
+";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "constants must be declared before use");
+    let res = parse_file(test);
+    assert!(res.is_err(), "constants must be declared before use");
+
+    let test = "
+const MAX_NAME = -1
+";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "negative constants");
+    let res = parse_file(test);
+    assert!(res.is_err(), "negative constants");
+
+    let test = "
+const MAX_NAME = 1
+const MAX_NAME = 2
+";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "constant defined twice");
+    let res = parse_file(test);
+    assert!(res.is_err(), "constant defined twice");
+
+    let test = "
+const MAX = 16
+struct player {
+    count u8
+    a []u8 max:64
+    b [count]u16 max_array_size:0x10
+    c []cstring max:MAX
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "bounded arrays");
+    let res = parse_file(test);
+    assert!(res.is_ok(), "bounded arrays");
+    let file = res.unwrap();
+    assert_eq!(file.structs[0].items[1].max_array_size, Some(64));
+    assert_eq!(file.structs[0].items[2].max_array_size, Some(16));
+    assert_eq!(file.structs[0].items[3].max_array_size, Some(16));
+
+    let test = "
+struct player {
+    a [4]u8 max:64
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "cannot bound arrays with known size");
+    let res = parse_file(test);
+    assert!(res.is_err(), "cannot bound arrays with known size");
+
+    let test = "
+struct player {
+    a u8 max:64
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "cannot bound non-arrays");
+    let res = parse_file(test);
+    assert!(res.is_err(), "cannot bound non-arrays");
+
+    let test = "
+struct player {
+    a []u8 max:lots
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "invalid array bound");
+    let res = parse_file(test);
+    assert!(res.is_err(), "invalid array bound");
+
+    let test = "
+struct player {
+    a u8
+    _ [3]u8
+    b u32
+    _ u16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "padding");
+    let file = parse_file(test).unwrap();
+    assert_eq!(file.structs[0].items[1].storage, Storage::Padding);
+    assert_eq!(file.structs[0].items[1].fixed_size(), Some(3));
+    assert_eq!(file.structs[0].items[3].fixed_size(), Some(2));
+
+    let test = "
+struct player {
+    _ []u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "padding needs a known size");
+    let res = parse_file(test);
+    assert!(res.is_err(), "padding needs a known size");
+
+    let test = "
+struct player {
+    _ string
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "padding cannot be a string");
+    let res = parse_file(test);
+    assert!(res.is_err(), "padding cannot be a string");
+
+    let test = "
+struct player {
+    _ Foo
+}
+struct Foo {
+    a u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "padding cannot be a user type");
+    let res = parse_file(test);
+    assert!(res.is_err(), "padding cannot be a user type");
+
+    let test = "
+struct player {
+    magic u32 = 0xCAFEBABE endian:big
+    _ i8 = -1
+    a u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "magic values");
+    let file = parse_file(test).unwrap();
+    assert_eq!(file.structs[0].items[0].storage, Storage::Magic(0xCAFEBABE));
+    assert!(matches!(file.structs[0].items[0].byte_order, Endian::Big));
+    assert_eq!(file.structs[0].items[1].storage, Storage::Magic(-1));
+
+    let test = "
+struct player {
+    magic u8 = 256
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "magic value out of range");
+    let res = parse_file(test);
+    assert!(res.is_err(), "magic value out of range");
+
+    let test = "
+struct player {
+    magic [4]u8 = 1
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "magic values cannot be arrays");
+    let res = parse_file(test);
+    assert!(res.is_err(), "magic values cannot be arrays");
+
+    let test = "
+struct player {
+    magic f32 = 1
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "magic values must be integers");
+    let res = parse_file(test);
+    assert!(res.is_err(), "magic values must be integers");
+
+    let test = "
+struct player {
+    len u8 = 4
+    a [len]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "magic values are not stored");
+    let res = parse_file(test);
+    assert!(res.is_err(), "magic values are not stored");
+
+    let test = "
+struct player {
+    bits u16 { version:4, flags:12 } endian:big
+    bits u8 { a:1,b:2 }
+    c [flags]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "bitfields");
+    let file = parse_file(test).unwrap();
+    let bits = &file.structs[0].items[0].bits;
+    assert_eq!(file.structs[0].items[0].storage, Storage::Bitfield);
+    assert_eq!(
+        (bits[0].name.as_str(), bits[0].width, bits[0].shift),
+        ("version", 4, 12)
+    );
+    assert_eq!(
+        (bits[1].name.as_str(), bits[1].width, bits[1].shift),
+        ("flags", 12, 0)
+    );
+    let bits = &file.structs[0].items[1].bits;
+    assert_eq!(
+        (bits[1].name.as_str(), bits[1].width, bits[1].shift),
+        ("b", 2, 5)
+    );
+
+    let test = "
+struct player {
+    bits u8 { a:4, b:5 }
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "bitfields wider than their container");
+    let res = parse_file(test);
+    assert!(res.is_err(), "bitfields wider than their container");
+
+    let test = "
+struct player {
+    bits u8 { a:0 }
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "empty bitfields");
+    let res = parse_file(test);
+    assert!(res.is_err(), "empty bitfields");
+
+    let test = "
+struct player {
+    bits i16 { a:4 }
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "bitfields need an unsigned container");
+    let res = parse_file(test);
+    assert!(res.is_err(), "bitfields need an unsigned container");
+
+    let test = "
+struct player {
+    bits u8 { }
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_err(), "bitfields need at least one field");
+
+    let test = "
+const V2 = 2
+struct player {
+    version u8
+    bits u8 { a:1, b:7 }
+    x u16 if version >= V2 && !(a | b & 0x2) endian:big
+    y [4]u8 if version
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "conditional fields");
+    let file = parse_file(test).unwrap();
+    assert_eq!(
+        file.structs[0].items[2].condition,
+        Some(Expr::Binary(
+            BinaryOp::And,
+            Box::new(Expr::Binary(
+                BinaryOp::Ge,
+                Box::new(Expr::Variable("version".to_string())),
+                Box::new(Expr::Number(2))
+            )),
+            Box::new(Expr::Not(Box::new(Expr::Binary(
+                BinaryOp::BitOr,
+                Box::new(Expr::Variable("a".to_string())),
+                Box::new(Expr::Binary(
+                    BinaryOp::BitAnd,
+                    Box::new(Expr::Variable("b".to_string())),
+                    Box::new(Expr::Number(2))
+                ))
+            ))))
+        ))
+    );
+    assert_eq!(
+        file.structs[0].items[3].condition,
+        Some(Expr::Variable("version".to_string()))
+    );
+
+    let test = "
+struct player {
+    x u16 if version == 1
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "conditions on undeclared fields");
+    let res = parse_file(test);
+    assert!(res.is_err(), "conditions on undeclared fields");
+
+    let test = "
+struct player {
+    version f32
+    x u16 if version
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "conditions on non-integer fields");
+    let res = parse_file(test);
+    assert!(res.is_err(), "conditions on non-integer fields");
+
+    let test = "
+struct player {
+    version u8
+    x u16 if (version > 1) & 1
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "conditions used as numbers");
+    let res = parse_file(test);
+    assert!(res.is_err(), "conditions used as numbers");
+
+    let test = "
+struct player {
+    version u8
+    x u16 if version
+    y [x]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "optional fields cannot be lengths");
+    let res = parse_file(test);
+    assert!(res.is_err(), "optional fields cannot be lengths");
+
+    let test = "
+struct player {
+    version u8
+    x u16 if version ==
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_err(), "incomplete condition");
+
+    let test = "
+const N = 4
+struct image {
+    width u16
+    height u16
+    pixels [width * height]i16
+    rest [N * 2]u8
+    tail [height - 4 + N % 3]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "array sizes from expressions");
+    let file = parse_file(test).unwrap();
+    assert!(matches!(
+        &file.structs[0].items[2].array,
+        Some(Array::Expression(Expr::Binary(BinaryOp::Mul, ..)))
+    ));
+    assert!(matches!(
+        file.structs[0].items[3].array,
+        Some(Array::Constant(8))
+    ));
+    assert_eq!(
+        match &file.structs[0].items[4].array {
+            Some(Array::Expression(expr)) => Some(expr.clone()),
+            _ => None,
+        },
+        Some(Expr::Binary(
+            BinaryOp::Add,
+            Box::new(Expr::Binary(
+                BinaryOp::Sub,
+                Box::new(Expr::Variable("height".to_string())),
+                Box::new(Expr::Number(4))
+            )),
+            Box::new(Expr::Number(1))
+        ))
+    );
+
+    let test = "
+const N = 4
+struct image {
+    pixels [N - 4]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "empty computed array size");
+    let res = parse_file(test);
+    assert!(res.is_err(), "empty computed array size");
+
+    let test = "
+const N = 4
+struct image {
+    pixels [N / (N - 4)]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "array size dividing by zero");
+    let res = parse_file(test);
+    assert!(res.is_err(), "array size dividing by zero");
+
+    let test = "
+struct image {
+    width u16
+    pixels [width > 2]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "conditions as array sizes");
+    let res = parse_file(test);
+    assert!(res.is_err(), "conditions as array sizes");
+
+    let test = "
+struct image {
+    width u16
+    pixels [width * 2]u8 prefix:u16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "size type for computed array sizes");
+    let res = parse_file(test);
+    assert!(res.is_err(), "size type for computed array sizes");
+
+    let test = "
+struct image {
+    width f32
+    pixels [width * 2]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "array sizes from non-integer fields");
+    let res = parse_file(test);
+    assert!(res.is_err(), "array sizes from non-integer fields");
+
+    let test = "
+struct inventory {
+    count u16 length_of:items
+    owner u8
+    items [count]u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "length_of items");
+    let file = parse_file(test).unwrap();
+    assert_eq!(
+        file.structs[0].items[0].storage,
+        Storage::LengthOf("items".to_string())
+    );
+    assert!(matches!(
+        &file.structs[0].items[2].array,
+        Some(Array::Derived(target)) if target == "count"
+    ));
+
+    let test = "
+struct inventory {
+    count u16 length_of:items
+    items [count * 2]u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "length_of items in expressions");
+    let res = parse_file(test);
+    assert!(res.is_err(), "length_of items in expressions");
+
+    let test = "
+struct inventory {
+    count u16 length_of:items
+    items [count]u32
+    other [count]u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "length_of items sizing several arrays");
+    let res = parse_file(test);
+    assert!(res.is_err(), "length_of items sizing several arrays");
+
+    let test = "
+struct inventory {
+    count u16 length_of:items
+    items []u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "length_of items not sizing their array");
+    let res = parse_file(test);
+    assert!(res.is_err(), "length_of items not sizing their array");
+
+    let test = "
+struct inventory {
+    count f32 length_of:items
+    items [count]u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "non-integer length_of items");
+    let res = parse_file(test);
+    assert!(res.is_err(), "non-integer length_of items");
+
+    let test = "
+struct inventory {
+    flag u8
+    count u16 length_of:items
+    items [count]u32 if flag
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "conditional arrays sized by length_of items");
+    let res = parse_file(test);
+    assert!(res.is_err(), "conditional arrays sized by length_of items");
+
+    let test = "
+struct entry {
+    x u8
+}
+struct catalog {
+    entries []entry prefix:u16 prefix_mode:bytes
+    counted []entry prefix_mode:count
+    data []u8 prefix_mode:bytes
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "byte length prefixes");
+    let file = parse_file(test).unwrap();
+    assert_eq!(file.structs[1].items[0].prefix_mode, PrefixMode::Bytes);
+    assert_eq!(file.structs[1].items[1].prefix_mode, PrefixMode::Count);
+    assert_eq!(file.structs[1].items[2].prefix_mode, PrefixMode::Count);
+
+    let test = "
+struct catalog {
+    entries [4]u32 prefix_mode:bytes
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "byte length prefixes without a prefix");
+    let res = parse_file(test);
+    assert!(res.is_err(), "byte length prefixes without a prefix");
+
+    let test = "
+struct catalog {
+    names []string prefix_mode:bytes
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "byte length prefixes for strings");
+    let res = parse_file(test);
+    assert!(res.is_err(), "byte length prefixes for strings");
+
+    let test = "
+struct catalog {
+    entries []u32 prefix_mode:words
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "unknown prefix mode");
+    let res = parse_file(test);
+    assert!(res.is_err(), "unknown prefix mode");
+
+    let test = "
+const CODE_LEN = 8
+struct label {
+    plain string
+    short string prefix:u8
+    code string width:CODE_LEN
+    tags [2]string width:4
+    notes []string prefix:u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "string formats");
+    let file = parse_file(test).unwrap();
+    let items = &file.structs[0].items;
+    assert_eq!(items[0].string_format, StringFormat::Prefixed(Type::U16));
+    assert_eq!(items[1].string_format, StringFormat::Prefixed(Type::U8));
+    assert_eq!(items[2].string_format, StringFormat::Fixed(8));
+    assert_eq!(items[3].string_format, StringFormat::Fixed(4));
+    assert_eq!(items[4].string_format, StringFormat::Prefixed(Type::U16));
+    assert!(matches!(items[4].array, Some(Array::Unknown(Type::U32))));
+
+    let test = "
+options prefix:u32
+struct label {
+    plain string
+    names []string
+}";
+    let file = parse_file(test).unwrap();
+    let items = &file.structs[0].items;
+    assert_eq!(items[0].string_format, StringFormat::Prefixed(Type::U32));
+    assert_eq!(items[1].string_format, StringFormat::Prefixed(Type::U32));
+    assert!(matches!(items[1].array, Some(Array::Unknown(Type::U32))));
+
+    let test = "
+struct label {
+    short string prefix:i8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "signed string prefixes");
+    let res = parse_file(test);
+    assert!(res.is_err(), "signed string prefixes");
+
+    let test = "
+struct label {
+    short string prefix:u8 width:4
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "fixed width strings with prefixes");
+    let res = parse_file(test);
+    assert!(res.is_err(), "fixed width strings with prefixes");
+
+    let test = "
+struct label {
+    short u32 width:4
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "width on non-strings");
+    let res = parse_file(test);
+    assert!(res.is_err(), "width on non-strings");
+
+    let test = "
+struct label {
+    short string width:0
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "empty fixed width strings");
+    let res = parse_file(test);
+    assert!(res.is_err(), "empty fixed width strings");
+
+    let test = "
+options prefix:u32
+struct label {
+    name []cstring prefix:u16
+    other []cstring
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "cstring length prefixes");
+    let file = parse_file(test).unwrap();
+    assert!(matches!(
+        file.structs[0].items[0].array,
+        Some(Array::Unknown(Type::U16))
+    ));
+    assert!(matches!(
+        file.structs[0].items[1].array,
+        Some(Array::Unknown(Type::U32))
+    ));
+
+    let test = "
+struct label {
+    name []cstring prefix:i16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "signed cstring length prefixes");
+    let res = parse_file(test);
+    assert!(res.is_err(), "signed cstring length prefixes");
+
+    let test = "
+struct packet {
+    kind u8
+    payload [..]byte
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "rest of input items");
+    let file = parse_file(test).unwrap();
+    assert!(matches!(file.structs[0].items[1].array, Some(Array::Rest)));
+
+    let test = "
+struct packet {
+    payload [..]byte
+    kind u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "items after rest of input");
+    let res = parse_file(test);
+    assert!(res.is_err(), "items after rest of input");
+
+    let test = "
+struct packet {
+    payload [..]u16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "rest of input non-byte items");
+    let res = parse_file(test);
+    assert!(res.is_err(), "rest of input non-byte items");
+
+    let test = "
+struct packet {
+    kind u8
+    payload [..]byte if kind == 1
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "conditional rest of input");
+    let res = parse_file(test);
+    assert!(res.is_err(), "conditional rest of input");
+
+    let test = "
+struct packet {
+    payload [..]byte prefix:u16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "prefixed rest of input");
+    let res = parse_file(test);
+    assert!(res.is_err(), "prefixed rest of input");
+
+    let test = "
+struct tiles {
+    rows u8
+    cols u8
+    cells [16][16]u8
+    grid [rows][cols]i16
+    layers [][rows * 2]u8 max:4
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "multi-dimensional arrays");
+    let file = parse_file(test).unwrap();
+    let items = &file.structs[0].items;
+    assert!(matches!(
+        &items[2].array,
+        Some(Array::Nested(outer, inner))
+            if matches!(**outer, Array::Constant(16)) && matches!(**inner, Array::Constant(16))
+    ));
+    assert_eq!(items[2].fixed_size(), Some(256));
+    assert!(matches!(
+        &items[3].array,
+        Some(Array::Nested(outer, inner))
+            if matches!(&**outer, Array::Variable(var, _) if var == "rows")
+                && matches!(&**inner, Array::Variable(var, _) if var == "cols")
+    ));
+    assert!(matches!(
+        &items[4].array,
+        Some(Array::Nested(outer, inner))
+            if matches!(**outer, Array::Unknown(Type::U8))
+                && matches!(**inner, Array::Expression(_))
+    ));
+
+    let test = "
+struct tiles {
+    cells [2][3][4]u8
+}";
+    let file = parse_file(test).unwrap();
+    let array = file.structs[0].items[0].array.as_ref().unwrap();
+    assert_eq!(array.dimensions().len(), 3);
+    assert_eq!(array.constant_len(), Some(24));
+
+    let test = "
+struct tiles {
+    cells [2][..]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "multi-dimensional rest of input");
+    let res = parse_file(test);
+    assert!(res.is_err(), "multi-dimensional rest of input");
+
+    let test = "
+struct tiles {
+    count u8 length_of:cells
+    cells [2][count]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "multi-dimensional arrays sized by length_of");
+    let res = parse_file(test);
+    assert!(res.is_err(), "multi-dimensional arrays sized by length_of");
+
+    let test = "
+struct tiles {
+    cells [2][3]u8 prefix:u16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(
+        res.is_ok(),
+        "multi-dimensional arrays with known size and prefix"
+    );
+    let res = parse_file(test);
+    assert!(
+        res.is_err(),
+        "multi-dimensional arrays with known size and prefix"
+    );
+
+    let test = "
+struct tiles {
+    cells [2][3]u8 max:4
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(
+        res.is_ok(),
+        "bounded multi-dimensional arrays with known size"
+    );
+    let res = parse_file(test);
+    assert!(
+        res.is_err(),
+        "bounded multi-dimensional arrays with known size"
+    );
+
+    let test = "
+struct tiles {
+    cells [2][undefined]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "undefined inner dimension");
+    let res = parse_file(test);
+    assert!(res.is_err(), "undefined inner dimension");
+
+    let test = "
+options strict:true
+struct flags {
+    on bool
+    history [4]bool strict:false
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "bools");
+    let file = parse_file(test).unwrap();
+    let items = &file.structs[0].items;
+    assert_eq!(items[0].kind, Type::Bool);
+    assert_eq!(items[0].fixed_size(), Some(1));
+    assert!(items[0].strict);
+    assert!(!items[1].strict);
+
+    let test = "
+struct flags {
+    on u8 strict:true
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "strict non-bool");
+    let res = parse_file(test);
+    assert!(res.is_err(), "strict non-bool");
+
+    let test = "
+struct flags {
+    on bool strict:yes
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "invalid strict value");
+    let res = parse_file(test);
+    assert!(res.is_err(), "invalid strict value");
+
+    let test = "
+struct flags {
+    on bool
+    data [on]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "bool as array size");
+    let res = parse_file(test);
+    assert!(res.is_err(), "bool as array size");
+
+    let test = "
+struct counters {
+    id varu32
+    delta vari64
+    items []u8 prefix:varu64
+    name string prefix:varu32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "varints");
+    let file = parse_file(test).unwrap();
+    let items = &file.structs[0].items;
+    assert_eq!(items[0].kind, Type::VarU32);
+    assert_eq!(items[0].fixed_size(), None);
+    assert_eq!(items[1].kind, Type::VarI64);
+    assert!(matches!(items[2].array, Some(Array::Unknown(Type::VarU64))));
+    assert_eq!(items[3].string_format, StringFormat::Prefixed(Type::VarU32));
+
+    let test = "
+enum kind : varu32 {
+    A = 1
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "varint enum");
+    let res = parse_file(test);
+    assert!(res.is_err(), "varint enum");
+
+    let test = "
+struct magic {
+    m varu32 = 7
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "varint magic value");
+    let res = parse_file(test);
+    assert!(res.is_err(), "varint magic value");
+
+    let test = "
+struct inventory {
+    count varu32 length_of:items
+    items [count]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "varint length_of");
+    let res = parse_file(test);
+    assert!(res.is_err(), "varint length_of");
+
+    let test = "
+struct sample {
+    left i24
+    right i24 endian:big
+    stamp u48
+    hash u128
+    delta i128
+    frames []u8 prefix:u24
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "odd width integers");
+    let file = parse_file(test).unwrap();
+    let items = &file.structs[0].items;
+    assert_eq!(items[0].kind, Type::I24);
+    assert_eq!(items[0].fixed_size(), Some(3));
+    assert_eq!(items[2].fixed_size(), Some(6));
+    assert_eq!(items[3].fixed_size(), Some(16));
+    assert!(matches!(items[5].array, Some(Array::Unknown(Type::U24))));
+
+    let test = "
+struct header {
+    magic u24 = 0x1000000
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "magic value too wide for u24");
+    let res = parse_file(test);
+    assert!(res.is_err(), "magic value too wide for u24");
+
+    let test = "
+enum hash : u128 {
+    A = 1
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "u128 enum");
+    let res = parse_file(test);
+    assert!(res.is_err(), "u128 enum");
+
+    let test = "
+struct hashes {
+    count u128
+    data [count]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "u128 as array size");
+    let res = parse_file(test);
+    assert!(res.is_err(), "u128 as array size");
+
+    let test = "
+struct hashes {
+    data []u8 prefix:i128
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "i128 prefix");
+    let res = parse_file(test);
+    assert!(res.is_err(), "i128 prefix");
+
+    let test = "
+struct position {
+    x q16.16
+    y q16.16 saturate:true
+    heading uq8.8
+    track [4]q4.4
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "fixed-point");
+    let file = parse_file(test).unwrap();
+    let items = &file.structs[0].items;
+    assert_eq!(
+        items[0].kind,
+        Type::Fixed {
+            signed: true,
+            int_bits: 16,
+            frac_bits: 16
+        }
+    );
+    assert_eq!(items[0].fixed_size(), Some(4));
+    assert!(!items[0].saturate);
+    assert!(items[1].saturate);
+    assert_eq!(items[2].kind.raw_type(), Some(Type::U16));
+    assert_eq!(items[3].fixed_size(), Some(4));
+
+    let test = "
+struct position {
+    x q12.12
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "fixed-point width");
+    let res = parse_file(test);
+    assert!(res.is_err(), "fixed-point width");
+
+    let test = "
+struct position {
+    x u16 saturate:true
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "saturate non-fixed");
+    let res = parse_file(test);
+    assert!(res.is_err(), "saturate non-fixed");
+
+    let test = "
+struct position {
+    n q8.8
+    data [n]u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "fixed-point as array size");
+    let res = parse_file(test);
+    assert!(res.is_err(), "fixed-point as array size");
+
+    let test = "
+options endian:little
+
+struct header options endian:big prefix:u32 {
+    kind u16
+    data []u8
+    low u16 endian:little
+}
+
+struct payload {
+    kind u16
+    data []u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "struct options");
+    let file = parse_file(test).unwrap();
+    let header = &file.structs[0];
+    assert!(matches!(header.options.endian, Endian::Big));
+    assert_eq!(header.options.array_size_type, Some(Type::U32));
+    assert!(matches!(header.items[0].byte_order, Endian::Big));
+    assert!(matches!(
+        header.items[1].array,
+        Some(Array::Unknown(Type::U32))
+    ));
+    assert!(matches!(header.items[2].byte_order, Endian::Little));
+    let payload = &file.structs[1];
+    assert!(matches!(payload.options.endian, Endian::Little));
+    assert!(matches!(payload.items[0].byte_order, Endian::Little));
+    assert!(matches!(
+        payload.items[1].array,
+        Some(Array::Unknown(Type::U8))
+    ));
+
+    let test = "
+struct header options scope:other {
+    kind u16
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "file-only struct option");
+    let res = parse_file(test);
+    assert!(res.is_err(), "file-only struct option");
+
+    let sources = [Source {
+        path: PathBuf::from("specs/common.zs"),
+        contents: "
+options scope:common
+enum unit : u8 { meters = 1 }
+struct vec3 {
+    x f32
+}"
+        .to_string(),
+    }];
+    let test = "
+include \"common.zs\"
+struct waypoint {
+    position vec3
+    unit     unit
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "include");
+    let file = parse_file_with_includes(test, Path::new("specs/main.zs"), &sources).unwrap();
+    assert_eq!(file.includes[0].path, "common.zs");
+    assert_eq!(file.includes[0].scope, "common");
+    assert_eq!(file.includes[0].types, vec!["vec3", "unit"]);
+    assert_eq!(
+        file.structs[0].items[0].kind,
+        Type::User("vec3".to_string())
+    );
+    assert!(matches!(
+        &file.structs[0].items[1].kind,
+        Type::Enum(name, _) if name == "unit"
+    ));
+    let res = parse_file(test);
+    assert!(res.is_err(), "include not loaded");
+
+    let test = "
+include \"common.zs\"
+struct vec3 {
+    x f32
+}";
+    let res = parse_file_with_includes(test, Path::new("specs/main.zs"), &sources);
+    assert!(res.is_err(), "type defined in include");
+
+    // the parsed file doesn't borrow from its input
+    let file = {
+        let test = String::from(
+            "
+struct header {
+    kind u16
+    body []u8 prefix:u16
+}",
+        );
+        parse_file(&test).unwrap()
+    };
+    assert_eq!(file.structs[0].name, "header");
+    assert_eq!(file.structs[0].items[1].name, "body");
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&file).unwrap();
+        let file: File = serde_json::from_str(&json).unwrap();
+        assert_eq!(file.structs[0].name, "header");
+        assert_eq!(file.structs[0].items[0].kind, Type::U16);
+        assert!(matches!(
+            file.structs[0].items[1].array,
+            Some(Array::Unknown(Type::U16))
+        ));
+    }
+
+    // every error is reported, pointing at where it is in the file
+    let test = "
+const n = 1
+const n = 2
+struct a {
+    x foo
+}
+struct b {
+    y u8 endian:sideways
+}
+struct c {
+    z bar
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "several errors");
+    let errors = parse_file(test).unwrap_err().0;
+    assert_eq!(errors.len(), 4);
+    assert!(matches!(
+        errors[0].line_col,
+        LineColLocation::Span((3, 7), _)
+    ));
+    assert!(matches!(
+        errors[1].line_col,
+        LineColLocation::Span((5, 5), _)
+    ));
+    assert!(matches!(
+        errors[2].line_col,
+        LineColLocation::Span((8, 10), _)
+    ));
+    assert!(matches!(
+        errors[3].line_col,
+        LineColLocation::Span((11, 5), _)
+    ));
+
+    let test = "
+struct header {
+    kind u16
+}
+struct header {
+    size u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "struct defined twice");
+    let res = parse_file(test);
+    assert!(res.is_err(), "struct defined twice");
+
+    let test = "
+struct header {
+    kind u16
+    kind u32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "field defined twice");
+    let res = parse_file(test);
+    assert!(res.is_err(), "field defined twice");
+
+    let test = "
+struct header {
+    _    u8
+    _    u8
+    kind u16
+}";
+    let res = parse_file(test);
+    assert!(res.is_ok(), "padding can repeat");
+
+    for name in ["type", "fn", "func", "range"] {
+        let test = format!(
+            "
+struct header {{
+    {} u16
+}}",
+            name
+        );
+        let res = StructParser::parse(Rule::file, &test);
+        assert!(res.is_ok(), "reserved field name {}", name);
+        let res = parse_file(&test);
+        assert!(res.is_err(), "reserved field name {}", name);
+    }
+
+    for name in ["Encode", "DecodeStream"] {
+        let test = format!(
+            "
+struct header {{
+    {} u16
+    bits u8 {{ {}:4, flags:4 }}
+}}",
+            name, name
+        );
+        let res = StructParser::parse(Rule::file, &test);
+        assert!(res.is_ok(), "Go method as field name {}", name);
+        let errors = parse_file(&test).unwrap_err().0;
+        assert_eq!(errors.len(), 2, "Go method as field name {}", name);
+    }
+
+    for definition in [
+        "struct Result {\n    x u8\n}",
+        "struct Vec {\n    x u8\n}",
+        "enum Option : u8 { none = 0 }",
+        "union PError { 1 => header }",
+        "extern CString",
+        "struct Narrow {\n    x u24\n}",
+        "enum FixedRaw : u8 { none = 0 }",
+    ] {
+        let test = format!(
+            "
+struct header {{
+    x u8
+}}
+{}
+",
+            definition
+        );
+        let res = StructParser::parse(Rule::file, &test);
+        assert!(res.is_ok(), "Rust name as type name: {}", definition);
+        let errors = parse_file(&test).unwrap_err().0;
+        assert!(
+            errors[0]
+                .variant
+                .message()
+                .ends_with("is already used by the generated Rust code"),
+            "Rust name as type name: {}",
+            definition
+        );
+    }
+
+    for definition in [
+        "struct binary {\n    x u8\n}",
+        "struct ps {\n    x u8\n}",
+        "union bytes { 1 => header }",
+        "enum int64 : u8 { none = 0 }",
+    ] {
+        let test = format!(
+            "
+struct header {{
+    x u8
+}}
+{}
+",
+            definition
+        );
+        let res = StructParser::parse(Rule::file, &test);
+        assert!(res.is_ok(), "Go name as type name: {}", definition);
+        let errors = parse_file(&test).unwrap_err().0;
+        assert!(
+            errors[0]
+                .variant
+                .message()
+                .ends_with("is already used by the generated Go code"),
+            "Go name as type name: {}",
+            definition
+        );
+    }
+
+    let test = "
+enum kind : u8 { self = 1 }";
+    let res = parse_file(test);
+    assert!(res.is_err(), "reserved variant name");
+
+    let test = "
+struct header {
+    version u8
+    bits u8 { version:4, flags:4 }
+}";
+    let res = parse_file(test);
+    assert!(res.is_err(), "bitfield member named like a field");
+
+    for length in [
+        "ratio f32",
+        "ratio string",
+        "ratio []u8",
+        "ratio u128",
+        "ratio header",
+    ] {
+        let test = format!(
+            "
+struct header {{
+    kind u16
+}}
+struct sample {{
+    {}
+    data [ratio]u8
+}}",
+            length
+        );
+        let res = StructParser::parse(Rule::file, &test);
+        assert!(res.is_ok(), "length {}", length);
+        let res = parse_file(&test);
+        assert!(res.is_err(), "length {}", length);
+    }
+
+    let test = "
+enum count : u8 { one = 1, two = 2 }
+struct sample {
+    n    count
+    data [n]u8
+}";
+    let file = parse_file(test).unwrap();
+    assert!(matches!(
+        &file.structs[0].items[1].array,
+        Some(Array::Variable(var, Type::Enum(..))) if var == "n"
+    ));
+
+    // lengths come from the same struct, not one that happens to have a field with that name
+    let test = "
+struct header {
+    count u8
+}
+struct sample {
+    data [count]u8
+    count u8
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "length declared later");
+    let res = parse_file(test);
+    assert!(res.is_err(), "length declared later");
+
+    // a struct with bad items still counts as defined, and all of its items are checked
+    let test = "
+struct a {
+    x foo
+    y u8 endian:sideways
+    z u8
+    z u16
+    w [q]u8
+}
+struct a {
+    v u8
+}
+struct c {
+    u a
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "struct with several bad items");
+    let errors = parse_file(test).unwrap_err().0;
+    assert_eq!(errors.len(), 5);
+    let expected = [
+        ((3, 5), "a: undefined type foo"),
+        ((4, 10), "unknown endianness sideways"),
+        ((6, 5), "a.z: field defined twice"),
+        (
+            (7, 8),
+            "q is neither a constant nor a field declared before this one",
+        ),
+        ((9, 8), "a: type defined twice"),
+    ];
+    for (err, (start, message)) in errors.iter().zip(expected) {
+        assert!(matches!(err.line_col, LineColLocation::Span(pos, _) if pos == start));
+        assert_eq!(err.variant.message(), message);
+    }
+
+    let test = "
+options scope:common go_import:example.com/specs/common-v2
+struct vec3 {
+    x f32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "go import path");
+    let file = parse_file(test).unwrap();
+    assert_eq!(
+        file.go_import.as_deref(),
+        Some("example.com/specs/common-v2")
+    );
+
+    let test = "
+struct vec3 options go_import:example.com/specs/common {
+    x f32
+}";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "file-only struct option");
+    let res = parse_file(test);
+    assert!(res.is_err(), "file-only struct option");
+
+    // a definition with errors still takes its name
+    let test = "
+struct header {
+    kind foo endian:sideways
+}
+enum header : u8 { one = 1 }
+union header { 1 => header }";
+    let res = StructParser::parse(Rule::file, test);
+    assert!(res.is_ok(), "duplicate of a struct with errors");
+    let errors = parse_file(test).unwrap_err().0;
+    let duplicates = errors
+        .iter()
+        .filter(|err| err.variant.message() == "header: type defined twice")
+        .count();
+    assert_eq!(duplicates, 2, "duplicate of a struct with errors");
+
+    // Go evaluates conditions and lengths as int64
+    for test in [
+        "
+struct sample {
+    n u64
+    x u8 if n > 5
+}",
+        "
+struct sample {
+    n varu64
+    data [n * 2]u8
+}",
+        "
+struct sample {
+    n u32
+    y u8 if n & 0x8000000000000000
+}",
+    ] {
+        let res = StructParser::parse(Rule::file, test);
+        assert!(res.is_ok(), "expression wider than i64");
+        let errors = parse_file(test).unwrap_err().0;
+        assert_eq!(errors.len(), 1, "expression wider than i64");
+    }
+    let test = "
+struct sample {
+    bits u64 { n:63, flag:1 }
+    x u8 if n > 5
+}";
+    let res = parse_file(test);
+    assert!(res.is_ok(), "narrow member of a u64 bitfield");
+
+    // a struct ending in the rest of the input can only be used as the last item
+    for (body, ok) in [
+        ("e env\n    x u8", false),
+        ("x u8\n    e [2]env", false),
+        ("x u8\n    w wrapper", true),
+        ("w wrapper\n    x u8", false),
+        ("kind u8\n    p payload tag:kind\n    x u8", false),
+        ("x u8\n    e env", true),
+    ] {
+        let test = format!(
+            "
+struct env {{
+    kind    u8
+    payload [..]byte
+}}
+struct wrapper {{
+    id  u8
+    env env
+}}
+union payload {{
+    1 => env
+    2 => wrapper
+}}
+struct k {{
+    {}
+}}",
+            body
+        );
+        let res = StructParser::parse(Rule::file, &test);
+        assert!(res.is_ok(), "struct holding the rest: {}", body);
+        let res = parse_file(&test);
+        assert_eq!(res.is_ok(), ok, "struct holding the rest: {}", body);
+    }
+
+    let sources = [Source {
+        path: PathBuf::from("specs/env.zs"),
+        contents: "
+struct env {
+    kind    u8
+    payload [..]byte
+}"
+        .to_string(),
+    }];
+    let test = "
+include \"env.zs\"
+struct k {
+    e env
+    x u8
+}";
+    let res = parse_file_with_includes(test, Path::new("specs/main.zs"), &sources);
+    assert!(res.is_err(), "included struct holding the rest");
+
+    for prefix in ["f32", "f64", "cstring", "q8.8"] {
+        let test = format!(
+            "
+struct sample options prefix:{} {{
+    data []u8
+}}",
+            prefix
+        );
+        let res = StructParser::parse(Rule::file, &test);
+        assert!(res.is_ok(), "struct prefix {}", prefix);
+        let res = parse_file(&test);
+        assert!(res.is_err(), "struct prefix {}", prefix);
+    }
+    let test = "
+options prefix:f32
+struct sample {
+    data []u8
+}";
+    let res = parse_file(test);
+    assert!(res.is_err(), "file prefix f32");
 }
@@ -1,52 +1,436 @@
 #[derive(Debug, PartialEq, Clone)]
-pub enum Type<'a> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Type {
     U8,
     U16,
     U32,
     U64,
+    /// Held in a `u32`, but only three bytes wide
+    U24,
+    I24,
+    /// Held in a `u64`, but only six bytes wide
+    U48,
+    U128,
     I8,
     I16,
     I32,
     I64,
+    I128,
+    /// LEB128, taking up as many bytes as the value needs
+    VarU32,
+    VarU64,
+    /// Zigzag encoded first, so small negative values stay short too
+    VarI64,
     F32,
     F64,
+    /// One byte, zero being false; see `Item::strict` for anything above one
+    Bool,
+    /// Stored as an integer `int_bits + frac_bits` wide, but held as a float; the
+    /// integer bits of signed ones include the sign
+    Fixed {
+        signed: bool,
+        int_bits: u32,
+        frac_bits: u32,
+    },
     Byte,
     String,
     CString,
-    User(&'a str),
+    User(String),
+    Enum(String, Box<Type>),
+    Union(String),
 }
 
-#[derive(Debug)]
-pub enum Array<'a> {
+impl Type {
+    /// Size in bytes of types that always encode to the same size
+    pub fn fixed_size(&self) -> Option<usize> {
+        match self {
+            Type::U8 | Type::I8 | Type::Byte | Type::Bool => Some(1),
+            Type::U16 | Type::I16 => Some(2),
+            Type::U32 | Type::I32 | Type::F32 => Some(4),
+            Type::U24 | Type::I24 => Some(3),
+            Type::U48 => Some(6),
+            Type::U64 | Type::I64 | Type::F64 => Some(8),
+            Type::U128 | Type::I128 => Some(16),
+            Type::Enum(_, kind) => kind.fixed_size(),
+            Type::Fixed {
+                int_bits,
+                frac_bits,
+                ..
+            } => Some((int_bits + frac_bits) as usize / 8),
+            Type::VarU32
+            | Type::VarU64
+            | Type::VarI64
+            | Type::String
+            | Type::CString
+            | Type::User(_)
+            | Type::Union(_) => None,
+        }
+    }
+
+    pub fn is_varint(&self) -> bool {
+        matches!(self, Type::VarU32 | Type::VarU64 | Type::VarI64)
+    }
+
+    /// Integer type that fixed-point numbers are stored as
+    pub fn raw_type(&self) -> Option<Type> {
+        let signed = match self {
+            Type::Fixed { signed, .. } => *signed,
+            _ => return None,
+        };
+        Some(match (signed, self.fixed_size()?) {
+            (true, 1) => Type::I8,
+            (true, 2) => Type::I16,
+            (true, 4) => Type::I32,
+            (true, 8) => Type::I64,
+            (false, 1) => Type::U8,
+            (false, 2) => Type::U16,
+            (false, 4) => Type::U32,
+            (false, 8) => Type::U64,
+            _ => return None,
+        })
+    }
+
+    /// Integers encoded in fewer bytes than the type holding them
+    pub fn is_narrow(&self) -> bool {
+        matches!(self, Type::U24 | Type::I24 | Type::U48)
+    }
+}
+
+/// Expression over earlier fields; constants are replaced by their value
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Expr {
+    Number(i128),
+    Variable(String),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Whether this evaluates to a boolean rather than an integer
+    pub fn is_boolean(&self) -> bool {
+        match self {
+            Expr::Number(_) | Expr::Variable(_) => false,
+            Expr::Not(_) => true,
+            Expr::Binary(op, ..) => op.is_boolean(),
+        }
+    }
+
+    /// Whether evaluating this can fail
+    pub fn is_checked(&self) -> bool {
+        match self {
+            Expr::Number(_) | Expr::Variable(_) => false,
+            Expr::Not(operand) => operand.is_checked(),
+            Expr::Binary(op, lhs, rhs) => {
+                op.is_arithmetic() || lhs.is_checked() || rhs.is_checked()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    /// Operators taking booleans; integer operands are compared against zero
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOp::Or | BinaryOp::And)
+    }
+
+    /// Operators producing booleans
+    pub fn is_boolean(&self) -> bool {
+        !matches!(self, BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::BitAnd)
+            && !self.is_arithmetic()
+    }
+
+    /// Operators that can overflow or divide by zero, so they need checking
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Array {
     Constant(usize),
-    Unknown(Type<'a>),
-    Variable(&'a str, Type<'a>),
+    Unknown(Type),
+    Variable(String, Type),
+    /// Computed from earlier fields, never just a single field or number
+    Expression(Expr),
+    /// Sized by the named `Storage::LengthOf` item, which is written from the array itself
+    Derived(String),
+    /// Whatever is left of the input, so only the last item can have this
+    Rest,
+    /// Outer dimension of a multi-dimensional array; each element is an array of the inner one
+    Nested(Box<Array>, Box<Array>),
+}
+
+impl Array {
+    /// Every dimension, starting from the outermost
+    pub fn dimensions(&self) -> Vec<&Array> {
+        match self {
+            Array::Nested(outer, inner) => {
+                let mut dims = vec![&**outer];
+                dims.extend(inner.dimensions());
+                dims
+            }
+            dim => vec![dim],
+        }
+    }
+
+    /// Same as `dimensions`, but mutable
+    pub fn dimensions_mut(&mut self) -> Vec<&mut Array> {
+        match self {
+            Array::Nested(outer, inner) => {
+                let mut dims = vec![&mut **outer];
+                dims.extend(inner.dimensions_mut());
+                dims
+            }
+            dim => vec![dim],
+        }
+    }
+
+    /// The dimension each element belongs to; cstrings take their length from this
+    pub fn innermost(&self) -> &Array {
+        match self {
+            Array::Nested(_, inner) => inner.innermost(),
+            dim => dim,
+        }
+    }
+
+    /// Total element count of arrays whose dimensions are all constant
+    pub fn constant_len(&self) -> Option<usize> {
+        match self {
+            Array::Constant(count) => Some(*count),
+            Array::Nested(outer, inner) => Some(outer.constant_len()? * inner.constant_len()?),
+            _ => None,
+        }
+    }
+}
+
+/// What the length prefix of an `Array::Unknown` counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PrefixMode {
+    Count,
+    /// Total size of the encoded elements, which are read until it's used up
+    Bytes,
+}
+
+/// How the length of `Type::String` items is known
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StringFormat {
+    /// Prefixed with its length as this unsigned integer type
+    Prefixed(Type),
+    /// Always this many bytes, padded with spaces which are trimmed when decoding
+    Fixed(usize),
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Endian {
     Little,
     Big,
 }
 
-#[derive(Debug)]
-pub struct Item<'a> {
-    pub name: &'a str,
-    pub kind: Type<'a>,
-    pub array: Option<Array<'a>>,
+/// How an item is kept in the generated struct
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Storage {
+    /// Regular struct field
+    Field,
+    /// Not stored; written as zeroes and skipped over when decoding
+    Padding,
+    /// Not stored; always written with this value, decoding fails on anything else
+    Magic(i128),
+    /// Not stored itself; its bits are unpacked into a field per `Bitfield`
+    Bitfield,
+    /// Not stored; always written with the length of the named array
+    LengthOf(String),
+}
+
+/// Named group of bits inside a bitfield item
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bitfield {
+    pub name: String,
+    pub width: u32,
+    /// Position of the lowest bit; fields are packed starting from the most significant bit
+    pub shift: u32,
+}
+
+/// Where something was written in its spec file, as byte offsets
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Item {
+    pub name: String,
+    /// The whole item, type and options included
+    pub span: Span,
+    pub storage: Storage,
+    pub kind: Type,
+    pub array: Option<Array>,
     pub byte_order: Endian,
-    pub type_alias: Option<&'a str>,
+    pub type_alias: Option<String>,
+    pub tag: Option<String>,
+    pub max_array_size: Option<usize>,
+    pub prefix_mode: PrefixMode,
+    /// Only used by `Type::String` items, including each element of string arrays
+    pub string_format: StringFormat,
+    /// Decoding fails on bool bytes other than 0 and 1, instead of reading them as true
+    pub strict: bool,
+    /// Fixed-point values out of range get clamped when encoding, instead of failing
+    pub saturate: bool,
+    pub bits: Vec<Bitfield>,
+    /// Only present when this holds, otherwise skipped entirely
+    pub condition: Option<Expr>,
+}
+
+impl Item {
+    /// Size in bytes of items that always encode to the same size
+    pub fn fixed_size(&self) -> Option<usize> {
+        let size = self.kind.fixed_size()?;
+        match &self.array {
+            None => Some(size),
+            Some(array) => Some(size * array.constant_len()?),
+        }
+    }
+
+    /// Each element of a multi-dimensional array, as an item of its own with the inner dimensions
+    pub fn row(&self) -> Option<Item> {
+        match &self.array {
+            Some(Array::Nested(_, inner)) => Some(Item {
+                array: Some((**inner).clone()),
+                type_alias: None,
+                condition: None,
+                ..self.clone()
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Item defaults of a struct, from its own options or else the file's
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StructOptions {
+    pub endian: Endian,
+    /// Unset so strings can keep their own default
+    pub array_size_type: Option<Type>,
+    pub strict: bool,
+    pub saturate: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Struct {
+    pub name: String,
+    /// Just the name
+    pub span: Span,
+    pub options: StructOptions,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Constant {
+    pub name: String,
+    pub value: usize,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Variant {
+    pub name: String,
+    pub value: i128,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Enum {
+    pub name: String,
+    /// Just the name
+    pub span: Span,
+    pub kind: Type,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Case {
+    pub value: i128,
+    pub kind: String,
+    pub span: Span,
 }
 
-#[derive(Debug)]
-pub struct Struct<'a> {
-    pub name: &'a str,
-    pub items: Vec<Item<'a>>,
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Union {
+    pub name: String,
+    /// Just the name
+    pub span: Span,
+    pub cases: Vec<Case>,
+}
+
+/// Another spec file whose types are used instead of being defined again
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Include {
+    /// As written, relative to the including file
+    pub path: String,
+    pub span: Span,
+    pub scope: String,
+    /// Import path of the Go package generated for it, from its `go_import` option
+    pub go_import: Option<String>,
+    /// Structs, enums and unions defined there; not those it includes itself
+    pub types: Vec<String>,
+    /// Needed to encode and decode fields of an included union type
+    pub unions: Vec<Union>,
+    /// Structs ending in an array that holds the rest of the input, including those
+    /// of the files it includes
+    pub rest_types: Vec<String>,
 }
 
-#[derive(Debug)]
-pub struct File<'a> {
+/// Everything parsed from a spec file; it owns its strings so it can outlive the input,
+/// and can be stored as JSON with the `serde` feature
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct File {
     pub scope: String,
-    pub raw_imports: Vec<&'a str>,
-    pub structs: Vec<Struct<'a>>,
+    pub raw_imports: Vec<String>,
+    /// Import path of the Go package generated for this file, for files that include it
+    pub go_import: Option<String>,
+    pub includes: Vec<Include>,
+    pub constants: Vec<Constant>,
+    pub enums: Vec<Enum>,
+    pub unions: Vec<Union>,
+    pub structs: Vec<Struct>,
 }
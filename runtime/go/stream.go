@@ -3,10 +3,79 @@ package stream
 import (
 	"bytes"
 	"encoding/binary"
+	"errors"
 	"io"
 	"math"
 )
 
+var (
+	ErrUnknownVariant = errors.New("pstruct: unknown variant")
+	ErrTagMismatch    = errors.New("pstruct: union tag does not match its value")
+	ErrArrayTooLarge  = errors.New("pstruct: array larger than its maximum size")
+	ErrBadMagic       = errors.New("pstruct: magic value does not match")
+	ErrOutOfRange     = errors.New("pstruct: value does not fit in its field")
+	ErrMissingField   = errors.New("pstruct: optional field is missing but its condition holds")
+	ErrInvalidBool    = errors.New("pstruct: bool is neither 0 nor 1")
+	ErrLengthMismatch = errors.New("pstruct: array length does not match the field sizing it")
+)
+
+// Uint128 holds the two halves of a 128-bit unsigned integer
+type Uint128 struct {
+	Hi, Lo uint64
+}
+
+// Int128 holds the two halves of a 128-bit two's complement integer
+type Int128 struct {
+	Hi int64
+	Lo uint64
+}
+
+// Checked does arithmetic for lengths and conditions, remembering whether
+// anything overflowed or divided by zero instead of panicking
+type Checked struct {
+	Overflow bool
+}
+
+func (c *Checked) Add(a, b int64) int64 {
+	r := a + b
+	if (r > a) != (b > 0) {
+		c.Overflow = true
+	}
+	return r
+}
+
+func (c *Checked) Sub(a, b int64) int64 {
+	r := a - b
+	if (r < a) != (b > 0) {
+		c.Overflow = true
+	}
+	return r
+}
+
+func (c *Checked) Mul(a, b int64) int64 {
+	r := a * b
+	if a != 0 && (r/a != b || (a == -1 && b == math.MinInt64)) {
+		c.Overflow = true
+	}
+	return r
+}
+
+func (c *Checked) Div(a, b int64) int64 {
+	if b == 0 || (a == math.MinInt64 && b == -1) {
+		c.Overflow = true
+		return 0
+	}
+	return a / b
+}
+
+func (c *Checked) Rem(a, b int64) int64 {
+	if b == 0 || (a == math.MinInt64 && b == -1) {
+		c.Overflow = true
+		return 0
+	}
+	return a % b
+}
+
 type Struct interface {
 	Encode() ([]byte, error)
 	EncodeStream(stream *Stream) error
@@ -14,6 +83,11 @@ type Struct interface {
 	DecodeStream(stream *Stream) error
 }
 
+// isBigEndian tells which end narrow integers keep their bytes at
+func isBigEndian(b binary.ByteOrder) bool {
+	return b.Uint16([]byte{0, 1}) == 1
+}
+
 func read(r io.Reader, size int) ([]byte, error) {
 	b := make([]byte, size)
 	if _, err := io.ReadFull(r, b); err != nil {
@@ -32,6 +106,13 @@ func (s *Stream) WriteU8(i uint8) error {
 	return err
 }
 
+func (s *Stream) WriteBool(v bool) error {
+	if v {
+		return s.WriteU8(1)
+	}
+	return s.WriteU8(0)
+}
+
 func (s *Stream) WriteU16(i uint16, b binary.ByteOrder) error {
 	data := make([]byte, 2)
 	b.PutUint16(data, i)
@@ -53,6 +134,47 @@ func (s *Stream) WriteU64(i uint64, b binary.ByteOrder) error {
 	return err
 }
 
+// WriteU24 writes the low three bytes of i, failing when the rest isn't zero
+func (s *Stream) WriteU24(i uint32, b binary.ByteOrder) error {
+	if i >= 1<<24 {
+		return ErrOutOfRange
+	}
+	return s.writeNarrow(uint64(i), 3, b)
+}
+
+// WriteU48 writes the low six bytes of i, failing when the rest isn't zero
+func (s *Stream) WriteU48(i uint64, b binary.ByteOrder) error {
+	if i >= 1<<48 {
+		return ErrOutOfRange
+	}
+	return s.writeNarrow(i, 6, b)
+}
+
+// writeNarrow writes the low width bytes of i
+func (s *Stream) writeNarrow(i uint64, width int, b binary.ByteOrder) error {
+	data := make([]byte, 8)
+	b.PutUint64(data, i)
+	if isBigEndian(b) {
+		data = data[8-width:]
+	} else {
+		data = data[:width]
+	}
+	_, err := s.Writer.Write(data)
+	return err
+}
+
+func (s *Stream) WriteU128(i Uint128, b binary.ByteOrder) error {
+	first, second := i.Lo, i.Hi
+	if isBigEndian(b) {
+		first, second = i.Hi, i.Lo
+	}
+	err := s.WriteU64(first, b)
+	if err != nil {
+		return err
+	}
+	return s.WriteU64(second, b)
+}
+
 func (s *Stream) WriteI8(i int8) error {
 	_, err := s.Writer.Write([]byte{byte(i)})
 	return err
@@ -70,6 +192,51 @@ func (s *Stream) WriteI64(i int64, b binary.ByteOrder) error {
 	return s.WriteU64(uint64(i), b)
 }
 
+// WriteI24 writes the low three bytes of i, failing when it doesn't fit in them
+func (s *Stream) WriteI24(i int32, b binary.ByteOrder) error {
+	if i < -1<<23 || i >= 1<<23 {
+		return ErrOutOfRange
+	}
+	return s.writeNarrow(uint64(i), 3, b)
+}
+
+func (s *Stream) WriteI128(i Int128, b binary.ByteOrder) error {
+	return s.WriteU128(Uint128{uint64(i.Hi), i.Lo}, b)
+}
+
+// WriteFixed64 writes f as a fixed-point number with frac bits after the binary
+// point, in an integer that is size bytes wide. Values it can't hold fail,
+// unless saturate clamps them to its range
+func (s *Stream) WriteFixed64(f float64, size int, frac uint, signed, saturate bool, b binary.ByteOrder) error {
+	scaled := math.Round(math.Ldexp(f, int(frac)))
+	bits := uint(size * 8)
+	// the raw values are written as their two's complement
+	low, high := 0.0, math.Ldexp(1, int(bits))
+	max, min := uint64(1)<<bits-1, uint64(0)
+	if signed {
+		low, high = -math.Ldexp(1, int(bits-1)), math.Ldexp(1, int(bits-1))
+		max, min = uint64(1)<<(bits-1)-1, uint64(1)<<(bits-1)
+	}
+	var n uint64
+	switch {
+	case scaled >= low && scaled < high && signed:
+		n = uint64(int64(scaled))
+	case scaled >= low && scaled < high:
+		n = uint64(scaled)
+	case !saturate:
+		return ErrOutOfRange
+	case scaled >= high:
+		n = max
+	case scaled < low:
+		n = min
+	}
+	return s.writeNarrow(n, size, b)
+}
+
+func (s *Stream) WriteFixed32(f float32, size int, frac uint, signed, saturate bool, b binary.ByteOrder) error {
+	return s.WriteFixed64(float64(f), size, frac, signed, saturate, b)
+}
+
 func (s *Stream) WriteF32(f float32, b binary.ByteOrder) error {
 	return s.WriteU32(math.Float32bits(f), b)
 }
@@ -78,13 +245,36 @@ func (s *Stream) WriteF64(f float64, b binary.ByteOrder) error {
 	return s.WriteU64(math.Float64bits(f), b)
 }
 
+// WriteVarU64 writes i as LEB128, taking up as many bytes as it needs
+func (s *Stream) WriteVarU64(i uint64) error {
+	buf := make([]byte, binary.MaxVarintLen64)
+	n := binary.PutUvarint(buf, i)
+	_, err := s.Writer.Write(buf[:n])
+	return err
+}
+
+func (s *Stream) WriteVarU32(i uint32) error {
+	return s.WriteVarU64(uint64(i))
+}
+
+// WriteVarI64 zigzag encodes i first, so small negative values stay short
+func (s *Stream) WriteVarI64(i int64) error {
+	return s.WriteVarU64(uint64(i<<1) ^ uint64(i>>63))
+}
+
 func (s *Stream) WriteBytes(buf []byte) error {
 	_, err := s.Writer.Write(buf)
 	return err
 }
 
 func (s *Stream) WriteString(str string, b binary.ByteOrder) error {
-	err := s.WriteU16(uint16(len(str)), b)
+	return s.WriteStringPrefixed(str, 2, b)
+}
+
+// WriteStringPrefixed writes str after its length, as an unsigned integer
+// that is size bytes wide
+func (s *Stream) WriteStringPrefixed(str string, size int, b binary.ByteOrder) error {
+	err := s.writeLength(len(str), size, b)
 	if err != nil {
 		return err
 	}
@@ -92,6 +282,17 @@ func (s *Stream) WriteString(str string, b binary.ByteOrder) error {
 	return err
 }
 
+// WriteStringFixed writes str padded with spaces to exactly width bytes
+func (s *Stream) WriteStringFixed(str string, width int) error {
+	if len(str) > width {
+		return ErrOutOfRange
+	}
+	b := bytes.Repeat([]byte{' '}, width)
+	copy(b, str)
+	_, err := s.Writer.Write(b)
+	return err
+}
+
 func (s *Stream) WriteCString(str string, i int) error {
 	b := make([]byte, i)
 	copy(b, str)
@@ -99,6 +300,38 @@ func (s *Stream) WriteCString(str string, i int) error {
 	return err
 }
 
+// WriteCStringPrefixed writes str and its null byte after their length, as an
+// unsigned integer that is size bytes wide
+func (s *Stream) WriteCStringPrefixed(str string, size int, b binary.ByteOrder) error {
+	err := s.writeLength(len(str)+1, size, b)
+	if err != nil {
+		return err
+	}
+	return s.WriteCStringUnsized(str)
+}
+
+// writeLength writes n as an unsigned integer that is size bytes wide, or as
+// a varint when size is 0
+func (s *Stream) writeLength(n int, size int, b binary.ByteOrder) error {
+	if size > 0 && size < 8 && uint64(n) >= 1<<(8*size) {
+		return ErrOutOfRange
+	}
+	switch size {
+	case 0:
+		return s.WriteVarU64(uint64(n))
+	case 1:
+		return s.WriteU8(uint8(n))
+	case 2:
+		return s.WriteU16(uint16(n), b)
+	case 3, 6:
+		return s.writeNarrow(uint64(n), size, b)
+	case 4:
+		return s.WriteU32(uint32(n), b)
+	default:
+		return s.WriteU64(uint64(n), b)
+	}
+}
+
 func (s *Stream) WriteCStringUnsized(str string) error {
 	_, err := s.Writer.Write([]byte(str))
 	if err != nil {
@@ -116,6 +349,24 @@ func (s *Stream) ReadU8() (uint8, error) {
 	return buf[0], nil
 }
 
+// ReadBool reads any byte other than 0 as true
+func (s *Stream) ReadBool() (bool, error) {
+	v, err := s.ReadU8()
+	return v != 0, err
+}
+
+// ReadBoolStrict fails on bytes other than 0 and 1
+func (s *Stream) ReadBoolStrict() (bool, error) {
+	v, err := s.ReadU8()
+	if err != nil {
+		return false, err
+	}
+	if v > 1 {
+		return false, ErrInvalidBool
+	}
+	return v == 1, nil
+}
+
 func (s *Stream) ReadU16(b binary.ByteOrder) (uint16, error) {
 	buf, err := read(s.Reader, 2)
 	if err != nil {
@@ -140,6 +391,45 @@ func (s *Stream) ReadU64(b binary.ByteOrder) (uint64, error) {
 	return b.Uint64(buf), nil
 }
 
+func (s *Stream) ReadU24(b binary.ByteOrder) (uint32, error) {
+	i, err := s.readNarrow(3, b)
+	return uint32(i), err
+}
+
+func (s *Stream) ReadU48(b binary.ByteOrder) (uint64, error) {
+	return s.readNarrow(6, b)
+}
+
+// readNarrow reads width bytes into the low bytes of an uint64
+func (s *Stream) readNarrow(width int, b binary.ByteOrder) (uint64, error) {
+	buf, err := read(s.Reader, width)
+	if err != nil {
+		return 0, err
+	}
+	data := make([]byte, 8)
+	if isBigEndian(b) {
+		copy(data[8-width:], buf)
+	} else {
+		copy(data, buf)
+	}
+	return b.Uint64(data), nil
+}
+
+func (s *Stream) ReadU128(b binary.ByteOrder) (Uint128, error) {
+	first, err := s.ReadU64(b)
+	if err != nil {
+		return Uint128{}, err
+	}
+	second, err := s.ReadU64(b)
+	if err != nil {
+		return Uint128{}, err
+	}
+	if isBigEndian(b) {
+		return Uint128{first, second}, nil
+	}
+	return Uint128{second, first}, nil
+}
+
 func (s *Stream) ReadI8() (int8, error) {
 	buf, err := read(s.Reader, 1)
 	if err != nil {
@@ -172,6 +462,36 @@ func (s *Stream) ReadI64(b binary.ByteOrder) (int64, error) {
 	return int64(b.Uint64(buf)), nil
 }
 
+// ReadI24 sign extends the three bytes it reads
+func (s *Stream) ReadI24(b binary.ByteOrder) (int32, error) {
+	i, err := s.readNarrow(3, b)
+	return int32(uint32(i)<<8) >> 8, err
+}
+
+func (s *Stream) ReadI128(b binary.ByteOrder) (Int128, error) {
+	i, err := s.ReadU128(b)
+	return Int128{int64(i.Hi), i.Lo}, err
+}
+
+// ReadFixed64 reads a fixed-point number with frac bits after the binary point,
+// from an integer that is size bytes wide
+func (s *Stream) ReadFixed64(size int, frac uint, signed bool, b binary.ByteOrder) (float64, error) {
+	n, err := s.readNarrow(size, b)
+	if err != nil {
+		return 0, err
+	}
+	if signed {
+		unused := 64 - uint(size*8)
+		return math.Ldexp(float64(int64(n<<unused)>>unused), -int(frac)), nil
+	}
+	return math.Ldexp(float64(n), -int(frac)), nil
+}
+
+func (s *Stream) ReadFixed32(size int, frac uint, signed bool, b binary.ByteOrder) (float32, error) {
+	f, err := s.ReadFixed64(size, frac, signed, b)
+	return float32(f), err
+}
+
 func (s *Stream) ReadF32(b binary.ByteOrder) (float32, error) {
 	f, err := s.ReadU32(b)
 	if err != nil {
@@ -188,17 +508,61 @@ func (s *Stream) ReadF64(b binary.ByteOrder) (float64, error) {
 	return math.Float64frombits(f), nil
 }
 
+// readUvarint fails on values that take more than bits bits
+func (s *Stream) readUvarint(bits uint) (uint64, error) {
+	var n uint64
+	for shift := uint(0); ; shift += 7 {
+		b, err := s.ReadU8()
+		if err != nil {
+			return 0, err
+		}
+		part := uint64(b & 0x7f)
+		if shift >= bits || (bits-shift < 7 && part>>(bits-shift) != 0) {
+			return 0, ErrOutOfRange
+		}
+		n |= part << shift
+		if b&0x80 == 0 {
+			return n, nil
+		}
+	}
+}
+
+func (s *Stream) ReadVarU32() (uint32, error) {
+	i, err := s.readUvarint(32)
+	return uint32(i), err
+}
+
+func (s *Stream) ReadVarU64() (uint64, error) {
+	return s.readUvarint(64)
+}
+
+func (s *Stream) ReadVarI64() (int64, error) {
+	i, err := s.readUvarint(64)
+	return int64(i>>1) ^ -int64(i&1), err
+}
+
 func (s *Stream) ReadBytes(i int) ([]byte, error) {
 	return read(s.Reader, int(i))
 }
 
+// ReadRest reads everything left in the stream
+func (s *Stream) ReadRest() ([]byte, error) {
+	return io.ReadAll(s.Reader)
+}
+
 func (s *Stream) ReadString(b binary.ByteOrder) (string, error) {
-	i, err := s.ReadU16(b)
+	return s.ReadStringPrefixed(2, b)
+}
+
+// ReadStringPrefixed reads a string after its length, as an unsigned integer
+// that is size bytes wide
+func (s *Stream) ReadStringPrefixed(size int, b binary.ByteOrder) (string, error) {
+	i, err := s.readLength(size, b)
 	if err != nil {
 		return "", err
 	}
 
-	buf, err := read(s.Reader, int(i))
+	buf, err := read(s.Reader, i)
 	if err != nil {
 		return "", err
 	}
@@ -206,6 +570,15 @@ func (s *Stream) ReadString(b binary.ByteOrder) (string, error) {
 	return string(buf), nil
 }
 
+// ReadStringFixed reads width bytes, dropping the spaces padding them
+func (s *Stream) ReadStringFixed(width int) (string, error) {
+	buf, err := read(s.Reader, width)
+	if err != nil {
+		return "", err
+	}
+	return string(bytes.TrimRight(buf, " ")), nil
+}
+
 func (s *Stream) ReadCString(i int) (string, error) {
 	buf, err := read(s.Reader, int(i))
 	if err != nil {
@@ -218,6 +591,64 @@ func (s *Stream) ReadCString(i int) (string, error) {
 	return string(buf[:end]), nil
 }
 
+// ReadCStringPrefixed reads a null-terminated string after its length, as an
+// unsigned integer that is size bytes wide
+func (s *Stream) ReadCStringPrefixed(size int, b binary.ByteOrder) (string, error) {
+	i, err := s.readLength(size, b)
+	if err != nil {
+		return "", err
+	}
+	return s.ReadCString(i)
+}
+
+// readLength reads an unsigned integer that is size bytes wide, or a varint
+// when size is 0
+func (s *Stream) readLength(size int, b binary.ByteOrder) (int, error) {
+	var n uint64
+	switch size {
+	case 0:
+		i, err := s.ReadVarU64()
+		if err != nil {
+			return 0, err
+		}
+		n = i
+	case 1:
+		i, err := s.ReadU8()
+		if err != nil {
+			return 0, err
+		}
+		n = uint64(i)
+	case 2:
+		i, err := s.ReadU16(b)
+		if err != nil {
+			return 0, err
+		}
+		n = uint64(i)
+	case 3, 6:
+		i, err := s.readNarrow(size, b)
+		if err != nil {
+			return 0, err
+		}
+		n = i
+	case 4:
+		i, err := s.ReadU32(b)
+		if err != nil {
+			return 0, err
+		}
+		n = uint64(i)
+	default:
+		i, err := s.ReadU64(b)
+		if err != nil {
+			return 0, err
+		}
+		n = i
+	}
+	if n > math.MaxInt32 {
+		return 0, ErrOutOfRange
+	}
+	return int(n), nil
+}
+
 func (s *Stream) ReadCStringUnsized() (string, error) {
 	b, err := s.ReadU8()
 	if err != nil {
@@ -234,6 +665,20 @@ func (s *Stream) ReadCStringUnsized() (string, error) {
 	return string(bytes), nil
 }
 
+// Limit returns a stream reading at most n bytes from s, for sequences
+// prefixed with their size in bytes
+func (s *Stream) Limit(n int64) *Stream {
+	return &Stream{Reader: &io.LimitedReader{R: s.Reader, N: n}}
+}
+
+// Remaining is the number of bytes a stream returned by Limit can still read
+func (s *Stream) Remaining() int64 {
+	if limited, ok := s.Reader.(*io.LimitedReader); ok {
+		return limited.N
+	}
+	return 0
+}
+
 func NewStream() *Stream {
 	return &Stream{}
 }
@@ -1,14 +1,23 @@
 #![no_std]
 extern crate alloc;
 
+use alloc::vec::Vec;
 use core::error::Error;
 use core::fmt::Display;
-use alloc::vec::Vec;
 
 #[derive(Debug)]
 pub enum PError {
     BufTooSmall,
     NotEnoughData,
+    UnknownVariant,
+    TagMismatch,
+    ArrayTooLarge,
+    BadMagic,
+    OutOfRange,
+    MissingField,
+    InvalidBool,
+    LengthMismatch,
+    InvalidUtf8,
 }
 
 impl Error for PError {}
@@ -190,9 +199,257 @@ basic_primitive!(i8);
 basic_primitive!(i16);
 basic_primitive!(i32);
 basic_primitive!(i64);
+basic_primitive!(u128);
+basic_primitive!(i128);
 basic_primitive!(f32);
 basic_primitive!(f64);
 
+/// Integers encoded in fewer bytes than the type holding them, like 24 bits in a `u32`
+pub trait Narrow: Sized {
+    /// Fails when the value doesn't fit in `width` bytes
+    fn encode_narrow_le(&self, width: usize, buf: &mut [u8]) -> Result<()>;
+    fn encode_narrow_be(&self, width: usize, buf: &mut [u8]) -> Result<()>;
+    fn decode_narrow_le(&mut self, width: usize, data: &[u8]) -> Result<()>;
+    fn decode_narrow_be(&mut self, width: usize, data: &[u8]) -> Result<()>;
+}
+
+macro_rules! narrow_primitive {
+    ($ty:ty) => {
+        impl Narrow for $ty {
+            fn encode_narrow_le(&self, width: usize, buf: &mut [u8]) -> Result<()> {
+                let size = core::mem::size_of::<$ty>();
+                let unused = (size - width) as u32 * 8;
+                // shifting back and forth sign extends signed types
+                if (*self << unused) >> unused != *self {
+                    return Err(PError::OutOfRange);
+                }
+                if buf.len() < width {
+                    return Err(PError::BufTooSmall);
+                }
+                buf[0..width].copy_from_slice(&self.to_le_bytes()[..width]);
+                Ok(())
+            }
+            fn encode_narrow_be(&self, width: usize, buf: &mut [u8]) -> Result<()> {
+                let size = core::mem::size_of::<$ty>();
+                let unused = (size - width) as u32 * 8;
+                if (*self << unused) >> unused != *self {
+                    return Err(PError::OutOfRange);
+                }
+                if buf.len() < width {
+                    return Err(PError::BufTooSmall);
+                }
+                buf[0..width].copy_from_slice(&self.to_be_bytes()[size - width..]);
+                Ok(())
+            }
+            fn decode_narrow_le(&mut self, width: usize, data: &[u8]) -> Result<()> {
+                let size = core::mem::size_of::<$ty>();
+                if data.len() < width {
+                    return Err(PError::NotEnoughData);
+                }
+                let mut bytes = [0; core::mem::size_of::<$ty>()];
+                bytes[..width].copy_from_slice(&data[..width]);
+                let unused = (size - width) as u32 * 8;
+                *self = (<$ty>::from_le_bytes(bytes) << unused) >> unused;
+                Ok(())
+            }
+            fn decode_narrow_be(&mut self, width: usize, data: &[u8]) -> Result<()> {
+                let size = core::mem::size_of::<$ty>();
+                if data.len() < width {
+                    return Err(PError::NotEnoughData);
+                }
+                let mut bytes = [0; core::mem::size_of::<$ty>()];
+                bytes[size - width..].copy_from_slice(&data[..width]);
+                let unused = (size - width) as u32 * 8;
+                *self = (<$ty>::from_be_bytes(bytes) << unused) >> unused;
+                Ok(())
+            }
+        }
+    };
+}
+
+narrow_primitive!(u32);
+narrow_primitive!(i32);
+narrow_primitive!(u64);
+
+/// Integers that fixed-point numbers are stored as
+pub trait FixedRaw: Primitive + Copy + Default {
+    const MIN: f64;
+    const MAX: f64;
+    /// Truncates, saturating at the ends of the range
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! fixed_raw {
+    ($ty:ty) => {
+        impl FixedRaw for $ty {
+            const MIN: f64 = <$ty>::MIN as f64;
+            const MAX: f64 = <$ty>::MAX as f64;
+            fn from_f64(value: f64) -> Self {
+                value as $ty
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+fixed_raw!(u8);
+fixed_raw!(u16);
+fixed_raw!(u32);
+fixed_raw!(u64);
+fixed_raw!(i8);
+fixed_raw!(i16);
+fixed_raw!(i32);
+fixed_raw!(i64);
+
+/// Floats stored as integers with `frac_bits` of their bits after the binary point
+pub trait Fixed: Sized {
+    /// Fails on values that `T` can't hold, unless `saturate` clamps them to its range
+    fn to_fixed<T: FixedRaw>(&self, frac_bits: u32, saturate: bool) -> Result<T>;
+    fn from_fixed<T: FixedRaw>(raw: T, frac_bits: u32) -> Self;
+}
+
+impl Fixed for f64 {
+    fn to_fixed<T: FixedRaw>(&self, frac_bits: u32, saturate: bool) -> Result<T> {
+        let scaled = *self * (1u128 << frac_bits) as f64;
+        // core has no round(), and casting truncates, so this rounds half away from zero
+        let rounded = if scaled < 0.0 {
+            scaled - 0.5
+        } else {
+            scaled + 0.5
+        };
+        // also false for NaN, which saturates to zero
+        let in_range = rounded > T::MIN - 1.0 && rounded < T::MAX + 1.0;
+        if !in_range && !saturate {
+            return Err(PError::OutOfRange);
+        }
+        Ok(T::from_f64(rounded))
+    }
+    fn from_fixed<T: FixedRaw>(raw: T, frac_bits: u32) -> Self {
+        raw.to_f64() / (1u128 << frac_bits) as f64
+    }
+}
+
+impl Fixed for f32 {
+    fn to_fixed<T: FixedRaw>(&self, frac_bits: u32, saturate: bool) -> Result<T> {
+        (*self as f64).to_fixed(frac_bits, saturate)
+    }
+    fn from_fixed<T: FixedRaw>(raw: T, frac_bits: u32) -> Self {
+        f64::from_fixed(raw, frac_bits) as f32
+    }
+}
+
+/// LEB128 encoding, which takes up as many bytes as the value needs
+pub trait Varint: Sized {
+    fn var_len(&self) -> usize;
+    /// Returns how many bytes were written
+    fn encode_var(&self, buf: &mut [u8]) -> Result<usize>;
+    /// Returns the value along with how many bytes were read
+    fn decode_var(data: &[u8]) -> Result<(Self, usize)>;
+}
+
+fn uvarint_len(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+fn encode_uvarint(mut value: u64, buf: &mut [u8]) -> Result<usize> {
+    let len = uvarint_len(value);
+    if buf.len() < len {
+        return Err(PError::BufTooSmall);
+    }
+    for byte in buf[..len].iter_mut() {
+        *byte = (value as u8 & 0x7f) | 0x80;
+        value >>= 7;
+    }
+    buf[len - 1] &= 0x7f;
+    Ok(len)
+}
+
+// fails when the value takes more than `bits` bits
+fn decode_uvarint(data: &[u8], bits: u32) -> Result<(u64, usize)> {
+    let mut value = 0;
+    for (idx, byte) in data.iter().enumerate() {
+        let shift = 7 * idx as u32;
+        let part = (byte & 0x7f) as u64;
+        if shift >= bits || (bits - shift < 7 && part >> (bits - shift) != 0) {
+            return Err(PError::OutOfRange);
+        }
+        value |= part << shift;
+        if byte & 0x80 == 0 {
+            return Ok((value, idx + 1));
+        }
+    }
+    Err(PError::NotEnoughData)
+}
+
+impl Varint for u32 {
+    fn var_len(&self) -> usize {
+        uvarint_len(*self as u64)
+    }
+    fn encode_var(&self, buf: &mut [u8]) -> Result<usize> {
+        encode_uvarint(*self as u64, buf)
+    }
+    fn decode_var(data: &[u8]) -> Result<(Self, usize)> {
+        let (value, len) = decode_uvarint(data, 32)?;
+        Ok((value as u32, len))
+    }
+}
+
+impl Varint for u64 {
+    fn var_len(&self) -> usize {
+        uvarint_len(*self)
+    }
+    fn encode_var(&self, buf: &mut [u8]) -> Result<usize> {
+        encode_uvarint(*self, buf)
+    }
+    fn decode_var(data: &[u8]) -> Result<(Self, usize)> {
+        decode_uvarint(data, 64)
+    }
+}
+
+// zigzag maps 0, -1, 1, -2... to 0, 1, 2, 3...
+impl Varint for i64 {
+    fn var_len(&self) -> usize {
+        uvarint_len(((*self << 1) ^ (*self >> 63)) as u64)
+    }
+    fn encode_var(&self, buf: &mut [u8]) -> Result<usize> {
+        encode_uvarint(((*self << 1) ^ (*self >> 63)) as u64, buf)
+    }
+    fn decode_var(data: &[u8]) -> Result<(Self, usize)> {
+        let (value, len) = decode_uvarint(data, 64)?;
+        Ok(((value >> 1) as i64 ^ -((value & 1) as i64), len))
+    }
+}
+
+// any byte other than 0 decodes as true; generated code checks strict bools itself
+impl Primitive for bool {
+    fn encode_le(&self, buf: &mut [u8]) -> Result<()> {
+        (*self as u8).encode_le(buf)
+    }
+    fn encode_be(&self, buf: &mut [u8]) -> Result<()> {
+        (*self as u8).encode_be(buf)
+    }
+    fn decode_le(&mut self, data: &[u8]) -> Result<()> {
+        let mut byte = 0u8;
+        byte.decode_le(data)?;
+        *self = byte != 0;
+        Ok(())
+    }
+    fn decode_be(&mut self, data: &[u8]) -> Result<()> {
+        let mut byte = 0u8;
+        byte.decode_be(data)?;
+        *self = byte != 0;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::*;
@@ -208,4 +465,76 @@ mod test {
 
         // TODO: generate per primitive tests above instead
     }
+
+    #[test]
+    fn narrow() {
+        let mut buf: [u8; 3] = [0; 3];
+        0x123456u32.encode_narrow_le(3, &mut buf).unwrap();
+        assert_eq!(buf, [0x56, 0x34, 0x12], "u24 encode_narrow_le");
+        0x123456u32.encode_narrow_be(3, &mut buf).unwrap();
+        assert_eq!(buf, [0x12, 0x34, 0x56], "u24 encode_narrow_be");
+        let mut x: u32 = 0;
+        x.decode_narrow_be(3, &buf).unwrap();
+        assert_eq!(x, 0x123456, "u24 decode_narrow_be");
+        assert!(
+            0x1000000u32.encode_narrow_le(3, &mut buf).is_err(),
+            "u24 range"
+        );
+
+        (-2i32).encode_narrow_le(3, &mut buf).unwrap();
+        assert_eq!(buf, [0xfe, 0xff, 0xff], "i24 encode_narrow_le");
+        let mut y: i32 = 0;
+        y.decode_narrow_le(3, &buf).unwrap();
+        assert_eq!(y, -2, "i24 sign extension");
+        assert!(
+            (1i32 << 23).encode_narrow_le(3, &mut buf).is_err(),
+            "i24 range"
+        );
+        assert!(
+            (-1i32 << 23).encode_narrow_le(3, &mut buf).is_ok(),
+            "i24 min"
+        );
+    }
+
+    #[test]
+    fn fixed() {
+        assert_eq!(1.5f64.to_fixed::<i32>(16, false).unwrap(), 0x18000);
+        assert_eq!((-1.5f64).to_fixed::<i32>(16, false).unwrap(), -0x18000);
+        assert_eq!(f64::from_fixed(-0x18000i32, 16), -1.5);
+        assert_eq!(f32::from_fixed(0x0180u16, 8), 1.5);
+        assert_eq!(0.01f32.to_fixed::<u16>(8, false).unwrap(), 3, "rounding");
+        assert!(256.0f32.to_fixed::<u16>(8, false).is_err(), "uq8.8 range");
+        assert_eq!(256.0f32.to_fixed::<u16>(8, true).unwrap(), u16::MAX);
+        assert_eq!((-1.0f32).to_fixed::<u16>(8, true).unwrap(), 0);
+        assert!(f64::NAN.to_fixed::<i16>(8, false).is_err(), "NaN");
+        assert!((-128.0f64).to_fixed::<i16>(8, false).is_ok(), "q8.8 min");
+    }
+
+    #[test]
+    fn varints() {
+        let mut buf: [u8; 10] = [0; 10];
+        assert_eq!(300u32.encode_var(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[0xac, 0x02], "u32 encode_var");
+        assert_eq!(u32::decode_var(&buf).unwrap(), (300, 2), "u32 decode_var");
+        assert_eq!(u64::MAX.var_len(), 10);
+        u64::MAX.encode_var(&mut buf).unwrap();
+        assert_eq!(u64::decode_var(&buf).unwrap(), (u64::MAX, 10));
+        assert!(u32::decode_var(&buf).is_err(), "u32 overflow");
+        assert!(u32::decode_var(&[0x80]).is_err(), "unterminated varint");
+        for value in [0i64, -1, 1, -64, 64, i64::MIN, i64::MAX] {
+            let len = value.encode_var(&mut buf).unwrap();
+            assert_eq!(len, value.var_len());
+            assert_eq!(i64::decode_var(&buf).unwrap(), (value, len), "i64 zigzag");
+        }
+    }
+
+    #[test]
+    fn bools() {
+        let mut buf: [u8; 1] = [0; 1];
+        true.encode_le(&mut buf).unwrap();
+        assert_eq!(buf, [1], "bool encode_le");
+        let mut y = false;
+        y.decode_le(&[2]).unwrap();
+        assert!(y, "nonzero bool decode_le");
+    }
 }